@@ -0,0 +1,124 @@
+use crate::config::{Config, LayeredConfig};
+use crate::output::Output;
+use crate::report::Report;
+use crate::{configure_project, RubyMineInterpreter, RunOptions};
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Lets whoever started a run stop it between steps. Clones share the same flag,
+/// so one can be kept while the other goes into the options.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Fails once the run was cancelled, so steps can bail with `?`
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            anyhow::bail!("Cancelled");
+        }
+        Ok(())
+    }
+}
+
+/// What `configure_all` configures and how, with the CLI's defaults for anything
+/// not set. Built for tools like `dev up` that configure the IDE as one of their steps.
+#[derive(Clone)]
+pub struct ConfigureOptions {
+    args: RunOptions,
+    project_dir: PathBuf,
+    config: Option<Config>,
+    cancellation: CancellationToken,
+    out: Output,
+}
+
+impl ConfigureOptions {
+    pub fn new(project_dir: impl Into<PathBuf>) -> Self {
+        Self::from_run_options(RunOptions::default(), project_dir)
+    }
+
+    /// Options for a run with the CLI's `args`
+    pub fn from_run_options(args: RunOptions, project_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            args,
+            project_dir: project_dir.into(),
+            config: None,
+            cancellation: CancellationToken::default(),
+            out: Output::new(),
+        }
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.args.dry_run = dry_run;
+        self
+    }
+
+    /// The ruby to use instead of the one the project environment resolves
+    pub fn ruby(mut self, ruby: impl Into<PathBuf>) -> Self {
+        self.args.ruby = Some(ruby.into());
+        self
+    }
+
+    /// A named profile from the layered config files
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        self.args.profile = Some(profile.into());
+        self
+    }
+
+    /// Uses `config` as the effective configuration instead of loading the config files
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Where the run's messages go [default: kept in an `Output` nobody reads]
+    pub fn output(mut self, out: Output) -> Self {
+        self.out = out;
+        self
+    }
+}
+
+/// Does what running the CLI without a command does: the interpreter, the test
+/// templates, the debugger, RBS support and declared scripts, and the data sources,
+/// then returns the report of what was written
+pub fn configure_all(options: &ConfigureOptions) -> Result<Report> {
+    let mut interpreter = new_interpreter(options)?;
+    configure_project(&mut interpreter, &options.args, &options.cancellation)?;
+    Report::collect(&interpreter, &options.args, None)
+}
+
+/// The project's interpreter, detected without configuring anything, for its name,
+/// config paths and XML (`RubyMineInterpreter::jdk_table_xml`, `render`)
+pub fn interpreter(options: &ConfigureOptions) -> Result<RubyMineInterpreter> {
+    new_interpreter(options)
+}
+
+fn new_interpreter(options: &ConfigureOptions) -> Result<RubyMineInterpreter> {
+    let args = &options.args;
+    let config = match &options.config {
+        Some(config) => config.clone(),
+        None => LayeredConfig::load(
+            &args.config_flags(),
+            &options.project_dir,
+            args.profile.as_deref(),
+            &options.out,
+        )?
+        .effective()?,
+    };
+    options.cancellation.check()?;
+    RubyMineInterpreter::new(args, &config, &options.project_dir, &options.out)
+}
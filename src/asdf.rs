@@ -0,0 +1,49 @@
+use anyhow::{Context, Result};
+use dirs::home_dir;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// asdf's data directory (`$ASDF_DATA_DIR`, defaulting to ~/.asdf)
+fn data_dir() -> Option<PathBuf> {
+    env::var_os("ASDF_DATA_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".asdf")))
+}
+
+/// Whether `path` is one of asdf's shims, which only dispatch to the real executable
+pub fn is_shim(path: &Path) -> bool {
+    data_dir().is_some_and(|dir| path.starts_with(dir.join("shims")))
+}
+
+/// The real executable behind an asdf shim for `dir`, as reported by `asdf which`
+pub fn which(program: &str, dir: &Path) -> Result<Option<String>> {
+    let output = Command::new("asdf")
+        .args(["which", program])
+        .current_dir(dir)
+        .output()
+        .with_context(|| format!("Failed to execute 'asdf which {}'", program))?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((!path.is_empty()).then_some(path))
+}
+
+/// Where asdf installs rubies, one directory per version
+pub fn rubies_dir() -> Option<PathBuf> {
+    Some(data_dir()?.join("installs").join("ruby"))
+}
+
+/// The install directory containing `ruby_path`, e.g. ~/.asdf/installs/ruby/3.3.0
+pub fn install_dir(ruby_path: &Path) -> Option<PathBuf> {
+    let installs_dir = rubies_dir()?;
+    let version = ruby_path
+        .strip_prefix(&installs_dir)
+        .ok()?
+        .components()
+        .next()?;
+    Some(installs_dir.join(version))
+}
@@ -0,0 +1,133 @@
+use crate::output::Output;
+use crate::state;
+use crate::warnings;
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// A file mutation the tool performed, one JSON object per line of the audit log
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    /// RFC 3339 timestamp
+    pub at: String,
+    /// "create", "write", "remove", "backup", "restore" or "copy"
+    pub action: String,
+    pub file: PathBuf,
+    /// The file copied over `file`, for backups, restores and copies
+    pub source: Option<PathBuf>,
+    /// Hash of the content before, `None` when the file didn't exist
+    pub before: Option<String>,
+    /// Hash of the content after, `None` when the file was removed
+    pub after: Option<String>,
+    /// The backup of `file` made before it was written
+    pub backup: Option<PathBuf>,
+}
+
+impl Entry {
+    pub fn new(action: &str, file: &Path, before: Option<String>, after: Option<String>) -> Self {
+        Self {
+            at: Local::now().to_rfc3339(),
+            action: action.to_string(),
+            file: file.to_path_buf(),
+            source: None,
+            before,
+            after,
+            backup: None,
+        }
+    }
+}
+
+/// `<data dir>/audit.jsonl`
+pub fn path() -> Result<PathBuf> {
+    Ok(state::data_dir()?.join("audit.jsonl"))
+}
+
+/// Hash of `path`'s content, or `None` when it can't be read
+pub fn hash_file(path: &Path) -> Option<String> {
+    fs::read(path).ok().map(|content| hash(&content))
+}
+
+/// 64-bit FNV-1a of `content`, in hex: enough to tell two versions of a file apart,
+/// not to stand up to someone forging one
+pub fn hash(content: &[u8]) -> String {
+    let hash = content.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Appends `entry` to the log. A log that can't be written is warned about instead
+/// of failing the change it records, which has already happened.
+pub fn record(entry: &Entry, out: &Output) {
+    if let Err(err) = append(entry) {
+        warnings::warn(out, format!("Failed to update the audit log: {:#}", err));
+    }
+}
+
+fn append(entry: &Entry) -> Result<()> {
+    let path = path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    }
+    let mut log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(log, "{}", serde_json::to_string(entry)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Every recorded entry, oldest first. Lines that don't parse (e.g. cut short by a
+/// crash) are skipped.
+pub fn entries() -> Result<Vec<Entry>> {
+    let path = path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Writes the recorded changes to `out`, only those to files whose path is or ends with
+/// `file` when given
+pub fn history(file: Option<&Path>, out: &Output) -> Result<()> {
+    let entries: Vec<Entry> = entries()?
+        .into_iter()
+        .filter(|entry| file.is_none_or(|file| entry.file.ends_with(file)))
+        .collect();
+    if entries.is_empty() {
+        match file {
+            Some(file) => out.line(format!("No changes to {} recorded", file.display())),
+            None => out.line(format!("No changes recorded in {}", path()?.display())),
+        }
+        return Ok(());
+    }
+    let or_dash = |hash: &Option<String>| hash.clone().unwrap_or_else(|| "-".to_string());
+    for entry in entries {
+        let mut line = format!(
+            "{}  {:<7}  {}  {} -> {}",
+            entry.at,
+            entry.action,
+            entry.file.display(),
+            or_dash(&entry.before),
+            or_dash(&entry.after)
+        );
+        if let Some(source) = &entry.source {
+            line += &format!("  (from {})", source.display());
+        }
+        if let Some(backup) = &entry.backup {
+            line += &format!("  (backup: {})", backup.display());
+        }
+        out.line(line);
+    }
+    Ok(())
+}
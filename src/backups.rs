@@ -0,0 +1,377 @@
+use crate::audit;
+use crate::fs_backend::FsBackend;
+use crate::output::Output;
+use crate::state::{self, State, StoredBackup};
+use crate::warnings;
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// `jdk.table.backup.20240101_120000_250.xml` -> `20240101_120000_250`. Older
+/// releases named backups to the second, which sort before these.
+pub fn timestamp(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let (_, rest) = name.split_once(".backup.")?;
+    Some(rest.strip_suffix(".xml")?.to_string())
+}
+
+/// Backups of `file` made by `FsBackend::backup`, newest first, including the ones
+/// moved into the backup store
+pub fn of(file: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (file.parent(), file.file_stem()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.backup.", stem.to_string_lossy());
+    let stored = State::load()
+        .map(|state| state.backups)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|stored| stored.file == file)
+        .map(|stored| stored.backup);
+    let mut backups: Vec<(String, PathBuf)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+        })
+        .chain(stored.filter(|backup| backup.exists()))
+        .filter_map(|path| Some((timestamp(&path)?, path)))
+        .collect();
+    backups.sort();
+    backups.into_iter().rev().map(|(_, path)| path).collect()
+}
+
+/// Where `migrate-backups` gathers backups, one directory per backed up file
+pub fn store_dir() -> Result<PathBuf> {
+    Ok(state::data_dir()?.join("backups"))
+}
+
+/// The backup store's per-file directories
+fn store_subdirs() -> Result<Vec<PathBuf>> {
+    let Ok(entries) = fs::read_dir(store_dir()?) else {
+        return Ok(Vec::new());
+    };
+    Ok(entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect())
+}
+
+/// Drops `removed` backups from the ones the state file records as moved into the store
+fn forget(fs_backend: &FsBackend, removed: &[PathBuf]) -> Result<()> {
+    let mut state = State::load()?;
+    let before = state.backups.len();
+    state
+        .backups
+        .retain(|stored| !removed.contains(&stored.backup));
+    if state.backups.len() != before {
+        state.save(fs_backend)?;
+    }
+    Ok(())
+}
+
+/// The file `backup`, a backup next to it, is a copy of. Backups are named after
+/// the file's stem, so for files other than `.xml` ones this goes by the files around.
+fn original_of(backup: &Path) -> Option<PathBuf> {
+    let name = backup.file_name()?.to_str()?;
+    let (stem, _) = name.split_once(".backup.")?;
+    let xml = backup.with_file_name(format!("{}.xml", stem));
+    if xml.exists() {
+        return Some(xml);
+    }
+    let sibling = fs::read_dir(backup.parent()?)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            timestamp(path).is_none() && path.file_stem().is_some_and(|file_stem| file_stem == stem)
+        });
+    Some(sibling.unwrap_or(xml))
+}
+
+/// Moves the backups in `dirs` into the backup store, records them in the state
+/// file so `--repair restore` still finds them, and removes the originals
+pub fn migrate(
+    fs_backend: &FsBackend,
+    dirs: &[PathBuf],
+    dry_run: bool,
+    out: &Output,
+) -> Result<()> {
+    let store = store_dir()?;
+    let mut state = State::load()?;
+    let mut moved = 0;
+    for dir in dirs.iter().collect::<BTreeSet<_>>() {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        let mut found: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| timestamp(path).is_some())
+            .collect();
+        found.sort();
+        for backup in found {
+            let (Some(file), Some(name)) = (original_of(&backup), backup.file_name()) else {
+                continue;
+            };
+            let file_dir = audit::hash(file.to_string_lossy().as_bytes());
+            let destination = store.join(file_dir).join(name);
+            if dry_run {
+                out.line(format!(
+                    "# Moving {} to {}",
+                    backup.display(),
+                    destination.display()
+                ));
+                moved += 1;
+                continue;
+            }
+            if destination.exists() {
+                if audit::hash_file(&destination) != audit::hash_file(&backup) {
+                    warnings::warn(out, format!(
+                        "{} is already in the backup store with other content; leaving it where it is",
+                        backup.display()
+                    ));
+                    continue;
+                }
+            } else {
+                if let Some(parent) = destination.parent() {
+                    fs_backend.create_dir_all(parent)?;
+                }
+                fs_backend.copy(&backup, &destination)?;
+            }
+            fs_backend.remove_file(&backup)?;
+            if !state
+                .backups
+                .iter()
+                .any(|stored| stored.backup == destination)
+            {
+                state.backups.push(StoredBackup {
+                    file,
+                    backup: destination,
+                });
+            }
+            moved += 1;
+        }
+    }
+
+    if moved == 0 {
+        out.line("No backups to move");
+        return Ok(());
+    }
+    if dry_run {
+        return Ok(());
+    }
+    state.save(fs_backend)?;
+    out.line(format!(
+        "Moved {} backup(s) into {}",
+        moved,
+        store.display()
+    ));
+    Ok(())
+}
+
+/// Parses sizes like `500M`, `1.5GB` or `200MiB` (units are powers of 1024, as in `du -h`)
+pub fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid size {:?}", size))?;
+    let exponent = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 1,
+        "M" | "MB" | "MIB" => 2,
+        "G" | "GB" | "GIB" => 3,
+        _ => anyhow::bail!("Invalid size {:?}: unknown unit {:?}", size, unit),
+    };
+    Ok((number * 1024f64.powi(exponent)) as u64)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+/// Removes the oldest backups in `dirs` and in the backup store until they fit in
+/// `budget` bytes, except the ones `fs_backend` made, and says what it removed
+pub fn enforce(
+    fs_backend: &FsBackend,
+    mut dirs: Vec<PathBuf>,
+    budget: u64,
+    out: &Output,
+) -> Result<()> {
+    dirs.extend(store_subdirs()?);
+    let pruned = enforce_budget(fs_backend, &dirs, budget, &fs_backend.backups())?;
+    if !pruned.removed.is_empty() {
+        forget(fs_backend, &pruned.removed)?;
+        out.line(format!(
+            "Removed {} old backup(s), reclaiming {}",
+            pruned.removed.len(),
+            format_size(pruned.reclaimed)
+        ));
+    }
+    if pruned.remaining > budget {
+        warnings::warn(
+            out,
+            format!(
+                "this run's backups alone take up {}, over the {} backup budget",
+                format_size(pruned.remaining),
+                format_size(budget)
+            ),
+        );
+    }
+    Ok(())
+}
+
+/// What enforcing the budget removed, and what's left
+pub struct Pruned {
+    pub removed: Vec<PathBuf>,
+    pub reclaimed: u64,
+    pub remaining: u64,
+}
+
+/// Removes the oldest backups in `dirs` until they fit in `budget` bytes together.
+/// Backups in `keep` (the ones this run made) are never removed, so the total can
+/// stay over budget when they alone exceed it.
+pub fn enforce_budget(
+    fs_backend: &FsBackend,
+    dirs: &[PathBuf],
+    budget: u64,
+    keep: &[PathBuf],
+) -> Result<Pruned> {
+    let dirs: BTreeSet<&PathBuf> = dirs.iter().collect();
+    let mut backups = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(timestamp) = timestamp(&path) else {
+                continue;
+            };
+            let size = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+            backups.push((timestamp, path, size));
+        }
+    }
+    backups.sort();
+
+    let mut remaining: u64 = backups.iter().map(|(_, _, size)| size).sum();
+    let mut pruned = Pruned {
+        removed: Vec::new(),
+        reclaimed: 0,
+        remaining,
+    };
+    for (_, path, size) in backups {
+        if remaining <= budget {
+            break;
+        }
+        if keep.contains(&path) {
+            continue;
+        }
+        fs_backend.remove_file(&path)?;
+        remaining -= size;
+        pruned.reclaimed += size;
+        pruned.removed.push(path);
+    }
+    pruned.remaining = remaining;
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_dir;
+
+    fn backup(dir: &Path, timestamp: &str, size: usize) -> PathBuf {
+        let path = dir.join(format!("jdk.table.backup.{}.xml", timestamp));
+        fs::write(&path, "x".repeat(size)).unwrap();
+        path
+    }
+
+    #[test]
+    fn parses_sizes_in_powers_of_1024() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("2K").unwrap(), 2048);
+        assert_eq!(parse_size("500M").unwrap(), 500 * 1024 * 1024);
+        assert_eq!(parse_size("1.5GB").unwrap(), 3 * 512 * 1024 * 1024);
+        assert_eq!(parse_size(" 200 mib ").unwrap(), 200 * 1024 * 1024);
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("10TB").is_err());
+    }
+
+    #[test]
+    fn formats_sizes_in_the_largest_unit_under_1024() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(200 * 1024 * 1024), "200.0 MB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024 * 1024), "5120.0 GB");
+    }
+
+    #[test]
+    fn enforce_budget_removes_the_oldest_first() {
+        let dir = scratch_dir("oldest_first");
+        let other = scratch_dir("oldest_first_other");
+        let oldest = backup(&dir, "20240101_000000", 10);
+        let older = backup(&other, "20240102_000000_000", 10);
+        let newest = backup(&dir, "20240103_000000_000", 10);
+        let unrelated = dir.join("jdk.table.xml");
+        fs::write(&unrelated, "x".repeat(100)).unwrap();
+
+        let fs_backend = FsBackend::new(false, &Output::new());
+        let dirs = [dir.clone(), other, dir];
+        let pruned = enforce_budget(&fs_backend, &dirs, 15, &[]).unwrap();
+        assert_eq!(pruned.removed, [oldest.clone(), older.clone()]);
+        assert_eq!(pruned.reclaimed, 20);
+        assert_eq!(pruned.remaining, 10);
+        assert!(!oldest.exists() && !older.exists());
+        assert!(newest.exists() && unrelated.exists());
+    }
+
+    #[test]
+    fn enforce_budget_keeps_the_backups_it_is_told_to() {
+        let dir = scratch_dir("keep");
+        let oldest = backup(&dir, "20240101_000000_000", 10);
+        let older = backup(&dir, "20240102_000000_000", 10);
+        let newest = backup(&dir, "20240103_000000_000", 10);
+
+        let fs_backend = FsBackend::new(false, &Output::new());
+        let keep = [oldest.clone(), newest.clone()];
+        let pruned = enforce_budget(&fs_backend, &[dir], 0, &keep).unwrap();
+        assert_eq!(pruned.removed, [older]);
+        assert_eq!(pruned.remaining, 20);
+        assert!(oldest.exists() && newest.exists());
+    }
+
+    #[test]
+    fn enforce_budget_leaves_backups_within_budget() {
+        let dir = scratch_dir("within");
+        let only = backup(&dir, "20240101_000000_000", 10);
+
+        let fs_backend = FsBackend::new(false, &Output::new());
+        let pruned = enforce_budget(&fs_backend, &[dir.join("missing"), dir], 10, &[]).unwrap();
+        assert!(pruned.removed.is_empty());
+        assert_eq!(pruned.remaining, 10);
+        assert!(only.exists());
+    }
+}
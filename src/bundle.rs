@@ -0,0 +1,19 @@
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+
+/// Whether the project depends on gem `name`: from Gemfile.lock when there is one
+/// (it also lists what the Gemfile's gemspecs and groups pull in), else the Gemfile
+pub fn has_gem(project_dir: &Path, name: &str) -> bool {
+    let escaped = regex::escape(name);
+    if let Ok(locked) = fs::read_to_string(project_dir.join("Gemfile.lock")) {
+        // Top-level specs are indented by exactly four spaces: "    debug (1.9.1)"
+        let spec = Regex::new(&format!(r"(?m)^ {{4}}{} \(", escaped)).unwrap();
+        return spec.is_match(&locked);
+    }
+    let Ok(gemfile) = fs::read_to_string(project_dir.join("Gemfile")) else {
+        return false;
+    };
+    let declaration = Regex::new(&format!(r#"(?m)^\s*gem\s*\(?\s*["']{}["']"#, escaped)).unwrap();
+    declaration.is_match(&gemfile)
+}
@@ -0,0 +1,614 @@
+use clap::{Parser, Subcommand};
+use rubymine_configurator::{
+    report, Channel, CompletionKind, DatasourceEnv, Driver, EnvManager, EnvSource, GemsBinDir, Ide,
+    RenderArtifact, Repair, RunOptions, SdkPosition, SettingsSync, Shell,
+};
+use std::env;
+use std::path::PathBuf;
+
+/// The command line, which `main` turns into the library's `RunOptions`
+#[derive(Parser)]
+#[command(name = "rubymine-configurator")]
+#[command(about = "Creates a Ruby interpreter configuration for RubyMine that uses shadowenv")]
+pub struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Output configuration to stdout instead of writing to RubyMine config"
+    )]
+    dry_run: bool,
+
+    #[arg(
+        long = "datasource-subproject",
+        value_name = "DIR",
+        help = "Subproject opened as a project of its own that shares this project's data sources, generated into DIR/.idea from this project's (repeatable)"
+    )]
+    datasource_subprojects: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Read MySQL host, port and user from the [client] section of ~/.my.cnf when MYSQL_* variables aren't set"
+    )]
+    my_cnf: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "SOURCES",
+        help = "Order in which sources of MYSQL_* settings win, highest first [default: process,project,dotenv,my-cnf]"
+    )]
+    env_precedence: Vec<EnvSource>,
+
+    #[arg(
+        long,
+        value_name = "VAR",
+        help = "Show which source a setting such as MYSQL_HOST comes from, and what it overrides"
+    )]
+    explain_env: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Fail on any attempt to modify files on disk (for audits and inspection)"
+    )]
+    read_only: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "Ruby interpreter to use instead of the one found on PATH"
+    )]
+    ruby: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with_all = ["ruby", "docker_image", "compose_service"],
+        help = "Use the installed ruby matching Gemfile.lock's RUBY VERSION (or the Gemfile's `ruby file:`) instead of the one on PATH"
+    )]
+    ruby_from_lockfile: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Keep every tool this one runs (mise, homebrew, bundler) from using the network"
+    )]
+    offline: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "Detect as if started with the KEY=VALUE variables in PATH (e.g. a teammate's or CI's exported environment) on top of this shell's"
+    )]
+    env_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Keep the ruby path as found instead of resolving symlinks"
+    )]
+    no_resolve_symlinks: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Use the current directory as the project instead of walking up to its root"
+    )]
+    no_root_detection: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Treat warnings as errors: fail with exit status 3 (undoing what the run wrote) on any warning, check that data sources are reachable, and fail when the detected ruby doesn't match .ruby-version or the Gemfile"
+    )]
+    strict: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Write the interpreter even when its homePath doesn't run as a ruby"
+    )]
+    skip_validation: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Configure the OS's ruby (e.g. /usr/bin/ruby) when that's what detection finds"
+    )]
+    allow_system_ruby: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "HOW",
+        help = "Recover from a jdk.table.xml that doesn't parse: restore the newest backup that does, or set it aside and start fresh"
+    )]
+    repair: Option<Repair>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Only write to the project's .idea, referring to the interpreter by name, and print what to add to the global IDE config"
+    )]
+    project_only: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Update this worktree's existing interpreter under its current name instead of replacing it with a newly named one"
+    )]
+    update_in_place: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Write through a .idea symlink even when it leads outside the project"
+    )]
+    follow_idea_symlink: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "TAG",
+        help = "Record what this run creates under TAG, so `prune --tag TAG` can remove it later"
+    )]
+    tag: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        requires = "tag",
+        help = "Append the --tag to the interpreter name"
+    )]
+    tag_in_name: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        help = "Apply a [profiles.NAME] section from the config files"
+    )]
+    profile: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        help = "IDE to configure (IntelliJ IDEA requires the Ruby plugin) [default: rubymine]"
+    )]
+    ide: Option<Ide>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "VERSION",
+        help = "Use the config directory of a specific IDE version (e.g. 2024.1)"
+    )]
+    ide_version: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        help = "Release channel to use when both stable and EAP installs exist [default: any]"
+    )]
+    channel: Option<Channel>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "IDE install location, when it can't be discovered automatically"
+    )]
+    rubymine_app: Option<PathBuf>,
+
+    #[arg(
+        long,
+        conflicts_with = "ide_version",
+        help = "Apply the configuration to every installed IDE version"
+    )]
+    all_ide_versions: bool,
+
+    #[arg(
+        long,
+        help = "Configure every worktree of the current git repository (from `git worktree list`), skipping ones that already have an interpreter"
+    )]
+    from_git_worktrees: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Show a desktop notification with the outcome when the run finishes"
+    )]
+    notify: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Which gem executable directory wins when several exist [default: gem]"
+    )]
+    gems_bin_dir: Option<GemsBinDir>,
+
+    #[arg(
+        long,
+        help = "Point the SDK at a generated shim script instead of a custom-configurator list"
+    )]
+    shim: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "How to handle jdk.table.xml when Settings Sync is enabled [default: warn]"
+    )]
+    settings_sync: Option<SettingsSync>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        help = "Where the interpreter goes among the other SDKs in jdk.table.xml [default: bottom]"
+    )]
+    sdk_position: Option<SdkPosition>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "Tool RubyMine runs ruby through to load the project environment [default: direnv for projects with only an .envrc, else shadowenv]"
+    )]
+    env_manager: Option<EnvManager>,
+
+    #[arg(
+        long,
+        value_name = "COMMAND",
+        conflicts_with = "env_manager",
+        help = "Command RubyMine runs ruby through, with {dir} standing for the project dir (e.g. \"mytool env exec --root {dir} --\"); replaces --env-manager"
+    )]
+    configurator_cmd: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "shadowenv executable to use instead of searching homebrew, PATH and the usual install locations"
+    )]
+    shadowenv_path: Option<PathBuf>,
+
+    #[arg(
+        long = "shadowenv-arg",
+        global = true,
+        value_name = "ARG",
+        allow_hyphen_values = true,
+        help = "Extra argument for `shadowenv exec` in the interpreter, placed before --dir, e.g. --silent (repeatable)"
+    )]
+    shadowenv_args: Vec<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Run `shadowenv trust` for the project when shadowenv doesn't trust it yet"
+    )]
+    trust: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Write the configuration even when shadowenv can't be found, assuming it will be on RubyMine's PATH"
+    )]
+    allow_missing_shadowenv: bool,
+
+    #[arg(
+        long,
+        help = "Warn when the ruby lacks the gems RubyMine needs for debugging and tests"
+    )]
+    check_gems: bool,
+
+    #[arg(
+        long,
+        help = "Start the IDE's integrated terminal through the configurator too, so its shell has the project environment"
+    )]
+    terminal: bool,
+
+    #[arg(
+        long,
+        help = "Write .idea/rubymine-configurator-report.md summarizing what was configured"
+    )]
+    report: bool,
+
+    #[arg(
+        long,
+        value_name = "FILE|-",
+        help = "Also send the report to FILE (JSON if it ends in .json, markdown otherwise) or, with -, a summary to stdout. Sinks are written even when the run fails; repeatable"
+    )]
+    report_to: Vec<report::Sink>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PORT",
+        help = "Port the debugger attach configuration connects to [default: 12345 for debug, 1234 for ruby-debug-ide]"
+    )]
+    debug_port: Option<u16>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "IMAGE",
+        conflicts_with = "ruby",
+        help = "Write a remote SDK running the ruby of this Docker image instead of a local one"
+    )]
+    docker_image: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "DIR",
+        help = "Where the project is mounted in the container or synced on the SSH host [default: the compose service's mount of the project, else /app; over SSH the same path relative to the home directory]"
+    )]
+    docker_project_dir: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        conflicts_with_all = ["ruby", "docker_image"],
+        help = "Write a remote SDK running the ruby of this docker-compose service instead of a local one"
+    )]
+    compose_service: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        requires = "compose_service",
+        help = "Compose file defining the service [default: compose.yaml or docker-compose.yml in the project]"
+    )]
+    compose_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with_all = ["ruby", "docker_image", "compose_service", "ruby_from_lockfile"],
+        help = "Write a remote SDK for the container defined in .devcontainer/devcontainer.json"
+    )]
+    devcontainer: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "[USER@]HOST[:PORT]",
+        conflicts_with_all = ["ruby", "docker_image", "compose_service", "ruby_from_lockfile", "devcontainer"],
+        help = "Write a remote SDK running the ruby of a cloud dev environment (e.g. Spin) reached over SSH"
+    )]
+    ssh_host: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "SIZE",
+        help = "Remove the oldest backups once they take up more than SIZE together (e.g. 200MB)"
+    )]
+    max_backups_size: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Also write the variables the project environment sets into run configuration templates, for configuration types that don't run through the configurator"
+    )]
+    env_snapshot: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Write RUBY, GEM_HOME, GEM_PATH and PATH from the project environment to .idea/rubymine-configurator.env, and have test run configurations (through the EnvFile plugin) and File Watchers use it"
+    )]
+    shared_env: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        help = "JDBC driver for data sources [default: mysql.8]"
+    )]
+    driver: Option<Driver>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "VERSION",
+        help = "Driver version to record for data sources, e.g. 8.0.33"
+    )]
+    driver_version: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Introspect the schemas the MySQL server has (with the mysql client) instead of a fixed list"
+    )]
+    discover_schemas: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PREFIX",
+        help = "Only discover schemas whose name starts with PREFIX"
+    )]
+    schema_prefix: Option<String>,
+}
+
+impl From<Args> for RunOptions {
+    fn from(args: Args) -> Self {
+        Self {
+            command: args.command.map(Into::into),
+            invocation: Some(env::args().skip(1).collect()),
+            dry_run: args.dry_run,
+            datasource_subprojects: args.datasource_subprojects,
+            my_cnf: args.my_cnf,
+            env_precedence: args.env_precedence,
+            explain_env: args.explain_env,
+            read_only: args.read_only,
+            ruby: args.ruby,
+            ruby_from_lockfile: args.ruby_from_lockfile,
+            offline: args.offline,
+            env_file: args.env_file,
+            no_resolve_symlinks: args.no_resolve_symlinks,
+            no_root_detection: args.no_root_detection,
+            strict: args.strict,
+            skip_validation: args.skip_validation,
+            allow_system_ruby: args.allow_system_ruby,
+            repair: args.repair,
+            project_only: args.project_only,
+            update_in_place: args.update_in_place,
+            follow_idea_symlink: args.follow_idea_symlink,
+            tag: args.tag,
+            tag_in_name: args.tag_in_name,
+            profile: args.profile,
+            ide: args.ide,
+            ide_version: args.ide_version,
+            channel: args.channel,
+            rubymine_app: args.rubymine_app,
+            all_ide_versions: args.all_ide_versions,
+            from_git_worktrees: args.from_git_worktrees,
+            notify: args.notify,
+            gems_bin_dir: args.gems_bin_dir,
+            shim: args.shim,
+            settings_sync: args.settings_sync,
+            sdk_position: args.sdk_position,
+            env_manager: args.env_manager,
+            configurator_cmd: args.configurator_cmd,
+            shadowenv_path: args.shadowenv_path,
+            shadowenv_args: args.shadowenv_args,
+            trust: args.trust,
+            allow_missing_shadowenv: args.allow_missing_shadowenv,
+            check_gems: args.check_gems,
+            terminal: args.terminal,
+            report: args.report,
+            report_to: args.report_to,
+            debug_port: args.debug_port,
+            docker_image: args.docker_image,
+            docker_project_dir: args.docker_project_dir,
+            compose_service: args.compose_service,
+            compose_file: args.compose_file,
+            devcontainer: args.devcontainer,
+            ssh_host: args.ssh_host,
+            max_backups_size: args.max_backups_size,
+            env_snapshot: args.env_snapshot,
+            shared_env: args.shared_env,
+            driver: args.driver,
+            driver_version: args.driver_version,
+            discover_schemas: args.discover_schemas,
+            schema_prefix: args.schema_prefix,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Check the health of the generated configuration without changing anything
+    Doctor {
+        #[arg(
+            long,
+            help = "Point run configurations using an SDK that no longer exists at the current one"
+        )]
+        fix: bool,
+    },
+    /// Print the effective configuration and where each value comes from
+    PrintConfig,
+    /// Replace interpreters and data sources created by older versions of this tool
+    MigrateArtifacts,
+    /// Move the backups next to jdk.table.xml and in .idea into the tool's backup store
+    MigrateBackups,
+    /// Remove every interpreter, data source and run configuration created by runs with --tag
+    Prune,
+    /// Print the changes the tool made to files, oldest first, from its audit log
+    History {
+        #[arg(
+            long,
+            help = "Only changes to this file (a path, or its end such as jdk.table.xml)"
+        )]
+        file: Option<PathBuf>,
+    },
+    /// Check the jdk.table.xml this run would write the way the IDE reads it, without writing it
+    SimulateOpen,
+    /// Run the written interpreter the way RubyMine will (`ruby -v`, `gem env`)
+    Verify,
+    /// Print a single rendered XML fragment instead of writing any file
+    Render {
+        #[arg(value_enum)]
+        artifact: RenderArtifact,
+    },
+    /// Print the variables the project environment sets as an `<envs>` block to paste into a run configuration
+    Env {
+        #[arg(long, help = "Print a JSON object instead of XML")]
+        json: bool,
+        #[arg(
+            long,
+            help = "Print the whole environment, not just what the project environment sets or changes"
+        )]
+        all: bool,
+    },
+    /// Only create the interpreter
+    Interpreter {
+        #[arg(
+            long,
+            help = "Read the interpreters to create from stdin, one JSON object per line: {\"dir\": ..., \"ruby\": ...}"
+        )]
+        stdin: bool,
+    },
+    /// Only configure the project's MySQL data sources
+    Datasource {
+        #[arg(
+            long = "env",
+            value_enum,
+            help = "database.yml environment to add as a data source (repeatable) [default: development and test]"
+        )]
+        envs: Vec<DatasourceEnv>,
+    },
+    /// Only add the attach configuration for the debugger in the Gemfile
+    Debugger,
+    /// Only add the run configurations for the scripts declared in config
+    Scripts,
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Candidates for the completion scripts, one per line
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[arg(value_enum)]
+        kind: CompletionKind,
+        #[arg(default_value = "")]
+        prefix: String,
+    },
+}
+
+impl From<Commands> for rubymine_configurator::Commands {
+    fn from(command: Commands) -> Self {
+        match command {
+            Commands::Doctor { fix } => Self::Doctor { fix },
+            Commands::PrintConfig => Self::PrintConfig,
+            Commands::MigrateArtifacts => Self::MigrateArtifacts,
+            Commands::MigrateBackups => Self::MigrateBackups,
+            Commands::Prune => Self::Prune,
+            Commands::History { file } => Self::History { file },
+            Commands::SimulateOpen => Self::SimulateOpen,
+            Commands::Verify => Self::Verify,
+            Commands::Render { artifact } => Self::Render { artifact },
+            Commands::Env { json, all } => Self::Env { json, all },
+            Commands::Interpreter { stdin } => Self::Interpreter { stdin },
+            Commands::Datasource { envs } => Self::Datasource { envs },
+            Commands::Debugger => Self::Debugger,
+            Commands::Scripts => Self::Scripts,
+            Commands::Completions { shell } => Self::Completions { shell },
+            Commands::Complete { kind, prefix } => Self::Complete { kind, prefix },
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::backups;
+use crate::config::Config;
+use crate::idea_dir;
+use crate::jdk::JdkEntry;
+use crate::output::Output;
+use crate::state::State;
+use crate::RubyMineInterpreter;
+use roxmltree::Document;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// What the completion script is asking `__complete` for
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum CompletionKind {
+    /// Interpreter names from every jdk.table.xml of the IDE
+    Interpreters,
+    /// Timestamps of the backups next to jdk.table.xml and the project's .idea files, and in the backup store
+    Backups,
+    /// Tags recorded by runs with --tag
+    Tags,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Shell {
+    Bash,
+    Zsh,
+}
+
+/// Writes the candidates for `kind` starting with `prefix` to `out`, one per line. Runs on
+/// every tab press, so it only reads files and never reports errors.
+pub fn complete(
+    config: &Config,
+    project_dir: &Path,
+    kind: CompletionKind,
+    prefix: &str,
+    out: &Output,
+) {
+    let candidates = match kind {
+        CompletionKind::Interpreters => interpreter_names(config),
+        CompletionKind::Backups => backup_timestamps(config, project_dir),
+        CompletionKind::Tags => State::load()
+            .map(|state| {
+                state
+                    .artifacts
+                    .into_iter()
+                    .filter_map(|artifact| artifact.tag)
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+    for candidate in candidates
+        .iter()
+        .filter(|candidate| candidate.starts_with(prefix))
+    {
+        out.line(candidate);
+    }
+}
+
+/// The completion script for `shell`, which calls back into the binary through
+/// `__complete` for values that depend on the machine
+pub fn script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => BASH,
+        Shell::Zsh => ZSH,
+    }
+}
+
+fn interpreter_names(config: &Config) -> BTreeSet<String> {
+    let config_dirs = RubyMineInterpreter::discover_config_dirs(
+        config.ide.unwrap_or_default(),
+        config.channel.unwrap_or_default(),
+    )
+    .unwrap_or_default();
+
+    config_dirs
+        .iter()
+        .filter_map(|dir| fs::read_to_string(dir.join("options").join("jdk.table.xml")).ok())
+        .flat_map(|content| {
+            let Ok(doc) = Document::parse(&content) else {
+                return Vec::new();
+            };
+            doc.descendants()
+                .filter_map(|node| JdkEntry::parse(&node))
+                .map(|entry| entry.name)
+                .collect()
+        })
+        .collect()
+}
+
+fn backup_timestamps(config: &Config, project_dir: &Path) -> BTreeSet<String> {
+    let mut dirs: Vec<_> = RubyMineInterpreter::discover_config_dirs(
+        config.ide.unwrap_or_default(),
+        config.channel.unwrap_or_default(),
+    )
+    .unwrap_or_default()
+    .into_iter()
+    .map(|dir| dir.join("options"))
+    .collect();
+    dirs.push(idea_dir::path(project_dir));
+
+    let stored = State::load()
+        .map(|state| state.backups)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|stored| stored.backup);
+    dirs.iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .chain(stored)
+        .filter_map(|path| backups::timestamp(&path))
+        .collect()
+}
+
+const BASH: &str = r#"_rubymine_configurator() {
+    local cur prev
+    cur="${COMP_WORDS[COMP_CWORD]}"
+    prev="${COMP_WORDS[COMP_CWORD-1]}"
+    case "$prev" in
+        --tag)
+            COMPREPLY=($(compgen -W "$(rubymine-configurator __complete tags "$cur" 2>/dev/null)" -- "$cur"))
+            return ;;
+        --ruby|--rubymine-app|--datasource-subproject)
+            COMPREPLY=($(compgen -f -- "$cur"))
+            return ;;
+    esac
+    COMPREPLY=($(compgen -W "doctor print-config migrate-artifacts migrate-backups prune history render simulate-open verify env interpreter datasource debugger scripts completions" -- "$cur"))
+}
+complete -F _rubymine_configurator rubymine-configurator
+"#;
+
+const ZSH: &str = r#"#compdef rubymine-configurator
+
+_rubymine_configurator() {
+    local -a tags
+    if [[ "${words[CURRENT-1]}" == --tag ]]; then
+        tags=(${(f)"$(rubymine-configurator __complete tags "${words[CURRENT]}" 2>/dev/null)"})
+        compadd -a tags
+        return
+    fi
+    if [[ "${words[CURRENT-1]}" == (--ruby|--rubymine-app|--datasource-subproject) ]]; then
+        _files
+        return
+    fi
+    compadd doctor print-config migrate-artifacts migrate-backups prune history render simulate-open verify env interpreter datasource debugger scripts completions
+}
+
+compdef _rubymine_configurator rubymine-configurator
+"#;
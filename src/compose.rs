@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+use serde_yaml::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File names `docker compose` looks for, in its order of preference
+const FILE_NAMES: &[&str] = &[
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+
+pub fn find_file(project_dir: &Path) -> Option<PathBuf> {
+    FILE_NAMES
+        .iter()
+        .map(|name| project_dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Where `service` mounts the project: the target of a bind mount of the project dir,
+/// else the service's working_dir. Fails when the file has no such service.
+pub fn project_mount(file: &Path, service: &str, project_dir: &Path) -> Result<Option<String>> {
+    let content =
+        fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let root: Value = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", file.display()))?;
+    let definition = root
+        .get("services")
+        .and_then(|services| services.get(service))
+        .with_context(|| format!("No service {} in {}", service, file.display()))?;
+
+    // Relative sources are relative to the compose file
+    let base = file.parent().unwrap_or(Path::new("."));
+    let is_project = |source: &str| {
+        let source = base.join(source);
+        fs::canonicalize(&source).ok() == fs::canonicalize(project_dir).ok()
+    };
+
+    let volumes = definition
+        .get("volumes")
+        .and_then(Value::as_sequence)
+        .cloned()
+        .unwrap_or_default();
+    for volume in &volumes {
+        // Short syntax "./:/app:cached", or long syntax with source/target
+        let (source, target) = match volume {
+            Value::String(spec) => {
+                let mut parts = spec.splitn(3, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(source), Some(target)) => (source.to_string(), target.to_string()),
+                    _ => continue,
+                }
+            }
+            Value::Mapping(_) => {
+                let field = |name: &str| volume.get(name).and_then(Value::as_str);
+                match (field("source"), field("target")) {
+                    (Some(source), Some(target)) => (source.to_string(), target.to_string()),
+                    _ => continue,
+                }
+            }
+            _ => continue,
+        };
+        if is_project(&source) {
+            return Ok(Some(target));
+        }
+    }
+
+    Ok(definition
+        .get("working_dir")
+        .and_then(Value::as_str)
+        .map(|dir| dir.to_string()))
+}
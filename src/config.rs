@@ -0,0 +1,352 @@
+use crate::env_sources::EnvSource;
+use crate::jdbc::{Driver, DriverSetting};
+use crate::jdk_table::AdditionalValue;
+use crate::output::Output;
+use crate::scripts::Script;
+use crate::warnings;
+use crate::{Channel, EnvManager, GemsBinDir, Ide, SdkPosition, SettingsSync};
+use anyhow::{Context, Result};
+use dirs::home_dir;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Prefix of environment variables that set config keys, e.g. RUBYMINE_CONFIGURATOR_IDE_VERSION
+const ENV_PREFIX: &str = "RUBYMINE_CONFIGURATOR_";
+
+/// Name of the per-project config file, looked up in the project directory
+const PROJECT_FILE_NAME: &str = ".rubymine-configurator.toml";
+
+/// Settings that can come from flags, environment variables, the project's
+/// `.rubymine-configurator.toml`, a profile, or `~/.config/rubymine-configurator/config.toml`.
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// IDE to configure
+    pub ide: Option<Ide>,
+    /// Pins the IDE config directory, e.g. "2024.1" for RubyMine2024.1
+    pub ide_version: Option<String>,
+    /// Release channel to use when both stable and EAP installs exist
+    pub channel: Option<Channel>,
+    /// IDE install location, bypassing discovery
+    pub rubymine_app: Option<PathBuf>,
+    /// Which gem executable directory wins when several exist
+    pub gems_bin_dir: Option<GemsBinDir>,
+    /// Point the SDK at a generated shim script
+    pub shim: Option<bool>,
+    /// How to handle jdk.table.xml when Settings Sync is enabled
+    pub settings_sync: Option<SettingsSync>,
+    /// Where the interpreter goes among the other SDKs in jdk.table.xml
+    pub sdk_position: Option<SdkPosition>,
+    /// Tool RubyMine runs ruby through to load the project environment. Detected from
+    /// the project (.shadowenv.d or .envrc) when unset.
+    pub env_manager: Option<EnvManager>,
+    /// Keep spawned tools from using the network
+    pub offline: Option<bool>,
+    /// Sources of MYSQL_* settings, highest precedence first, e.g. ["dotenv", "process"]
+    pub env_precedence: Option<Vec<EnvSource>>,
+    /// `[user@]host[:port]` of a cloud dev environment whose ruby the SDK runs over SSH
+    pub ssh_host: Option<String>,
+    /// Total size backups may take up before the oldest are removed, e.g. "200MB"
+    pub max_backups_size: Option<String>,
+    /// Command RubyMine runs ruby through instead of the env manager's, with `{dir}`
+    /// standing for the project dir
+    pub configurator_cmd: Option<String>,
+    /// shadowenv executable, bypassing discovery
+    pub shadowenv_path: Option<PathBuf>,
+    /// Where discovery looks for shadowenv, e.g. ["/usr/local/bin/shadowenv", "PATH"],
+    /// with "PATH" standing for a PATH lookup. The newest executable one wins.
+    pub shadowenv_candidates: Option<Vec<String>>,
+    /// Extra `shadowenv exec` arguments for the interpreter, e.g. ["--silent"]
+    pub shadowenv_args: Option<Vec<String>>,
+    /// Write the project environment into run configuration templates, for types
+    /// that ignore the configurator
+    pub env_snapshot: Option<bool>,
+    /// Write .idea/rubymine-configurator.env with the project's RUBY, GEM_HOME, GEM_PATH
+    /// and PATH, for run configurations and File Watchers to share
+    pub shared_env: Option<bool>,
+    /// JDBC driver for data sources: "mysql.8", "mariadb" or "postgresql"
+    pub driver: Option<Driver>,
+    /// Driver version recorded for data sources, e.g. "8.0.33"
+    pub driver_version: Option<String>,
+    /// Per data source `driver` and `version`, by data source name, e.g.
+    /// `[datasource_drivers."development/primary"]`
+    pub datasource_drivers: Option<BTreeMap<String, DriverSetting>>,
+    /// Introspect the schemas a reachable MySQL server has instead of a fixed list
+    pub discover_schemas: Option<bool>,
+    /// Only discover schemas whose name starts with this, e.g. "shop_"
+    pub schema_prefix: Option<String>,
+    /// Subprojects of an umbrella repo, relative to the project dir, that are opened
+    /// as projects of their own and share its data sources, e.g. ["apps/admin"]
+    pub datasource_subprojects: Option<Vec<PathBuf>>,
+    /// Only write to the project's .idea, for machines whose global IDE config is
+    /// managed by other tooling
+    pub project_only: Option<bool>,
+    /// Rewrite this worktree's existing interpreter under its current name instead of
+    /// replacing it with a newly named one, so run configurations keep finding it
+    pub update_in_place: Option<bool>,
+    /// Glob patterns on the names of SDKs the tool must never touch, e.g. ["Ruby 2.7 (legacy)*"]
+    pub protected_sdk_names: Option<Vec<String>>,
+    /// Glob patterns on the homePaths of SDKs the tool must never touch, e.g.
+    /// ["~/.rubies/custom-*/bin/ruby"]
+    pub protected_sdk_homes: Option<Vec<String>>,
+    /// Run configurations for team workflows, one `[[scripts]]` table each with a
+    /// `name`, `command` and optionally `args`, `env`, `working_dir` and `kind`
+    pub scripts: Option<Vec<Script>>,
+    /// Extra settings on the SDK's `<additional>` element, for plugin flags the tool
+    /// doesn't know about: a string becomes an attribute, e.g. `FLAG = "true"`, and a
+    /// table a child element with those attributes
+    pub sdk_additional: Option<BTreeMap<String, AdditionalValue>>,
+}
+
+impl Config {
+    /// Values used when no layer sets a key
+    fn defaults() -> Self {
+        Self {
+            ide: Some(Ide::default()),
+            channel: Some(Channel::default()),
+            gems_bin_dir: Some(GemsBinDir::default()),
+            shim: Some(false),
+            settings_sync: Some(SettingsSync::default()),
+            sdk_position: Some(SdkPosition::default()),
+            offline: Some(false),
+            project_only: Some(false),
+            update_in_place: Some(false),
+            ..Self::default()
+        }
+    }
+
+    pub fn global_path() -> Option<PathBuf> {
+        home_dir().map(|home| {
+            home.join(".config")
+                .join("rubymine-configurator")
+                .join("config.toml")
+        })
+    }
+
+    pub fn project_path(project_dir: &Path) -> PathBuf {
+        project_dir.join(PROJECT_FILE_NAME)
+    }
+
+    /// The project config file in `project_dir` on its own, empty when there's none
+    pub fn project_file(project_dir: &Path) -> Result<Self> {
+        let path = Self::project_path(project_dir);
+        let Some(table) = LayeredConfig::read_table(&path)? else {
+            return Ok(Self::default());
+        };
+        // Profiles only apply to the project they're used in
+        let table = table
+            .into_iter()
+            .filter(|(key, _)| key != "profiles")
+            .collect();
+        Self::deserialize(toml::Value::Table(table))
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Every key a layer can set
+    fn keys() -> Vec<String> {
+        // Unlike TOML, JSON keeps the fields that are None
+        match serde_json::to_value(Self::default()) {
+            Ok(serde_json::Value::Object(fields)) => {
+                fields.into_iter().map(|(key, _)| key).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// `raw` from an environment variable as the value of `key`: a TOML value such as
+    /// `8080`, `true` or `["dotenv", "process"]`, or the text itself when that isn't one
+    /// `key` takes, e.g. an ide_version of 2024.1
+    fn env_value(key: &str, raw: &str) -> toml::Value {
+        let text = toml::Value::String(raw.to_string());
+        let Ok(mut parsed) = format!("value = {}", raw).parse::<toml::Table>() else {
+            return text;
+        };
+        let Some(value) = parsed.remove("value") else {
+            return text;
+        };
+        let takes = |value: &toml::Value| {
+            let table = toml::Table::from_iter([(key.to_string(), value.clone())]);
+            Self::deserialize(toml::Value::Table(table)).is_ok()
+        };
+        if !takes(&value) && takes(&text) {
+            return text;
+        }
+        value
+    }
+}
+
+/// Where a config value came from
+#[derive(Debug, Clone)]
+pub enum Source {
+    Flag,
+    Env(String),
+    Profile(String, PathBuf),
+    ProjectFile(PathBuf),
+    GlobalFile(PathBuf),
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Flag => write!(f, "command-line flag"),
+            Source::Env(var) => write!(f, "environment variable {}", var),
+            Source::Profile(name, path) => write!(f, "profile {} in {}", name, path.display()),
+            Source::ProjectFile(path) => write!(f, "project config {}", path.display()),
+            Source::GlobalFile(path) => write!(f, "global config {}", path.display()),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+struct Layer {
+    source: Source,
+    table: toml::Table,
+}
+
+/// Every config layer, highest precedence first
+pub struct LayeredConfig {
+    layers: Vec<Layer>,
+}
+
+impl LayeredConfig {
+    /// Loads all layers. `flags` holds only the values given on the command line.
+    /// Environment variables that don't name a config key are skipped with a warning
+    /// to `out`.
+    pub fn load(
+        flags: &Config,
+        project_dir: &Path,
+        profile: Option<&str>,
+        out: &Output,
+    ) -> Result<Self> {
+        let mut layers = vec![Layer {
+            source: Source::Flag,
+            table: toml::Table::try_from(flags)?,
+        }];
+
+        let keys = Config::keys();
+        for (var, value) in env::vars() {
+            let Some(key) = var.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let key = key.to_lowercase();
+            if !keys.contains(&key) {
+                warnings::warn(
+                    out,
+                    format!("ignoring {}, which doesn't set a config key", var),
+                );
+                continue;
+            }
+            let mut table = toml::Table::new();
+            table.insert(key.clone(), Config::env_value(&key, &value));
+            layers.push(Layer {
+                source: Source::Env(var.clone()),
+                table,
+            });
+        }
+
+        let mut files = vec![];
+        let project_path = Config::project_path(project_dir);
+        files.push((Source::ProjectFile(project_path.clone()), project_path));
+        if let Some(global_path) = Config::global_path() {
+            files.push((Source::GlobalFile(global_path.clone()), global_path));
+        }
+
+        // A profile applies on top of the files it is defined in
+        let mut profile_layers = Vec::new();
+        let mut file_layers = Vec::new();
+        for (source, path) in files {
+            let Some(mut table) = Self::read_table(&path)? else {
+                continue;
+            };
+            let profiles = table.remove("profiles");
+            if let (Some(name), Some(toml::Value::Table(profiles))) = (profile, profiles) {
+                if let Some(toml::Value::Table(profile_table)) = profiles.get(name) {
+                    profile_layers.push(Layer {
+                        source: Source::Profile(name.to_string(), path.clone()),
+                        table: profile_table.clone(),
+                    });
+                }
+            }
+            file_layers.push(Layer { source, table });
+        }
+        if let Some(name) = profile {
+            if profile_layers.is_empty() {
+                anyhow::bail!("Profile {} is not defined in any config file", name);
+            }
+        }
+        layers.extend(profile_layers);
+        layers.extend(file_layers);
+
+        layers.push(Layer {
+            source: Source::Default,
+            table: toml::Table::try_from(Config::defaults())?,
+        });
+
+        // Validate each layer on its own so errors point at the right source
+        for layer in &layers {
+            Config::deserialize(toml::Value::Table(layer.table.clone()))
+                .with_context(|| format!("Invalid configuration from {}", layer.source))?;
+        }
+
+        Ok(Self { layers })
+    }
+
+    fn read_table(path: &Path) -> Result<Option<toml::Table>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let table = content
+            .parse::<toml::Table>()
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+        Ok(Some(table))
+    }
+
+    /// Each key with the value and source of the layer that wins
+    pub fn resolved(&self) -> BTreeMap<String, (toml::Value, Source)> {
+        let mut resolved = BTreeMap::new();
+        for layer in &self.layers {
+            for (key, value) in &layer.table {
+                resolved
+                    .entry(key.clone())
+                    .or_insert_with(|| (value.clone(), layer.source.clone()));
+            }
+        }
+        resolved
+    }
+
+    /// The merged configuration
+    pub fn effective(&self) -> Result<Config> {
+        let table: toml::Table = self
+            .resolved()
+            .into_iter()
+            .map(|(key, (value, _))| (key, value))
+            .collect();
+        Ok(Config::deserialize(toml::Value::Table(table))?)
+    }
+
+    /// The merged configuration as TOML, annotating where each value came from
+    pub fn describe(&self) -> String {
+        self.resolved()
+            .into_iter()
+            .map(|(key, (value, source))| describe_setting(key, value, source))
+            .collect()
+    }
+}
+
+/// `key = value` as a line of TOML, annotated with `source`
+pub(crate) fn describe_setting(
+    key: String,
+    value: toml::Value,
+    source: impl fmt::Display,
+) -> String {
+    let table = toml::Table::from_iter([(key, value)]);
+    let line = toml::to_string(&table).unwrap_or_default();
+    format!("{:<40} # {}\n", line.trim_end(), source)
+}
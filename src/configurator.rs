@@ -0,0 +1,186 @@
+use crate::debugger::Debugger;
+use crate::fs_backend::Mark;
+use crate::{warnings, DatasourceEnv, RubyMineInterpreter};
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// One part of the IDE config the tool manages for a project. The CLI runs each
+/// through `run`, so every step gets the same dry-run and rollback behaviour.
+pub trait Configurator {
+    /// What the step configures, for messages, e.g. "interpreter"
+    fn name(&self) -> &'static str;
+
+    /// Makes the change, through the interpreter's `fs` so that it can be reverted
+    fn apply(&self, interpreter: &mut RubyMineInterpreter) -> Result<()>;
+
+    /// Prints what `apply` would change without writing anything. Steps print their
+    /// dry run themselves, so by default this is `apply` with --dry-run on.
+    fn plan(&self, interpreter: &mut RubyMineInterpreter) -> Result<()> {
+        let dry_run = std::mem::replace(&mut interpreter.dry_run, true);
+        let result = self.apply(interpreter);
+        interpreter.dry_run = dry_run;
+        result
+    }
+
+    /// Undoes what `apply` wrote since `mark`, returning the files something else
+    /// changed since, which are left as they are
+    fn revert(&self, interpreter: &RubyMineInterpreter, mark: Mark) -> Result<Vec<PathBuf>> {
+        interpreter.fs.roll_back_to(mark)
+    }
+}
+
+/// Plans `configurator` in a dry run and applies it otherwise. A step that fails is
+/// reverted before its error is returned, so it never leaves half its files written.
+pub fn run(configurator: &dyn Configurator, interpreter: &mut RubyMineInterpreter) -> Result<()> {
+    if interpreter.dry_run {
+        return configurator.plan(interpreter);
+    }
+    let mark = interpreter.fs.mark();
+    let Err(err) = configurator.apply(interpreter) else {
+        return Ok(());
+    };
+    match configurator.revert(interpreter, mark) {
+        Ok(left) if left.is_empty() => {}
+        Ok(left) => {
+            let files: Vec<String> = left.iter().map(|file| file.display().to_string()).collect();
+            warnings::warn(
+                &interpreter.out,
+                format!(
+                    "the {} step failed; these files changed again since it wrote them, so they were left as they are: {}",
+                    configurator.name(),
+                    files.join(", ")
+                ),
+            );
+        }
+        Err(revert_err) => warnings::warn(
+            &interpreter.out,
+            format!(
+                "failed to revert the {} step: {:#}",
+                configurator.name(),
+                revert_err
+            ),
+        ),
+    }
+    Err(err)
+}
+
+/// The interpreter in jdk.table.xml (or only in .idea with --project-only)
+pub struct Interpreter;
+
+impl Configurator for Interpreter {
+    fn name(&self) -> &'static str {
+        "interpreter"
+    }
+
+    fn apply(&self, interpreter: &mut RubyMineInterpreter) -> Result<()> {
+        interpreter.create_interpreter()
+    }
+}
+
+/// The Minitest run configuration template in .idea/workspace.xml
+pub struct Minitest;
+
+impl Configurator for Minitest {
+    fn name(&self) -> &'static str {
+        "Minitest"
+    }
+
+    fn apply(&self, interpreter: &mut RubyMineInterpreter) -> Result<()> {
+        interpreter.create_minitest_config()
+    }
+}
+
+/// The interpreter and Minitest template in every IDE version's config
+pub struct AllIdeVersions;
+
+impl Configurator for AllIdeVersions {
+    fn name(&self) -> &'static str {
+        "all IDE versions"
+    }
+
+    fn apply(&self, interpreter: &mut RubyMineInterpreter) -> Result<()> {
+        if interpreter.project_only {
+            anyhow::bail!("--all-ide-versions writes each IDE version's global config, which --project-only leaves alone");
+        }
+        crate::configure_all_ide_versions(interpreter)
+    }
+}
+
+/// .idea/rubymine-configurator.env
+pub struct SharedEnv;
+
+impl Configurator for SharedEnv {
+    fn name(&self) -> &'static str {
+        "shared env file"
+    }
+
+    fn apply(&self, interpreter: &mut RubyMineInterpreter) -> Result<()> {
+        interpreter.write_shared_env()
+    }
+}
+
+/// The attach configuration for the project's debugger
+pub struct DebuggerAttach(pub Debugger);
+
+impl Configurator for DebuggerAttach {
+    fn name(&self) -> &'static str {
+        "debugger"
+    }
+
+    fn apply(&self, interpreter: &mut RubyMineInterpreter) -> Result<()> {
+        interpreter.configure_debugger(self.0)
+    }
+}
+
+/// Run configurations for the scripts declared in config
+pub struct Scripts;
+
+impl Configurator for Scripts {
+    fn name(&self) -> &'static str {
+        "scripts"
+    }
+
+    fn apply(&self, interpreter: &mut RubyMineInterpreter) -> Result<()> {
+        interpreter.configure_scripts()
+    }
+}
+
+/// RBS support and the sig/ source root
+pub struct Rbs;
+
+impl Configurator for Rbs {
+    fn name(&self) -> &'static str {
+        "RBS"
+    }
+
+    fn apply(&self, interpreter: &mut RubyMineInterpreter) -> Result<()> {
+        interpreter.configure_rbs()
+    }
+}
+
+/// The terminal's shell command
+pub struct Terminal;
+
+impl Configurator for Terminal {
+    fn name(&self) -> &'static str {
+        "terminal"
+    }
+
+    fn apply(&self, interpreter: &mut RubyMineInterpreter) -> Result<()> {
+        interpreter.configure_terminal()
+    }
+}
+
+/// The MySQL data sources for these database.yml environments (the default ones
+/// when empty)
+pub struct Datasources(pub Vec<DatasourceEnv>);
+
+impl Configurator for Datasources {
+    fn name(&self) -> &'static str {
+        "data sources"
+    }
+
+    fn apply(&self, interpreter: &mut RubyMineInterpreter) -> Result<()> {
+        interpreter.configure_datasources(&self.0)
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::Result;
+use std::path::Path;
+
+/// Placeholder replaced with the project dir
+pub const DIR_PLACEHOLDER: &str = "{dir}";
+
+/// Splits a command line into words the way a POSIX shell would for quotes and
+/// backslashes (no expansions), e.g. `mytool env exec --root {dir} --`
+pub fn parse(command: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => anyhow::bail!("Unterminated ' in {:?}", command),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => anyhow::bail!("Unterminated \" in {:?}", command),
+                        },
+                        Some(c) => word.push(c),
+                        None => anyhow::bail!("Unterminated \" in {:?}", command),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.extend(chars.next());
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    if words.is_empty() {
+        anyhow::bail!("The configurator command is empty");
+    }
+    Ok(words)
+}
+
+/// The command with `{dir}` replaced by the project dir
+pub fn expand(words: &[String], project_dir: &str) -> Vec<String> {
+    words
+        .iter()
+        .map(|word| word.replace(DIR_PLACEHOLDER, project_dir))
+        .collect()
+}
+
+/// Short name for the interpreter name, from the program's file name
+pub fn name(words: &[String]) -> String {
+    words
+        .first()
+        .and_then(|program| Path::new(program).file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(command: &str) -> Vec<String> {
+        parse(command).unwrap()
+    }
+
+    #[test]
+    fn splits_on_unquoted_whitespace() {
+        assert_eq!(
+            words("  mytool env\texec --root {dir} --  "),
+            ["mytool", "env", "exec", "--root", "{dir}", "--"]
+        );
+    }
+
+    #[test]
+    fn single_quotes_keep_everything_literal() {
+        assert_eq!(
+            words(r#"run 'a b' 'it'\''s' '"$x"'s"#),
+            ["run", "a b", "it's", r#""$x"s"#]
+        );
+        assert_eq!(words("run ''"), ["run", ""]);
+    }
+
+    #[test]
+    fn double_quotes_only_unescape_what_the_shell_does() {
+        assert_eq!(
+            words(r#"run "a b" "say \"hi\"" "\$HOME \\ \n" "it's""#),
+            ["run", "a b", r#"say "hi""#, r"$HOME \ \n", "it's"]
+        );
+        assert_eq!(words(r#"run """#), ["run", ""]);
+    }
+
+    #[test]
+    fn backslashes_escape_the_next_character_outside_quotes() {
+        assert_eq!(words(r"run a\ b \'x\' c\\d"), ["run", "a b", "'x'", r"c\d"]);
+        assert_eq!(words(r"run trailing\"), ["run", "trailing"]);
+    }
+
+    #[test]
+    fn fails_on_unterminated_quotes_and_empty_commands() {
+        assert!(parse("run 'open").is_err());
+        assert!(parse(r#"run "open"#).is_err());
+        assert!(parse(r#"run "escaped\"#).is_err());
+        assert!(parse("").is_err());
+        assert!(parse("  \t ").is_err());
+    }
+
+    #[test]
+    fn expand_replaces_every_dir_placeholder() {
+        let expanded = expand(
+            &words("mytool --root {dir} --cache {dir}/tmp --"),
+            "/src/my app",
+        );
+        assert_eq!(
+            expanded,
+            [
+                "mytool",
+                "--root",
+                "/src/my app",
+                "--cache",
+                "/src/my app/tmp",
+                "--"
+            ]
+        );
+        assert_eq!(name(&expanded), "mytool");
+        assert_eq!(
+            name(&words("/opt/homebrew/bin/shadowenv exec --")),
+            "shadowenv"
+        );
+    }
+}
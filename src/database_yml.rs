@@ -0,0 +1,147 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde_yaml::{Mapping, Value};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// One database connection from config/database.yml
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    /// Rails environment, e.g. "development"
+    pub environment: String,
+    /// Name of the connection within a multi-database environment ("primary", "shard", ...)
+    pub name: Option<String>,
+    pub adapter: String,
+    pub host: Option<String>,
+    pub port: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub database: Option<String>,
+}
+
+impl DatabaseConfig {
+    /// Label used for the data source, e.g. "development" or "development/shard"
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{}/{}", self.environment, name),
+            None => self.environment.clone(),
+        }
+    }
+}
+
+/// Reads every connection from `path`, in file order. Returns an empty list if
+/// the file doesn't exist.
+pub fn load(path: &Path) -> Result<Vec<DatabaseConfig>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    parse(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+fn parse(content: &str) -> Result<Vec<DatabaseConfig>> {
+    // Control-flow ERB tags (`<% if ... %>`) on their own line aren't YAML; drop them
+    let erb_line = Regex::new(r"^\s*<%[^=].*%>\s*$")?;
+    let yaml: String = content
+        .lines()
+        .filter(|line| !erb_line.is_match(line))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut root: Value = serde_yaml::from_str(&yaml)?;
+    root.apply_merge()?;
+    let Value::Mapping(environments) = root else {
+        return Ok(Vec::new());
+    };
+
+    let mut configs = Vec::new();
+    for (environment, value) in &environments {
+        let (Some(environment), Value::Mapping(mapping)) = (environment.as_str(), value) else {
+            continue;
+        };
+        // Shared blocks like `default: &default` are only there to be merged into others
+        if environment == "default" {
+            continue;
+        }
+
+        if mapping.contains_key("adapter") || mapping.contains_key("database") {
+            configs.extend(connection(environment, None, mapping));
+            continue;
+        }
+
+        // Multi-database environments nest one mapping per connection
+        for (name, value) in mapping {
+            if let (Some(name), Value::Mapping(connection_mapping)) = (name.as_str(), value) {
+                configs.extend(connection(
+                    environment,
+                    Some(name.to_string()),
+                    connection_mapping,
+                ));
+            }
+        }
+    }
+    Ok(configs)
+}
+
+fn connection(
+    environment: &str,
+    name: Option<String>,
+    mapping: &Mapping,
+) -> Option<DatabaseConfig> {
+    let field = |key: &str| -> Option<String> {
+        let value = match mapping.get(key)? {
+            Value::String(value) => value.clone(),
+            Value::Number(value) => value.to_string(),
+            _ => return None,
+        };
+        resolve_erb(&value)
+    };
+
+    Some(DatabaseConfig {
+        environment: environment.to_string(),
+        name,
+        adapter: field("adapter")?,
+        host: field("host"),
+        port: field("port"),
+        username: field("username"),
+        password: field("password"),
+        database: field("database"),
+    })
+}
+
+/// Evaluates the common `<%= ENV["X"] %>` and `<%= ENV.fetch("X") { default } %>`
+/// forms. Anything more involved resolves to `None`, so callers fall back to their defaults.
+fn resolve_erb(value: &str) -> Option<String> {
+    if !value.contains("<%") {
+        return Some(value.to_string());
+    }
+
+    let env_re = Regex::new(
+        r#"<%=\s*ENV(?:\[\s*["'](\w+)["']\s*\]|\.fetch\(\s*["'](\w+)["']\s*(?:,\s*["']?([^"')]*)["']?\s*)?\)(?:\s*\{\s*["']?([^"'}]*)["']?\s*\})?)(?:\s*\|\|\s*["']?([^"'%]*?)["']?)?\s*%>"#,
+    )
+    .ok()?;
+
+    let mut failed = false;
+    let resolved = env_re.replace_all(value, |captures: &regex::Captures| {
+        let var = captures.get(1).or(captures.get(2)).map(|m| m.as_str());
+        let default = captures
+            .get(3)
+            .or(captures.get(4))
+            .or(captures.get(5))
+            .map(|m| m.as_str().trim().to_string());
+        match var.and_then(|var| env::var(var).ok()).or(default) {
+            Some(value) => value,
+            None => {
+                failed = true;
+                String::new()
+            }
+        }
+    });
+
+    if failed || resolved.contains("<%") {
+        return None;
+    }
+    Some(resolved.trim().to_string()).filter(|value| !value.is_empty())
+}
@@ -0,0 +1,175 @@
+use crate::{bundle, xml_misc};
+use anyhow::Result;
+use roxmltree::{Document, Node};
+use std::path::Path;
+use xmlwriter::{Options, XmlWriter};
+
+/// Port ruby-debug-ide hands the IDE back connections on
+const DEBASE_DISPATCHER_PORT: &str = "26162";
+
+/// The debugger backend the project bundles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Debugger {
+    /// ruby/debug (`rdbg`), the default since Ruby 3.1
+    Debug,
+    /// ruby-debug-ide with debase, for older projects
+    Debase,
+}
+
+impl Debugger {
+    /// The debugger the project bundles. `debug` wins when both are present since
+    /// it's the one RubyMine prefers.
+    pub fn detect(project_dir: &Path) -> Option<Self> {
+        let has_gem = |name| bundle::has_gem(project_dir, name);
+        if has_gem("debug") {
+            Some(Debugger::Debug)
+        } else if has_gem("ruby-debug-ide") || has_gem("debase") {
+            Some(Debugger::Debase)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Debugger::Debug => "debug",
+            Debugger::Debase => "ruby-debug-ide",
+        }
+    }
+
+    pub fn default_port(self) -> u16 {
+        match self {
+            Debugger::Debug => 12345,
+            Debugger::Debase => 1234,
+        }
+    }
+
+    /// How to start the app so the attach configuration can connect to it
+    pub fn start_command(self, port: u16) -> String {
+        match self {
+            Debugger::Debug => format!("rdbg --open --port {} -c -- bin/rails server", port),
+            Debugger::Debase => format!(
+                "rdebug-ide --port {} --dispatcher-port {} -- bin/rails server",
+                port, DEBASE_DISPATCHER_PORT
+            ),
+        }
+    }
+}
+
+/// The attach configuration, as the settings that go into workspace.xml
+pub struct AttachConfig<'a> {
+    pub name: String,
+    pub module: &'a str,
+    pub debugger: Debugger,
+    /// Where the debuggee runs: localhost unless it's on a remote dev environment
+    pub remote_host: &'a str,
+    pub port: u16,
+    /// Project dir as the debuggee sees it (differs from $PROJECT_DIR$ in a container)
+    pub remote_root: &'a str,
+}
+
+impl AttachConfig<'_> {
+    fn write(&self, writer: &mut XmlWriter) {
+        writer.start_element("configuration");
+        xml_misc::write_attribute(writer, "name", &self.name);
+        writer.write_attribute("type", "RemoteRubyDebugConfigurationType");
+        writer.write_attribute("factoryName", "Ruby remote debug");
+
+        writer.start_element("module");
+        xml_misc::write_attribute(writer, "name", self.module);
+        writer.end_element();
+
+        let mut settings = vec![
+            ("REMOTE_HOST", self.remote_host.to_string()),
+            ("REMOTE_PORT", self.port.to_string()),
+            ("REMOTE_ROOT", self.remote_root.to_string()),
+            ("LOCAL_ROOT", "$PROJECT_DIR$".to_string()),
+        ];
+        if self.debugger == Debugger::Debase {
+            settings.push(("LOCAL_PORT", DEBASE_DISPATCHER_PORT.to_string()));
+        }
+        for (name, value) in settings {
+            writer.start_element("RUBY_REMOTE_DEBUG");
+            writer.write_attribute("NAME", name);
+            xml_misc::write_attribute(writer, "VALUE", &value);
+            writer.end_element();
+        }
+
+        writer.start_element("method");
+        writer.write_attribute("v", "2");
+        writer.end_element();
+
+        writer.end_element(); // configuration
+    }
+
+    /// workspace.xml with the configuration added to the RunManager, replacing an
+    /// earlier one of the same name
+    pub fn apply(&self, workspace_xml: &str) -> Result<String> {
+        let doc = Document::parse(workspace_xml)?;
+        let root = doc.root_element();
+        let has_run_manager = root.children().any(|child| is_run_manager(&child));
+
+        let mut writer = XmlWriter::new(Options::default());
+        writer.write_declaration();
+        xml_misc::write_document(&mut writer, &doc, |writer, root| {
+            writer.start_element(root.tag_name().name());
+            xml_misc::copy_attributes(writer, root);
+            for child in root.children() {
+                if is_run_manager(&child) {
+                    self.write_run_manager(writer, Some(&child));
+                } else if child.is_element() {
+                    copy(writer, &child);
+                } else {
+                    xml_misc::write(writer, &child);
+                }
+            }
+            if !has_run_manager {
+                self.write_run_manager(writer, None);
+            }
+            writer.end_element();
+            Ok(())
+        })?;
+        Ok(xml_misc::end_document(writer))
+    }
+
+    fn write_run_manager(&self, writer: &mut XmlWriter, existing: Option<&Node>) {
+        writer.start_element("component");
+        writer.write_attribute("name", "RunManager");
+        if let Some(existing) = existing {
+            for attr in existing.attributes().filter(|attr| attr.name() != "name") {
+                xml_misc::copy_attribute(writer, existing, &attr);
+            }
+            for child in existing.children() {
+                let ours = child.has_tag_name("configuration")
+                    && child.attribute("name") == Some(self.name.as_str());
+                if ours {
+                    continue;
+                }
+                if child.is_element() {
+                    copy(writer, &child);
+                } else {
+                    xml_misc::write(writer, &child);
+                }
+            }
+        }
+        self.write(writer);
+        writer.end_element();
+    }
+}
+
+fn is_run_manager(node: &Node) -> bool {
+    node.has_tag_name("component") && node.attribute("name") == Some("RunManager")
+}
+
+fn copy(writer: &mut XmlWriter, node: &Node) {
+    writer.start_element(node.tag_name().name());
+    xml_misc::copy_attributes(writer, node);
+    for child in node.children() {
+        if child.is_element() {
+            copy(writer, &child);
+        } else {
+            xml_misc::write(writer, &child);
+        }
+    }
+    writer.end_element();
+}
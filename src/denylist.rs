@@ -0,0 +1,140 @@
+use crate::config::Config;
+use crate::jdk::JdkEntry;
+use anyhow::{Context, Result};
+use dirs::home_dir;
+use regex::Regex;
+
+/// SDKs the tool never replaces, removes or deduplicates away, whatever else a run
+/// decides: hand-crafted ones teammates rely on
+pub struct Denylist {
+    names: Vec<Regex>,
+    home_paths: Vec<Regex>,
+}
+
+impl Denylist {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Self::new(
+            config.protected_sdk_names.as_deref().unwrap_or_default(),
+            config.protected_sdk_homes.as_deref().unwrap_or_default(),
+        )
+    }
+
+    /// From glob patterns on SDK names, where `*` matches anything, and on homePaths,
+    /// where `*` stops at a `/`, `**` doesn't and a leading `~/` is the home directory
+    fn new(names: &[String], home_paths: &[String]) -> Result<Self> {
+        let home_paths = home_paths
+            .iter()
+            .map(|pattern| match (pattern.strip_prefix("~/"), home_dir()) {
+                (Some(rest), Some(home)) => home.join(rest).to_string_lossy().to_string(),
+                _ => pattern.clone(),
+            })
+            .map(|pattern| glob(&pattern, Some('/')))
+            .collect::<Result<_>>()
+            .context("Invalid protected_sdk_homes")?;
+        Ok(Self {
+            names: names
+                .iter()
+                .map(|pattern| glob(pattern, None))
+                .collect::<Result<_>>()
+                .context("Invalid protected_sdk_names")?,
+            home_paths,
+        })
+    }
+
+    pub fn protects(&self, entry: &JdkEntry) -> bool {
+        self.names.iter().any(|name| name.is_match(&entry.name))
+            || entry.home_path.as_deref().is_some_and(|home_path| {
+                self.home_paths
+                    .iter()
+                    .any(|pattern| pattern.is_match(home_path))
+            })
+    }
+}
+
+/// `pattern` as an anchored regex; `*` doesn't match `separator` when there's one
+fn glob(pattern: &str, separator: Option<char>) -> Result<Regex> {
+    let any = match separator {
+        Some(separator) => format!("[^{}]*", regex::escape(&separator.to_string())),
+        None => ".*".to_string(),
+    };
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str(&any),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).with_context(|| format!("Invalid pattern {:?}", pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    fn entry(name: &str, home_path: &str) -> JdkEntry {
+        JdkEntry {
+            name: name.to_string(),
+            version: None,
+            home_path: Some(home_path.to_string()),
+            configurator: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn star_crosses_separators_only_when_told_to() {
+        let names = glob("docker/*", None).unwrap();
+        assert!(names.is_match("docker/web/ruby"));
+
+        let single = glob("/opt/rubies/*/bin/ruby", Some('/')).unwrap();
+        assert!(single.is_match("/opt/rubies/3.3.0/bin/ruby"));
+        assert!(!single.is_match("/opt/rubies/3.3.0/x/bin/ruby"));
+        assert!(!single.is_match("/opt/rubies/3.3.0/bin/ruby.bak"));
+
+        let double = glob("/opt/**/ruby", Some('/')).unwrap();
+        assert!(double.is_match("/opt/rubies/3.3.0/bin/ruby"));
+        assert!(!double.is_match("/usr/opt/rubies/ruby"));
+
+        let one = glob("Ruby 3.?", None).unwrap();
+        assert!(one.is_match("Ruby 3.3"));
+        assert!(!one.is_match("Ruby 3.10"));
+    }
+
+    #[test]
+    fn name_patterns_take_regex_metacharacters_literally() {
+        let denylist = Denylist::new(&patterns(&["Ruby 2.7 (legacy)*"]), &[]).unwrap();
+        assert!(denylist.protects(&entry("Ruby 2.7 (legacy)", "/usr/bin/ruby")));
+        assert!(denylist.protects(&entry("Ruby 2.7 (legacy) + shadowenv", "/usr/bin/ruby")));
+        assert!(!denylist.protects(&entry("Ruby 2.7 legacy", "/usr/bin/ruby")));
+        assert!(!denylist.protects(&entry("Ruby 2x7 (legacy)", "/usr/bin/ruby")));
+        assert!(!denylist.protects(&entry("My Ruby 2.7 (legacy)", "/usr/bin/ruby")));
+    }
+
+    #[test]
+    fn home_patterns_expand_a_leading_tilde() {
+        let home = test_support::home();
+        let denylist =
+            Denylist::new(&[], &patterns(&["~/.rubies/*/bin/ruby", "/srv/~/ruby"])).unwrap();
+        let in_home = home.join(".rubies/3.3.0/bin/ruby");
+        assert!(denylist.protects(&entry("Ruby 3.3.0", &in_home.to_string_lossy())));
+        assert!(!denylist.protects(&entry("Ruby 3.3.0", "~/.rubies/3.3.0/bin/ruby")));
+        assert!(denylist.protects(&entry("Ruby 3.3.0", "/srv/~/ruby")));
+
+        let without_home = JdkEntry {
+            home_path: None,
+            ..entry("Ruby 3.3.0", "")
+        };
+        assert!(!denylist.protects(&without_home));
+    }
+}
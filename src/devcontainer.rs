@@ -0,0 +1,174 @@
+use crate::docker::Container;
+use crate::network;
+use crate::output::Output;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The parts of devcontainer.json that say where ruby runs
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct DevContainer {
+    image: Option<String>,
+    build: Option<Build>,
+    /// Older spelling of `build.dockerfile`
+    docker_file: Option<String>,
+    docker_compose_file: Option<ComposeFiles>,
+    service: Option<String>,
+    workspace_folder: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Build {
+    dockerfile: Option<String>,
+    context: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ComposeFiles {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// The devcontainer definition, where the dev containers spec looks for it
+pub fn find(project_dir: &Path) -> Option<PathBuf> {
+    [
+        project_dir.join(".devcontainer").join("devcontainer.json"),
+        project_dir.join(".devcontainer.json"),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+/// The container the devcontainer runs in and the folder the project is mounted at.
+/// A Dockerfile-based devcontainer is built (and tagged after the project) first,
+/// which --offline forbids.
+pub fn container(
+    file: &Path,
+    project_dir: &Path,
+    offline: bool,
+    out: &Output,
+) -> Result<(Container, String)> {
+    let content =
+        fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let definition: DevContainer = serde_json::from_str(&strip_jsonc(&content))
+        .with_context(|| format!("Failed to parse {}", file.display()))?;
+    // Paths in devcontainer.json are relative to the file itself
+    let base = file.parent().unwrap_or(project_dir);
+
+    let project_name = project_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let workspace_folder = definition
+        .workspace_folder
+        .clone()
+        .unwrap_or_else(|| format!("/workspaces/{}", project_name));
+
+    if let Some(compose_files) = definition.docker_compose_file {
+        let compose_file = match compose_files {
+            ComposeFiles::One(file) => file,
+            // The last file is the one overriding the others
+            ComposeFiles::Many(files) => files.into_iter().last().unwrap_or_default(),
+        };
+        let service = definition
+            .service
+            .context("devcontainer.json names a compose file but no service")?;
+        let container = Container::Compose {
+            file: base.join(compose_file),
+            service,
+        };
+        return Ok((container, workspace_folder));
+    }
+
+    if let Some(image) = definition.image {
+        return Ok((Container::Image(image), workspace_folder));
+    }
+
+    let build = definition.build.unwrap_or_default();
+    let dockerfile = build
+        .dockerfile
+        .or(definition.docker_file)
+        .context("devcontainer.json has no image, Dockerfile or compose file")?;
+    let context = base.join(build.context.as_deref().unwrap_or("."));
+    let tag = format!(
+        "rubymine-configurator/{}",
+        project_name.to_lowercase().replace(' ', "-")
+    );
+    network::ensure_online(offline, "building the devcontainer image")?;
+    out.status(format!("Building the devcontainer image {}", tag));
+    let status = network::docker()
+        .args(["build", "-q", "-t", &tag])
+        .args(network::docker_proxy_args("--build-arg"))
+        .arg("-f")
+        .arg(base.join(&dockerfile))
+        .arg(&context)
+        .status()
+        .context("Failed to run docker build")?;
+    if !status.success() {
+        anyhow::bail!("Building {} failed", dockerfile);
+    }
+    Ok((Container::Image(tag), workspace_folder))
+}
+
+/// devcontainer.json is JSON with comments and trailing commas; drops both
+fn strip_jsonc(content: &str) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let mut without_comments = Vec::with_capacity(chars.len());
+    let mut in_string = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if in_string {
+            without_comments.push(c);
+            if c == '\\' {
+                without_comments.extend(next);
+                i += 1;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        } else if c == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+            continue;
+        } else {
+            in_string = c == '"';
+            without_comments.push(c);
+        }
+        i += 1;
+    }
+
+    // A comma with only whitespace before the closing bracket
+    let mut stripped = String::with_capacity(without_comments.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &c) in without_comments.iter().enumerate() {
+        if in_string {
+            in_string = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = without_comments[i + 1..]
+                .iter()
+                .find(|c| !c.is_whitespace());
+            if matches!(next, Some('}') | Some(']')) {
+                continue;
+            }
+        }
+        stripped.push(c);
+    }
+    stripped
+}
@@ -0,0 +1,21 @@
+use std::path::{Path, PathBuf};
+
+/// The file direnv loads the project environment from
+pub const ENVRC: &str = ".envrc";
+
+/// Location of the direnv executable: PATH first, then where homebrew installs it
+pub fn find_path() -> Option<String> {
+    if let Ok(Some(path)) = crate::RubyMineInterpreter::which("direnv") {
+        return Some(path);
+    }
+
+    ["/opt/homebrew/bin/direnv", "/usr/local/bin/direnv"]
+        .into_iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+pub fn has_envrc(project_dir: &Path) -> bool {
+    project_dir.join(ENVRC).is_file()
+}
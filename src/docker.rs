@@ -0,0 +1,198 @@
+use crate::jdk_table::Element;
+use crate::network;
+use crate::ruby_engine::{self, RubyInfo};
+use crate::ssh::SshHost;
+use anyhow::{Context, Result};
+use std::fmt;
+use std::path::PathBuf;
+
+/// What the remote SDK starts ruby in
+pub enum Container {
+    Image(String),
+    /// A service of a docker-compose file, which also defines its volumes
+    Compose {
+        file: PathBuf,
+        service: String,
+    },
+    /// A cloud dev environment reached over SSH rather than a container
+    Ssh(SshHost),
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Container::Image(image) => write!(f, "{}", image),
+            Container::Compose { service, .. } => write!(f, "compose service {}", service),
+            Container::Ssh(host) => write!(f, "{}", host),
+        }
+    }
+}
+
+impl Container {
+    /// How the interpreter name says the ruby is reached
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Container::Image(_) | Container::Compose { .. } => "docker",
+            Container::Ssh(_) => "ssh",
+        }
+    }
+}
+
+/// A ruby inside a container, with the project mounted into it
+pub struct DockerRuby {
+    pub container: Container,
+    /// Where the project is mounted in the container
+    pub container_dir: String,
+    pub ruby_path: String,
+    pub gems_bin_dir: String,
+    pub ruby: RubyInfo,
+}
+
+impl DockerRuby {
+    /// Asks a throwaway container where its ruby lives and what it is. Fails with
+    /// --offline, since that can pull an image or reach an SSH host.
+    pub fn probe(
+        container: Container,
+        project_dir: &str,
+        container_dir: &str,
+        offline: bool,
+    ) -> Result<Self> {
+        network::ensure_online(offline, &format!("running ruby in {}", container))?;
+        let script = format!(
+            "puts RbConfig.ruby; {}; puts Gem.bindir; puts Dir.pwd",
+            ruby_engine::PROBE
+        );
+        let mut command = match &container {
+            Container::Image(image) => {
+                let mut command = network::docker();
+                command
+                    .args(["run", "--rm", "--entrypoint", "ruby"])
+                    .args(network::docker_proxy_args("-e"))
+                    .args(["-v", &format!("{}:{}", project_dir, container_dir)])
+                    .args(["-w", container_dir, image])
+                    .arg("-e")
+                    .arg(&script);
+                command
+            }
+            Container::Compose { file, service } => {
+                let mut command = network::docker();
+                command
+                    .args(["compose", "-f"])
+                    .arg(file)
+                    .args(["run", "--rm", "--no-deps", "-T", "--entrypoint", "ruby"])
+                    .args(network::docker_proxy_args("-e"))
+                    .arg(service)
+                    .arg("-e")
+                    .arg(&script);
+                command
+            }
+            Container::Ssh(host) => host.ruby_command(container_dir, &script),
+        };
+        let output = command
+            .output()
+            .with_context(|| format!("Failed to run {}", container.kind()))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Could not run ruby in {}: {}",
+                container,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let mut lines = stdout.lines();
+        let ruby_path = lines
+            .next()
+            .map(|line| line.trim().to_string())
+            .filter(|path| !path.is_empty())
+            .with_context(|| format!("Could not find ruby in {}", container))?;
+        let ruby = RubyInfo::parse(lines.by_ref())
+            .with_context(|| format!("Could not determine the Ruby version in {}", container))?;
+        let gems_bin_dir = lines
+            .next()
+            .map(|line| line.trim().to_string())
+            .unwrap_or_default();
+        // Over SSH the project dir may be given relative to the home directory
+        let container_dir = match &container {
+            Container::Ssh(_) => lines
+                .next()
+                .map(|line| line.trim().to_string())
+                .unwrap_or(container_dir.to_string()),
+            _ => container_dir.to_string(),
+        };
+
+        Ok(Self {
+            container,
+            container_dir,
+            ruby_path,
+            gems_bin_dir,
+            ruby,
+        })
+    }
+
+    /// homePath of the remote SDK, which RubyMine keys remote interpreters by
+    pub fn home_path(&self) -> String {
+        let ruby_path = self.ruby_path.trim_start_matches('/');
+        match &self.container {
+            Container::Image(image) => format!("docker://{}/{}", image, ruby_path),
+            Container::Compose { file, service } => format!(
+                "docker-compose://[{}]:{}/{}",
+                file.to_string_lossy(),
+                service,
+                ruby_path
+            ),
+            Container::Ssh(host) => format!(
+                "ssh://{}@{}:{}/{}",
+                host.user, host.hostname, host.port, ruby_path
+            ),
+        }
+    }
+
+    /// The remote SDK part of `<additional>`: the interpreter in the container, and the
+    /// project mounted (and mapped) at the container dir
+    pub fn remote_data(&self, project_dir: &str) -> Vec<Element> {
+        let path_mappings = Element::new("PATH_MAPPINGS").child(
+            Element::new("mapping")
+                .attribute("local-root", project_dir)
+                .attribute("remote-root", &self.container_dir),
+        );
+
+        let remote_sdk_data = Element::new("REMOTE_SDK_DATA")
+            .attribute("INTERPRETER_PATH", &self.ruby_path)
+            .attribute("RUN_AS_ROOT_VIA_SUDO", "false")
+            .attribute("INITIALIZED", "false")
+            .attribute("VALID", "true");
+        let remote_sdk_data = match &self.container {
+            Container::Image(image) => remote_sdk_data.child(
+                Element::new("DOCKER_DATA")
+                    .attribute("IMAGE_NAME", image)
+                    .child(
+                        Element::new("VOLUME_BINDINGS").child(
+                            Element::new("binding")
+                                .attribute("host-path", project_dir)
+                                .attribute("container-path", &self.container_dir),
+                        ),
+                    ),
+            ),
+            // The compose file already says what gets mounted where
+            Container::Compose { file, service } => remote_sdk_data.child(
+                Element::new("DOCKER_COMPOSE_DATA")
+                    .attribute("SERVICE_NAME", service)
+                    .child(
+                        Element::new("CONFIGURATION_FILES")
+                            .child(Element::new("file").attribute("path", file.to_string_lossy())),
+                    ),
+            ),
+            // Credentials stay with the SSH agent; RubyMine only needs to know where to connect
+            Container::Ssh(host) => remote_sdk_data
+                .attribute("HOST", &host.hostname)
+                .attribute("PORT", host.port.to_string())
+                .attribute("USERNAME", &host.user)
+                .attribute("ANONYMOUS", "false")
+                .attribute("USE_KEY_PAIR", "false")
+                .attribute("USE_AUTH_AGENT", "true"),
+        };
+
+        vec![path_mappings, remote_sdk_data]
+    }
+}
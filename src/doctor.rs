@@ -0,0 +1,250 @@
+use crate::output::Output;
+use crate::{gem_probe, ruby_version_check, run_configs, simulate_open, RubyMineInterpreter};
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+/// Reports problems with the generated configuration. Nothing is written unless `fix`
+/// is set, and then only run configurations pointing at a missing SDK are changed.
+pub fn run(interpreter: &RubyMineInterpreter, fix: bool) -> Result<()> {
+    let mut problems = 0;
+
+    problems += check_ruby_version(interpreter);
+    problems += check_gems(interpreter);
+    problems += check_sdk_table(interpreter)?;
+    problems += check_shims(interpreter)?;
+    problems += check_run_configs(interpreter, fix)?;
+
+    interpreter.out.line("");
+    if problems > 0 {
+        anyhow::bail!("doctor found {} problem(s)", problems);
+    }
+    interpreter.out.line("No problems found");
+    Ok(())
+}
+
+fn report(out: &Output, ok: bool, subject: &str, detail: &str) -> usize {
+    let status = if ok { "ok" } else { "problem" };
+    if detail.is_empty() {
+        out.line(format!("  [{}] {}", status, subject));
+    } else {
+        out.line(format!("  [{}] {}: {}", status, subject, detail));
+    }
+    usize::from(!ok)
+}
+
+fn check_ruby_version(interpreter: &RubyMineInterpreter) -> usize {
+    interpreter.out.line(format!(
+        "Ruby version ({}):",
+        interpreter.ruby.sdk_version()
+    ));
+    let requirements = ruby_version_check::requirements(Path::new(&interpreter.current_dir));
+    if requirements.is_empty() {
+        interpreter
+            .out
+            .line("  no .ruby-version or Gemfile ruby directive");
+        return 0;
+    }
+
+    requirements
+        .iter()
+        .map(|requirement| {
+            let ok = ruby_version_check::satisfied_by(&interpreter.ruby, &requirement.version);
+            report(
+                &interpreter.out,
+                ok,
+                requirement.source,
+                &requirement.version,
+            )
+        })
+        .sum()
+}
+
+fn check_gems(interpreter: &RubyMineInterpreter) -> usize {
+    interpreter.out.line("Gems:");
+    let Some(missing) = interpreter.missing_gems() else {
+        interpreter.out.line("  could not be checked");
+        return 0;
+    };
+
+    gem_probe::REQUIRED_GEMS
+        .iter()
+        .map(|gem| {
+            let subject = gem.alternatives.join(" or ");
+            if missing.iter().any(|missing| missing.feature == gem.feature) {
+                let install = gem_probe::install_command(gem, Path::new(&interpreter.current_dir));
+                report(
+                    &interpreter.out,
+                    false,
+                    &subject,
+                    &format!("missing, needed for {} ({})", gem.feature, install),
+                )
+            } else {
+                report(&interpreter.out, true, &subject, "")
+            }
+        })
+        .sum()
+}
+
+/// Every SDK, not just ours: a broken entry can keep the IDE from loading the others
+fn check_sdk_table(interpreter: &RubyMineInterpreter) -> Result<usize> {
+    let config_file = interpreter.interpreter_config_file()?;
+    interpreter
+        .out
+        .line(format!("SDK table ({}):", config_file.display()));
+    let Ok(content) = fs::read_to_string(&config_file) else {
+        interpreter.out.line("  not written yet");
+        return Ok(0);
+    };
+    let problems = simulate_open::validate(&content, true);
+    if problems.is_empty() {
+        return Ok(report(&interpreter.out, true, "opens cleanly", ""));
+    }
+    Ok(problems
+        .iter()
+        .map(|problem| report(&interpreter.out, false, &problem.to_string(), ""))
+        .sum())
+}
+
+fn check_shims(interpreter: &RubyMineInterpreter) -> Result<usize> {
+    let shims = RubyMineInterpreter::existing_shims()?;
+    interpreter.out.line(format!(
+        "Shims ({}):",
+        RubyMineInterpreter::shims_dir()?.display()
+    ));
+    if shims.is_empty() {
+        interpreter.out.line("  none");
+        return Ok(0);
+    }
+
+    let orphaned = interpreter.orphaned_shims()?;
+    let current_shim = interpreter.shim_path()?;
+    let mut problems = 0;
+
+    for shim in &shims {
+        let name = shim
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let content = fs::read_to_string(shim).unwrap_or_default();
+
+        if orphaned.contains(shim) {
+            problems += report(
+                &interpreter.out,
+                false,
+                &name,
+                "not used by any interpreter (rerun with --shim to remove it)",
+            );
+        } else if !is_executable(shim) {
+            problems += report(&interpreter.out, false, &name, "not executable");
+        } else if let Some(dir) = project_dir(&content).filter(|dir| !Path::new(dir).is_dir()) {
+            problems += report(
+                &interpreter.out,
+                false,
+                &name,
+                &format!("project dir {} no longer exists", dir),
+            );
+        } else if *shim == current_shim && content != interpreter.shim_content() {
+            problems += report(
+                &interpreter.out,
+                false,
+                &name,
+                "out of date with the current ruby or project dir (rerun with --shim)",
+            );
+        } else {
+            problems += report(&interpreter.out, true, &name, "");
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Run configurations must use an SDK that exists in jdk.table.xml, or RubyMine
+/// refuses to run them
+fn check_run_configs(interpreter: &RubyMineInterpreter, fix: bool) -> Result<usize> {
+    interpreter.out.line("Run configurations:");
+    let workspace_file = interpreter.idea_dir().join("workspace.xml");
+    let Ok(content) = fs::read_to_string(&workspace_file) else {
+        interpreter.out.line("  no .idea/workspace.xml");
+        return Ok(0);
+    };
+    let references = run_configs::sdk_references(&content)?;
+    if references.is_empty() {
+        interpreter.out.line("  none use a specific SDK");
+        return Ok(0);
+    }
+
+    let sdk_names: Vec<String> = interpreter
+        .interpreter_entries()?
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect();
+    let managed = interpreter.managed_interpreter()?;
+    let mut dangling = Vec::new();
+    let mut problems = 0;
+    for reference in &references {
+        if sdk_names.contains(&reference.sdk_name) {
+            problems += report(
+                &interpreter.out,
+                true,
+                &reference.configuration,
+                &reference.sdk_name,
+            );
+            continue;
+        }
+        if !dangling.contains(&reference.sdk_name) {
+            dangling.push(reference.sdk_name.clone());
+        }
+        let detail = match (&managed, fix) {
+            (Some(managed), true) => format!(
+                "SDK {} doesn't exist; now uses {}",
+                reference.sdk_name, managed.name
+            ),
+            (Some(_), false) => format!(
+                "SDK {} doesn't exist (rerun with --fix to use the current one)",
+                reference.sdk_name
+            ),
+            (None, _) => format!(
+                "SDK {} doesn't exist, and neither does this tool's (run it without a command first)",
+                reference.sdk_name
+            ),
+        };
+        let fixed = fix && managed.is_some();
+        problems += report(&interpreter.out, fixed, &reference.configuration, &detail);
+    }
+
+    if let (Some(managed), true, false) = (&managed, fix, dangling.is_empty()) {
+        if interpreter.dry_run {
+            interpreter
+                .out
+                .line(format!("# Would update {}", workspace_file.display()));
+        } else {
+            interpreter.update_config_file(&workspace_file, |xml| match xml {
+                Some(xml) => run_configs::repoint(xml, &dangling, &managed.name).map(Some),
+                None => Ok(None),
+            })?;
+        }
+    }
+    Ok(problems)
+}
+
+/// The project dir recorded in the shim's header comment
+fn project_dir(content: &str) -> Option<&str> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("# Generated by rubymine-configurator for "))
+        .map(|dir| dir.trim())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .map(|metadata| metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
@@ -0,0 +1,27 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Variables that differ between any two processes and say nothing about the project
+const VOLATILE: &[&str] = &["PWD", "OLDPWD", "SHLVL", "_"];
+
+/// Set by the shadowenv shell hook to track what it changed
+pub const SHADOWENV_DATA: &str = "__shadowenv_data";
+
+/// Variables the project environment adds or changes compared to `clean`, which is
+/// what run configurations that bypass the configurator would otherwise get
+pub fn delta(
+    project_env: &HashMap<String, String>,
+    clean: &HashMap<String, String>,
+) -> BTreeMap<String, String> {
+    project_env
+        .iter()
+        .filter(|(key, _)| !VOLATILE.contains(&key.as_str()) && key.as_str() != SHADOWENV_DATA)
+        .filter(|(key, value)| clean.get(*key) != Some(*value))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// Whether `clean` already has shadowenv's changes, as when this runs from a shell
+/// with the hook active in the project, which hides them from the delta
+pub fn already_activated(clean: &HashMap<String, String>) -> bool {
+    clean.contains_key(SHADOWENV_DATA)
+}
@@ -0,0 +1,344 @@
+use crate::my_cnf;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A place a setting like MYSQL_HOST can come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum EnvSource {
+    /// The environment this tool was started in
+    Process,
+    /// The project environment loaded through the env manager (e.g. shadowenv)
+    Project,
+    /// .env.local and .env in the project directory
+    Dotenv,
+    /// The [client] group of ~/.my.cnf, only consulted with --my-cnf
+    MyCnf,
+}
+
+/// Highest precedence first. The process env wins so a one-off `MYSQL_HOST=db ...` works.
+pub const DEFAULT_PRECEDENCE: [EnvSource; 4] = [
+    EnvSource::Process,
+    EnvSource::Project,
+    EnvSource::Dotenv,
+    EnvSource::MyCnf,
+];
+
+/// Dotenv files, the overriding one first
+const DOTENV_FILES: [&str; 2] = [".env.local", ".env"];
+
+impl fmt::Display for EnvSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EnvSource::Process => "process",
+            EnvSource::Project => "project",
+            EnvSource::Dotenv => "dotenv",
+            EnvSource::MyCnf => "my-cnf",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The ~/.my.cnf option standing in for an environment variable
+fn my_cnf_option(var: &str) -> Option<&'static str> {
+    match var {
+        "MYSQL_HOST" => Some("host"),
+        "MYSQL_PORT" => Some("port"),
+        "MYSQL_USER" => Some("user"),
+        "MYSQL_PASSWORD" => Some("password"),
+        _ => None,
+    }
+}
+
+/// What one source says about a variable
+pub struct Candidate {
+    pub source: EnvSource,
+    /// Why the source wasn't looked at, if it wasn't
+    pub skipped: Option<String>,
+    pub value: Option<String>,
+    /// File the value was read from
+    pub origin: Option<PathBuf>,
+}
+
+/// The sources in precedence order, each loaded once
+pub struct EnvChain {
+    sources: Vec<(EnvSource, Result<Values, String>)>,
+}
+
+struct Values {
+    vars: HashMap<String, (String, Option<PathBuf>)>,
+}
+
+impl EnvChain {
+    /// `project_env` is empty when the project environment wasn't captured (e.g. in a
+    /// container); `my_cnf` is whether --my-cnf was given
+    pub fn new(
+        precedence: &[EnvSource],
+        project_dir: &Path,
+        project_env: &HashMap<String, String>,
+        my_cnf: bool,
+    ) -> Self {
+        let mut sources = Vec::new();
+        for &source in precedence {
+            // A source listed twice only counts where it first appears
+            if sources.iter().any(|(seen, _)| *seen == source) {
+                continue;
+            }
+            let values = match source {
+                EnvSource::Process => Ok(Values::plain(env::vars().collect())),
+                EnvSource::Project if project_env.is_empty() => {
+                    Err("the project environment wasn't captured".to_string())
+                }
+                EnvSource::Project => Ok(Values::plain(project_env.clone())),
+                EnvSource::Dotenv => Ok(Values::dotenv(project_dir)),
+                EnvSource::MyCnf if !my_cnf => Err("--my-cnf wasn't given".to_string()),
+                EnvSource::MyCnf => Ok(Values::my_cnf()),
+            };
+            sources.push((source, values));
+        }
+        Self { sources }
+    }
+
+    /// The value of the highest-precedence source that sets `var`
+    pub fn get(&self, var: &str) -> Option<String> {
+        self.explain(var)
+            .into_iter()
+            .find_map(|candidate| candidate.value)
+    }
+
+    /// Every source in precedence order with what it says about `var`
+    pub fn explain(&self, var: &str) -> Vec<Candidate> {
+        self.sources
+            .iter()
+            .map(|(source, values)| match values {
+                Ok(values) => {
+                    let found = values.vars.get(var).cloned();
+                    Candidate {
+                        source: *source,
+                        skipped: None,
+                        value: found.as_ref().map(|(value, _)| value.clone()),
+                        origin: found.and_then(|(_, origin)| origin),
+                    }
+                }
+                Err(reason) => Candidate {
+                    source: *source,
+                    skipped: Some(reason.clone()),
+                    value: None,
+                    origin: None,
+                },
+            })
+            .collect()
+    }
+}
+
+impl Values {
+    fn plain(vars: HashMap<String, String>) -> Self {
+        Self {
+            vars: vars
+                .into_iter()
+                .map(|(key, value)| (key, (value, None)))
+                .collect(),
+        }
+    }
+
+    fn dotenv(project_dir: &Path) -> Self {
+        let mut vars = HashMap::new();
+        for name in DOTENV_FILES {
+            let path = project_dir.join(name);
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            for (key, value) in parse_dotenv(&content) {
+                vars.entry(key).or_insert((value, Some(path.clone())));
+            }
+        }
+        Self { vars }
+    }
+
+    fn my_cnf() -> Self {
+        let options = my_cnf::client_options();
+        let origin = my_cnf::path();
+        let vars = ["MYSQL_HOST", "MYSQL_PORT", "MYSQL_USER", "MYSQL_PASSWORD"]
+            .into_iter()
+            .filter_map(|var| {
+                let value = options.get(my_cnf_option(var)?)?;
+                Some((var.to_string(), (value.clone(), origin.clone())))
+            })
+            .collect();
+        Self { vars }
+    }
+}
+
+/// Sets the variables of the `KEY=value` file at `path` in this process, as if the
+/// tool had been started with them, so detection and every command it spawns see
+/// them. Returns how many were set.
+pub fn apply_file(path: &Path) -> Result<usize> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let vars: Vec<(String, String)> = parse_dotenv(&content)
+        .into_iter()
+        .filter(|(key, _)| {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+        .collect();
+    for (key, value) in &vars {
+        env::set_var(key, value);
+    }
+    Ok(vars.len())
+}
+
+/// `KEY=value` lines, with optional `export`, quotes and comments
+fn parse_dotenv(content: &str) -> Vec<(String, String)> {
+    let mut vars = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        let value = if let Some(quoted) = value
+            .strip_prefix('"')
+            .and_then(|value| value.split_once('"'))
+            .or_else(|| {
+                value
+                    .strip_prefix('\'')
+                    .and_then(|value| value.split_once('\''))
+            }) {
+            quoted.0
+        } else {
+            value.split(" #").next().unwrap_or_default().trim_end()
+        };
+        vars.push((key.trim().to_string(), value.to_string()));
+    }
+    vars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_dir;
+
+    fn project_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = scratch_dir(name);
+        for (name, content) in files {
+            fs::write(dir.join(name), content).unwrap();
+        }
+        dir
+    }
+
+    fn project_env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn get_takes_the_first_source_that_sets_the_var() {
+        let dir = project_dir(
+            "precedence",
+            &[
+                (".env", "MYSQL_HOST=dotenv\nMYSQL_USER=dotenv\n"),
+                (".env.local", "MYSQL_USER=local\n"),
+            ],
+        );
+        let project = project_env(&[("MYSQL_HOST", "project")]);
+
+        let chain = EnvChain::new(
+            &[EnvSource::Project, EnvSource::Dotenv],
+            &dir,
+            &project,
+            false,
+        );
+        assert_eq!(chain.get("MYSQL_HOST").as_deref(), Some("project"));
+        assert_eq!(chain.get("MYSQL_USER").as_deref(), Some("local"));
+        assert_eq!(chain.get("MYSQL_PORT"), None);
+        let user = chain.explain("MYSQL_USER");
+        assert_eq!(user[1].origin, Some(dir.join(".env.local")));
+
+        let chain = EnvChain::new(
+            &[EnvSource::Dotenv, EnvSource::Project],
+            &dir,
+            &project,
+            false,
+        );
+        assert_eq!(chain.get("MYSQL_HOST").as_deref(), Some("dotenv"));
+    }
+
+    #[test]
+    fn explain_lists_each_source_once_with_why_it_was_skipped() {
+        env::set_var("ENV_SOURCES_TEST_HOST", "process");
+        let dir = project_dir("skipped", &[]);
+        let precedence = [
+            EnvSource::Project,
+            EnvSource::Process,
+            EnvSource::Project,
+            EnvSource::MyCnf,
+        ];
+
+        let chain = EnvChain::new(&precedence, &dir, &HashMap::new(), false);
+        let candidates = chain.explain("ENV_SOURCES_TEST_HOST");
+        let sources: Vec<EnvSource> = candidates
+            .iter()
+            .map(|candidate| candidate.source)
+            .collect();
+        assert_eq!(
+            sources,
+            [EnvSource::Project, EnvSource::Process, EnvSource::MyCnf]
+        );
+        assert_eq!(
+            candidates[0].skipped.as_deref(),
+            Some("the project environment wasn't captured")
+        );
+        assert_eq!(candidates[1].skipped, None);
+        assert_eq!(candidates[1].value.as_deref(), Some("process"));
+        assert_eq!(
+            candidates[2].skipped.as_deref(),
+            Some("--my-cnf wasn't given")
+        );
+        assert_eq!(
+            chain.get("ENV_SOURCES_TEST_HOST").as_deref(),
+            Some("process")
+        );
+    }
+
+    #[test]
+    fn parses_dotenv_lines() {
+        let content = "\
+# a comment
+export MYSQL_HOST=db.internal
+MYSQL_USER = app   # who connects
+MYSQL_PASSWORD=\"p#ss word\" # quoted
+MYSQL_DATABASE='app_dev'
+MYSQL_PORT=33#06
+
+not a variable
+EMPTY=
+";
+        let vars = parse_dotenv(content);
+        let vars: Vec<(&str, &str)> = vars
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            vars,
+            [
+                ("MYSQL_HOST", "db.internal"),
+                ("MYSQL_USER", "app"),
+                ("MYSQL_PASSWORD", "p#ss word"),
+                ("MYSQL_DATABASE", "app_dev"),
+                ("MYSQL_PORT", "33#06"),
+                ("EMPTY", ""),
+            ]
+        );
+    }
+}
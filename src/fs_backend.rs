@@ -0,0 +1,295 @@
+use crate::audit::{self, Entry};
+use crate::output::Output;
+use crate::safe_write;
+use anyhow::{Context, Result};
+use chrono::Local;
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// How far a run had got, so that what came after can be rolled back on its own
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mark {
+    changes: usize,
+    written: usize,
+    backups: usize,
+}
+
+/// A change made to a file, with what the file held before so it can be undone
+struct Change {
+    path: PathBuf,
+    /// The content before, `None` when the file didn't exist
+    before: Option<Vec<u8>>,
+    /// Hash of the content the change left, `None` when it removed the file
+    after: Option<String>,
+}
+
+/// Every filesystem mutation the tool performs goes through this backend,
+/// so policies like read-only mode are enforced, and each change is recorded in
+/// the audit log, in a single place.
+pub struct FsBackend {
+    read_only: bool,
+    /// Every change made to a file so far, in order, for rolling them back
+    changes: RefCell<Vec<Change>>,
+    /// Files written so far, for reporting what a run changed
+    written: RefCell<Vec<PathBuf>>,
+    /// Backups made so far, with the file each one is a copy of
+    backups: RefCell<Vec<(PathBuf, PathBuf)>>,
+    /// Directories nothing may be written under, with the reason
+    protected: Vec<(PathBuf, String)>,
+    /// Where audit log failures are warned about
+    out: Output,
+}
+
+impl FsBackend {
+    pub fn new(read_only: bool, out: &Output) -> Self {
+        Self {
+            read_only,
+            changes: RefCell::new(Vec::new()),
+            written: RefCell::new(Vec::new()),
+            backups: RefCell::new(Vec::new()),
+            protected: Vec::new(),
+            out: out.clone(),
+        }
+    }
+
+    /// Refuses every later write under `dir`, explaining why with `reason`
+    pub fn protect(&mut self, dir: PathBuf, reason: String) {
+        self.protected.push((dir, reason));
+    }
+
+    /// Fails when `action` (e.g. "write") on `path` would be refused
+    pub fn ensure_writable(&self, action: &str, path: &Path) -> Result<()> {
+        if self.read_only {
+            anyhow::bail!(
+                "Refusing to {} {}: running in read-only mode",
+                action,
+                path.display()
+            );
+        }
+        if let Some((_, reason)) = self.protected.iter().find(|(dir, _)| path.starts_with(dir)) {
+            anyhow::bail!("Refusing to {} {}: {}", action, path.display(), reason);
+        }
+        Ok(())
+    }
+
+    pub fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.ensure_writable("create directory", path)?;
+        fs::create_dir_all(path)
+            .with_context(|| format!("Failed to create directory {}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path, contents: &str) -> Result<()> {
+        self.ensure_writable("write", path)?;
+        // Writing while the IDE saves the same file would lose one of the two
+        safe_write::wait_until_idle(path)?;
+        let previous = fs::read(path).ok();
+        let before = previous.as_deref().map(audit::hash);
+        let after = audit::hash(contents.as_bytes());
+        write_atomically(path, contents.as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        let action = if previous.is_some() {
+            "write"
+        } else {
+            "create"
+        };
+        self.changes.borrow_mut().push(Change {
+            path: path.to_path_buf(),
+            before: previous,
+            after: Some(after.clone()),
+        });
+        let mut entry = Entry::new(action, path, before, Some(after));
+        entry.backup = self
+            .backups
+            .borrow()
+            .iter()
+            .rfind(|(original, _)| original == path)
+            .map(|(_, backup)| backup.clone());
+        audit::record(&entry, &self.out);
+        let mut written = self.written.borrow_mut();
+        if !written.iter().any(|file| file == path) {
+            written.push(path.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Files written through `write`, in the order they were first written
+    pub fn written(&self) -> Vec<PathBuf> {
+        self.written.borrow().clone()
+    }
+
+    /// Whether anything was changed so far
+    pub fn changed(&self) -> bool {
+        !self.changes.borrow().is_empty()
+    }
+
+    /// Backups made through `backup`
+    pub fn backups(&self) -> Vec<PathBuf> {
+        self.backups
+            .borrow()
+            .iter()
+            .map(|(_, backup)| backup.clone())
+            .collect()
+    }
+
+    /// Undoes every change so far: files this run created are removed, and the others
+    /// get back what they held before it, whether or not they were backed up. Returns
+    /// the files something else changed since, which are left as they are.
+    pub fn roll_back(&self) -> Result<Vec<PathBuf>> {
+        self.roll_back_to(Mark::default())
+    }
+
+    /// Where the run has got to, for `roll_back_to`
+    pub fn mark(&self) -> Mark {
+        Mark {
+            changes: self.changes.borrow().len(),
+            written: self.written.borrow().len(),
+            backups: self.backups.borrow().len(),
+        }
+    }
+
+    /// `roll_back` for the changes made since `mark`: a file changed before and after
+    /// it goes back to what it held at `mark`. Backups made since are removed.
+    pub fn roll_back_to(&self, mark: Mark) -> Result<Vec<PathBuf>> {
+        let changes = self.changes.borrow_mut().split_off(mark.changes);
+        self.written.borrow_mut().truncate(mark.written);
+        let backups = self.backups.borrow_mut().split_off(mark.backups);
+
+        let mut left: Vec<PathBuf> = Vec::new();
+        for change in changes.iter().rev() {
+            // The IDE saving the file since would lose what it saved
+            if left.contains(&change.path) || audit::hash_file(&change.path) != change.after {
+                if !left.contains(&change.path) {
+                    left.push(change.path.clone());
+                }
+                continue;
+            }
+            self.restore(change)?;
+        }
+        for (_, backup) in backups {
+            self.ensure_writable("remove", &backup)?;
+            let before = audit::hash_file(&backup);
+            fs::remove_file(&backup)
+                .with_context(|| format!("Failed to remove {}", backup.display()))?;
+            audit::record(&Entry::new("remove", &backup, before, None), &self.out);
+        }
+        Ok(left)
+    }
+
+    /// Puts back what `change` replaced, without recording it as a change of its own
+    fn restore(&self, change: &Change) -> Result<()> {
+        let path = &change.path;
+        self.ensure_writable("restore", path)?;
+        match &change.before {
+            Some(content) => {
+                write_atomically(path, content)
+                    .with_context(|| format!("Failed to restore {}", path.display()))?;
+            }
+            None => {
+                fs::remove_file(path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+        }
+        let before = change.after.clone();
+        let after = change.before.as_deref().map(audit::hash);
+        audit::record(&Entry::new("restore", path, before, after), &self.out);
+        Ok(())
+    }
+
+    /// Marks `path` as executable (no-op on platforms without permission bits)
+    pub fn set_executable(&self, path: &Path) -> Result<()> {
+        self.ensure_writable("change permissions of", path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+                .with_context(|| format!("Failed to make {} executable", path.display()))?;
+        }
+        Ok(())
+    }
+
+    pub fn remove_file(&self, path: &Path) -> Result<()> {
+        self.ensure_writable("remove", path)?;
+        let previous = fs::read(path).ok();
+        let before = previous.as_deref().map(audit::hash);
+        fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        self.changes.borrow_mut().push(Change {
+            path: path.to_path_buf(),
+            before: previous,
+            after: None,
+        });
+        audit::record(&Entry::new("remove", path, before, None), &self.out);
+        Ok(())
+    }
+
+    pub fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        let previous = fs::read(to).ok();
+        self.copy_as("copy", from, to)?;
+        self.changes.borrow_mut().push(Change {
+            path: to.to_path_buf(),
+            before: previous,
+            after: audit::hash_file(to),
+        });
+        Ok(())
+    }
+
+    /// `copy`, recorded in the audit log as `action`
+    fn copy_as(&self, action: &str, from: &Path, to: &Path) -> Result<()> {
+        self.ensure_writable("write", to)?;
+        let before = audit::hash_file(to);
+        fs::copy(from, to)
+            .with_context(|| format!("Failed to copy {} to {}", from.display(), to.display()))?;
+        let mut entry = Entry::new(action, to, before, audit::hash_file(to));
+        entry.source = Some(from.to_path_buf());
+        audit::record(&entry, &self.out);
+        Ok(())
+    }
+
+    /// Copies `file` next to itself with a timestamped `.backup.*.xml` extension,
+    /// returning the backup's path (`None` if `file` doesn't exist). Names go down to
+    /// the millisecond, with a counter on top when that's taken, so a second backup
+    /// of the same file in a run never replaces the first.
+    pub fn backup(&self, file: &Path) -> Result<Option<PathBuf>> {
+        if !file.exists() {
+            return Ok(None);
+        }
+        let timestamp = Local::now().format("%Y%m%d_%H%M%S_%3f").to_string();
+        let mut backup_file = file.with_extension(format!("backup.{}.xml", timestamp));
+        let mut count = 1;
+        while backup_file.exists() {
+            count += 1;
+            backup_file = file.with_extension(format!("backup.{}_{}.xml", timestamp, count));
+        }
+        self.copy_as("backup", file, &backup_file)?;
+        self.backups
+            .borrow_mut()
+            .push((file.to_path_buf(), backup_file.clone()));
+        Ok(Some(backup_file))
+    }
+}
+
+/// Writes `contents` to a temp file next to `path`, syncs it and renames it over
+/// `path`, so a run killed mid-write leaves either the old file or the new one,
+/// never a truncated one. A symlinked `path` has its target replaced, not the link.
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.{}.tmp", file_name, process::id()));
+
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents)?;
+        // The replaced file keeps its permissions, e.g. a shim stays executable
+        if let Ok(metadata) = fs::metadata(&path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
@@ -0,0 +1,62 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Gems RubyMine needs for a feature; any one of the alternatives will do
+pub struct RequiredGem {
+    pub alternatives: &'static [&'static str],
+    pub feature: &'static str,
+}
+
+pub static REQUIRED_GEMS: &[RequiredGem] = &[
+    RequiredGem {
+        alternatives: &["debug", "ruby-debug-ide"],
+        feature: "debugging",
+    },
+    RequiredGem {
+        alternatives: &["minitest"],
+        feature: "running tests",
+    },
+];
+
+/// The required gems none of whose alternatives are installed for the ruby that
+/// `command` (the configurator prefix, e.g. `shadowenv exec --dir D --`) runs.
+/// `None` when ruby couldn't be asked.
+pub fn missing(command: &[String]) -> Option<Vec<&'static RequiredGem>> {
+    let names: Vec<&str> = REQUIRED_GEMS
+        .iter()
+        .flat_map(|gem| gem.alternatives.iter().copied())
+        .collect();
+    let script = format!(
+        "%w[{}].each {{ |name| puts name if Gem::Specification.find_all_by_name(name).any? }}",
+        names.join(" ")
+    );
+
+    let output = Command::new(&command[0])
+        .args(&command[1..])
+        .args(["ruby", "-e", &script])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let installed: Vec<&str> = stdout.lines().map(|line| line.trim()).collect();
+    Some(
+        REQUIRED_GEMS
+            .iter()
+            .filter(|gem| !gem.alternatives.iter().any(|name| installed.contains(name)))
+            .collect(),
+    )
+}
+
+/// The command that installs `gem`: `bundle add` when the project has a Gemfile,
+/// since gems outside the bundle aren't loadable from it
+pub fn install_command(gem: &RequiredGem, project_dir: &Path) -> String {
+    let name = gem.alternatives[0];
+    if project_dir.join("Gemfile").exists() {
+        format!("bundle add {} --group development,test", name)
+    } else {
+        format!("gem install {}", name)
+    }
+}
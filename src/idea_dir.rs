@@ -0,0 +1,25 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory holding a project's IDE settings
+pub const NAME: &str = ".idea";
+
+pub fn path(project_dir: &Path) -> PathBuf {
+    project_dir.join(NAME)
+}
+
+/// Where `project_dir`'s .idea really is when it's a symlink leading outside the
+/// project, e.g. to a shared settings checkout. `None` for a plain directory or a
+/// symlink within the project.
+pub fn outside_target(project_dir: &Path) -> Option<PathBuf> {
+    let link = path(project_dir);
+    if !fs::symlink_metadata(&link).ok()?.file_type().is_symlink() {
+        return None;
+    }
+    // A dangling link counts as outside: there's nothing in the project to write to
+    let Ok(target) = fs::canonicalize(&link) else {
+        return Some(project_dir.join(fs::read_link(&link).ok()?));
+    };
+    let project_dir = fs::canonicalize(project_dir).unwrap_or(project_dir.to_path_buf());
+    (!target.starts_with(&project_dir)).then_some(target)
+}
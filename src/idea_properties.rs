@@ -0,0 +1,66 @@
+use dirs::home_dir;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Values from the IDE's idea.properties files, which can relocate the config
+/// and system directories (`idea.config.path`, `idea.system.path`).
+#[derive(Debug, Default)]
+pub struct IdeaProperties {
+    values: HashMap<String, String>,
+}
+
+impl IdeaProperties {
+    /// Loads `files` in order; properties from earlier files win over later ones
+    pub fn load(files: &[PathBuf]) -> Self {
+        let mut values = HashMap::new();
+        for file in files.iter().rev() {
+            if let Ok(content) = fs::read_to_string(file) {
+                values.extend(Self::parse(&content));
+            }
+        }
+        Self { values }
+    }
+
+    fn parse(content: &str) -> HashMap<String, String> {
+        let mut values = HashMap::new();
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+            let Some(separator) = line.find(['=', ':']) else {
+                continue;
+            };
+            let key = line[..separator].trim();
+            let value = line[separator + 1..].trim();
+            // Java properties escape backslashes, which matters for Windows paths
+            values.insert(key.to_string(), value.replace("\\\\", "\\"));
+        }
+        values
+    }
+
+    /// A property as a path, with `${user.home}` and a leading `~` expanded
+    pub fn path(&self, key: &str) -> Option<PathBuf> {
+        let value = self.values.get(key)?;
+        let home = home_dir().unwrap_or_else(|| PathBuf::from("/"));
+        let home_str = home.to_string_lossy();
+
+        let expanded = value.replace("${user.home}", &home_str);
+        let path = match expanded.strip_prefix("~/") {
+            Some(rest) => home.join(rest),
+            None => PathBuf::from(expanded),
+        };
+        (!path.as_os_str().is_empty()).then_some(path)
+    }
+}
+
+/// Location of idea.properties inside an IDE install (bin/ next to the launcher)
+pub fn bundled_properties_file(app_path: &Path) -> PathBuf {
+    let contents_dir = app_path.join("Contents");
+    if contents_dir.is_dir() {
+        contents_dir.join("bin").join("idea.properties")
+    } else {
+        app_path.join("bin").join("idea.properties")
+    }
+}
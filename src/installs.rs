@@ -0,0 +1,88 @@
+use crate::{Channel, Ide};
+use dirs::data_local_dir;
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Uninstall keys the Windows installer registers each IDE version under
+const UNINSTALL_KEYS: &[&str] = &[
+    r"HKLM\SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall",
+    r"HKLM\SOFTWARE\WOW6432Node\Microsoft\Windows\CurrentVersion\Uninstall",
+    r"HKCU\SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall",
+];
+
+/// Whether an installer or Toolbox display name is `ide` itself, not e.g. IDEA Community,
+/// which can't run the Ruby plugin
+fn is_ide(ide: Ide, display_name: &str) -> bool {
+    display_name.starts_with(ide.windows_install_prefix()) && !display_name.contains("Community")
+}
+
+/// Install locations from the Windows uninstall registry keys, newest version last.
+/// Covers installs outside Program Files, e.g. per-user ones in %LOCALAPPDATA%\Programs.
+pub fn registry(ide: Ide, channel: Channel) -> Vec<PathBuf> {
+    if !cfg!(windows) {
+        return Vec::new();
+    }
+    let mut installs = Vec::new();
+    for key in UNINSTALL_KEYS {
+        let Ok(output) = Command::new("reg").args(["query", key, "/s"]).output() else {
+            continue;
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        // One block per subkey: the key path, then `    Name    REG_SZ    value` lines
+        for block in stdout
+            .split("\r\n\r\n")
+            .flat_map(|block| block.split("\n\n"))
+        {
+            let value = |name: &str| {
+                block.lines().find_map(|line| {
+                    let mut fields = line.trim().splitn(3, "    ");
+                    (fields.next()? == name && fields.next()?.starts_with("REG_"))
+                        .then(|| fields.next().unwrap_or_default().trim().to_string())
+                })
+            };
+            let (Some(display_name), Some(location)) =
+                (value("DisplayName"), value("InstallLocation"))
+            else {
+                continue;
+            };
+            if is_ide(ide, &display_name) && channel.accepts(&display_name) {
+                installs.push((display_name, PathBuf::from(location.trim_matches('"'))));
+            }
+        }
+    }
+    installs.sort();
+    installs.into_iter().map(|(_, location)| location).collect()
+}
+
+/// Install locations Toolbox 2.x records in its state.json, newest version last
+pub fn toolbox_state(ide: Ide, channel: Channel) -> Vec<PathBuf> {
+    let Some(state_file) =
+        data_local_dir().map(|dir| dir.join("JetBrains").join("Toolbox").join("state.json"))
+    else {
+        return Vec::new();
+    };
+    let Some(state) = fs::read_to_string(state_file)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+    else {
+        return Vec::new();
+    };
+
+    let mut installs: Vec<(String, PathBuf)> = state["tools"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|tool| {
+            let display_name = tool["displayName"].as_str()?;
+            let version = tool["displayVersion"].as_str().unwrap_or_default();
+            let location = tool["installLocation"].as_str()?;
+            let name = format!("{} {}", display_name, version);
+            (is_ide(ide, display_name) && channel.accepts(&name))
+                .then(|| (name, PathBuf::from(location)))
+        })
+        .collect();
+    installs.sort();
+    installs.into_iter().map(|(_, location)| location).collect()
+}
@@ -0,0 +1,30 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// One interpreter to configure, as another detection tool describes it on a JSON line:
+/// `{"dir": "...", "ruby": "..."}`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InterpreterSpec {
+    /// The project directory
+    pub dir: PathBuf,
+    /// The ruby to use, instead of the one detected in the project's environment
+    pub ruby: Option<PathBuf>,
+}
+
+/// One spec per non-blank line, with relative paths resolved against `base`
+pub fn parse(input: &str, base: &Path) -> Result<Vec<InterpreterSpec>> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let mut spec: InterpreterSpec = serde_json::from_str(line)
+                .with_context(|| format!("Invalid interpreter on line {}", index + 1))?;
+            spec.dir = base.join(&spec.dir);
+            spec.ruby = spec.ruby.map(|ruby| base.join(ruby));
+            Ok(spec)
+        })
+        .collect()
+}
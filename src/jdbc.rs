@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+/// JDBC driver family of a data source, named after the IDE's driver-ref
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Driver {
+    #[default]
+    #[cfg_attr(feature = "cli", value(name = "mysql.8"))]
+    #[serde(rename = "mysql.8")]
+    Mysql8,
+    #[cfg_attr(feature = "cli", value(name = "mariadb"))]
+    #[serde(rename = "mariadb")]
+    Mariadb,
+    #[cfg_attr(feature = "cli", value(name = "postgresql"))]
+    #[serde(rename = "postgresql")]
+    Postgresql,
+}
+
+/// Driver settings for one data source in `datasource_drivers`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DriverSetting {
+    pub driver: Option<Driver>,
+    /// Driver version recorded in database-info, e.g. "8.0.33"
+    pub version: Option<String>,
+}
+
+impl Driver {
+    /// The IDE's built-in driver definition the data source points at
+    pub fn driver_ref(self) -> &'static str {
+        match self {
+            Driver::Mysql8 => "mysql.8",
+            Driver::Mariadb => "mariadb",
+            Driver::Postgresql => "postgresql",
+        }
+    }
+
+    pub fn jdbc_class(self) -> &'static str {
+        match self {
+            Driver::Mysql8 => "com.mysql.cj.jdbc.Driver",
+            Driver::Mariadb => "org.mariadb.jdbc.Driver",
+            Driver::Postgresql => "org.postgresql.Driver",
+        }
+    }
+
+    pub fn jdbc_url(self, host: &str, port: &str) -> String {
+        let scheme = match self {
+            Driver::Mysql8 => "mysql",
+            Driver::Mariadb => "mariadb",
+            Driver::Postgresql => "postgresql",
+        };
+        format!("jdbc:{}://{}:{}", scheme, host, port)
+    }
+
+    /// Product name and DBMS id of database-info
+    pub fn product(self) -> (&'static str, &'static str) {
+        match self {
+            Driver::Mysql8 => ("MySQL", "MYSQL"),
+            Driver::Mariadb => ("MariaDB", "MARIADB"),
+            Driver::Postgresql => ("PostgreSQL", "POSTGRES"),
+        }
+    }
+
+    pub fn driver_name(self) -> &'static str {
+        match self {
+            Driver::Mysql8 => "MySQL Connector/J",
+            Driver::Mariadb => "MariaDB Connector/J",
+            Driver::Postgresql => "PostgreSQL JDBC Driver",
+        }
+    }
+
+    /// Driver version written when none is configured, as the driver reports it
+    pub fn default_version(self) -> &'static str {
+        match self {
+            Driver::Mysql8 => {
+                "mysql-connector-java-8.0.25 (Revision: 08be9e9b4cba6aa115f9b27b215887af40b159e0)"
+            }
+            Driver::Mariadb => "3.1.4",
+            Driver::Postgresql => "42.6.0",
+        }
+    }
+
+    /// Whether a database.yml entry with `adapter` is a database this driver connects to
+    pub fn reads_adapter(self, adapter: &str) -> bool {
+        match self {
+            Driver::Mysql8 | Driver::Mariadb => adapter.contains("mysql") || adapter == "trilogy",
+            Driver::Postgresql => adapter == "postgresql" || adapter == "postgis",
+        }
+    }
+
+    /// Port and user the server and its client assume when none is given
+    pub fn defaults(self) -> (&'static str, &'static str) {
+        match self {
+            Driver::Mysql8 | Driver::Mariadb => ("3306", "root"),
+            Driver::Postgresql => ("5432", "postgres"),
+        }
+    }
+
+    /// Characters allowed in identifiers besides letters, digits and `_`, and the
+    /// identifier quote
+    pub fn identifier_syntax(self) -> (&'static str, &'static str) {
+        match self {
+            Driver::Mysql8 | Driver::Mariadb => ("#@", "`"),
+            Driver::Postgresql => ("", "\""),
+        }
+    }
+}
+
+/// `major.minor` of a driver version string, for exact-driver-version
+pub fn exact_version(version: &str) -> String {
+    version
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .find(|part| part.contains('.'))
+        .map(|number| number.split('.').take(2).collect::<Vec<_>>().join("."))
+        .unwrap_or_else(|| version.to_string())
+}
@@ -0,0 +1,127 @@
+use crate::jdk_table::SdkEntry;
+use roxmltree::Node;
+
+/// What most of the tool needs to know about an SDK entry from jdk.table.xml.
+///
+/// IDE versions don't agree on how entries are spelled: the usual form is
+/// `<name value="..."/>`, but some write the value as a text node or put it
+/// on the `<jdk>` element itself. Only direct children are considered so that
+/// nested elements (e.g. inside `<additional>`) can't be mistaken for them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JdkEntry {
+    pub name: String,
+    pub version: Option<String>,
+    pub home_path: Option<String>,
+    /// The `custom-configurator` command RubyMine runs ruby through, if any
+    pub configurator: Vec<String>,
+}
+
+impl JdkEntry {
+    /// Parses a `<jdk>` element, returning `None` for anything without a usable name
+    pub fn parse(node: &Node) -> Option<Self> {
+        SdkEntry::parse(node).map(Self::from)
+    }
+}
+
+impl From<SdkEntry> for JdkEntry {
+    fn from(sdk: SdkEntry) -> Self {
+        let configurator = sdk
+            .additional
+            .and_then(|additional| additional.version_manager)
+            .and_then(|manager| manager.custom_configurator)
+            .unwrap_or_default();
+        Self {
+            name: sdk.name,
+            version: sdk.version,
+            home_path: sdk.home_path,
+            configurator,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jdk_table::JdkTable;
+    use roxmltree::Document;
+
+    fn entries(xml: &str) -> Vec<JdkEntry> {
+        JdkTable::parse(xml)
+            .unwrap()
+            .sdks
+            .into_iter()
+            .map(JdkEntry::from)
+            .collect()
+    }
+
+    #[test]
+    fn parses_values_from_attributes() {
+        let entries = entries(include_str!(
+            "../tests/fixtures/jdk_table/attribute_values.xml"
+        ));
+        assert_eq!(
+            entries,
+            [JdkEntry {
+                name: "Ruby 3.3.0 (shop) + shadowenv".to_string(),
+                version: Some(
+                    "ver.3.3.0 p0 (2023-12-25 revision 5124f9ac75) [arm64-darwin23]".to_string()
+                ),
+                home_path: Some("/opt/rubies/3.3.0/bin/ruby".to_string()),
+                configurator: [
+                    "/opt/homebrew/bin/shadowenv",
+                    "exec",
+                    "--dir",
+                    "/Users/me/src/shop",
+                    "--",
+                ]
+                .map(str::to_string)
+                .to_vec(),
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_values_from_text_and_the_jdk_element() {
+        let entries = entries(include_str!("../tests/fixtures/jdk_table/text_values.xml"));
+        assert_eq!(
+            entries,
+            [
+                JdkEntry {
+                    name: "Ruby 3.2.2".to_string(),
+                    version: Some("ver.3.2.2".to_string()),
+                    home_path: Some("/usr/local/bin/ruby".to_string()),
+                    configurator: Vec::new(),
+                },
+                JdkEntry {
+                    name: "Ruby 3.1.4 (legacy)".to_string(),
+                    version: None,
+                    home_path: Some("/usr/bin/ruby".to_string()),
+                    configurator: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_nameless_entries_and_other_components() {
+        let entries = entries(include_str!("../tests/fixtures/jdk_table/nameless.xml"));
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["Ruby 3.3.0"]);
+    }
+
+    #[test]
+    fn parse_only_takes_jdk_elements() {
+        let doc =
+            Document::parse(include_str!("../tests/fixtures/jdk_table/nameless.xml")).unwrap();
+        let component = doc
+            .descendants()
+            .find(|node| node.has_tag_name("component"))
+            .unwrap();
+        assert_eq!(JdkEntry::parse(&component), None);
+        let parsed: Vec<JdkEntry> = component
+            .children()
+            .filter_map(|node| JdkEntry::parse(&node))
+            .collect();
+        assert_eq!(parsed.len(), 1);
+    }
+}
@@ -0,0 +1,641 @@
+use crate::xml_misc;
+use anyhow::Result;
+use roxmltree::{Document, Node};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use xmlwriter::{Options, XmlWriter};
+
+/// The component of jdk.table.xml holding the SDKs
+pub const COMPONENT: &str = "ProjectJdkTable";
+
+/// Attributes of `<additional>` that have fields of their own
+const MODELED_ATTRIBUTES: [&str; 2] = ["version", "GEMS_BIN_DIR_PATH"];
+
+/// The SDKs in jdk.table.xml, in file order, as data that can be queried, compared
+/// and changed before being written back
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JdkTable {
+    pub sdks: Vec<SdkEntry>,
+}
+
+/// One `<jdk>` entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SdkEntry {
+    /// The `version` attribute of `<jdk>`, i.e. of the entry format
+    pub format_version: Option<String>,
+    pub name: String,
+    /// e.g. "RUBY_SDK"
+    pub sdk_type: Option<String>,
+    /// The SDK's own version, e.g. "ver.3.3.0 p0 (2023-12-25 revision 5124f9ac75) [arm64-darwin23]"
+    pub version: Option<String>,
+    pub home_path: Option<String>,
+    pub roots: Option<Roots>,
+    pub additional: Option<Additional>,
+}
+
+/// Class and source paths of an SDK
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Roots {
+    pub class_path: Vec<Root>,
+    pub source_path: Vec<Root>,
+}
+
+/// A `<root>`: a "composite" one holding others, or a "simple" one with a url
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Root {
+    pub root_type: String,
+    pub url: Option<String>,
+    pub children: Vec<Root>,
+}
+
+/// The Ruby plugin's settings for an SDK
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Additional {
+    pub version: Option<String>,
+    pub gems_bin_dir_path: Option<String>,
+    /// Attributes besides those two, in order
+    pub attributes: Vec<(String, String)>,
+    /// Remote SDK settings (PATH_MAPPINGS, REMOTE_SDK_DATA) and anything else there,
+    /// kept as they are
+    pub other: Vec<Element>,
+    pub version_manager: Option<VersionManager>,
+}
+
+/// `<VERSION_MANAGER>`: how the plugin finds and runs the ruby
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionManager {
+    /// e.g. "rbenv", "asdf" or "system"
+    pub id: String,
+    /// The command RubyMine runs ruby through, when it has one
+    pub custom_configurator: Option<Vec<String>>,
+}
+
+/// An element the model has no type for, with its attributes in order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+}
+
+/// A setting passed through to `<additional>` as is: an attribute, or a child
+/// element with these attributes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AdditionalValue {
+    Attribute(String),
+    Element(BTreeMap<String, String>),
+}
+
+/// How an SDK differs between two tables
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SdkChange {
+    Added(String),
+    Removed(String),
+    Changed(String),
+}
+
+impl JdkTable {
+    /// The entries of every `ProjectJdkTable` in `xml`, skipping those without a name
+    pub fn parse(xml: &str) -> Result<Self> {
+        let doc = Document::parse(xml)?;
+        let sdks = doc
+            .descendants()
+            .filter(|node| {
+                node.has_tag_name("component") && node.attribute("name") == Some(COMPONENT)
+            })
+            .flat_map(|component| component.children())
+            .filter_map(|node| SdkEntry::parse(&node))
+            .collect();
+        Ok(Self { sdks })
+    }
+
+    /// A jdk.table.xml with only these entries
+    pub fn to_xml(&self) -> String {
+        let mut writer = XmlWriter::new(Options::default());
+        writer.write_declaration();
+        writer.start_element("application");
+        writer.start_element("component");
+        writer.write_attribute("name", COMPONENT);
+        for sdk in &self.sdks {
+            sdk.write(&mut writer);
+        }
+        writer.end_element(); // component
+        writer.end_element(); // application
+        writer.end_document()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SdkEntry> {
+        self.sdks.iter().find(|sdk| sdk.name == name)
+    }
+
+    /// Replaces the entry of the same name, or adds `sdk` at the end
+    pub fn upsert(&mut self, sdk: SdkEntry) {
+        match self
+            .sdks
+            .iter_mut()
+            .find(|existing| existing.name == sdk.name)
+        {
+            Some(existing) => *existing = sdk,
+            None => self.sdks.push(sdk),
+        }
+    }
+
+    /// Removes every entry named `name`, returning the first
+    pub fn remove(&mut self, name: &str) -> Option<SdkEntry> {
+        let index = self.sdks.iter().position(|sdk| sdk.name == name)?;
+        let removed = self.sdks.remove(index);
+        self.sdks.retain(|sdk| sdk.name != name);
+        Some(removed)
+    }
+
+    /// What turns this table into `other`, by SDK name
+    pub fn diff(&self, other: &JdkTable) -> Vec<SdkChange> {
+        let mut changes: Vec<SdkChange> = self
+            .sdks
+            .iter()
+            .filter_map(|sdk| match other.get(&sdk.name) {
+                None => Some(SdkChange::Removed(sdk.name.clone())),
+                Some(theirs) if theirs != sdk => Some(SdkChange::Changed(sdk.name.clone())),
+                Some(_) => None,
+            })
+            .collect();
+        changes.extend(
+            other
+                .sdks
+                .iter()
+                .filter(|sdk| self.get(&sdk.name).is_none())
+                .map(|sdk| SdkChange::Added(sdk.name.clone())),
+        );
+        changes
+    }
+}
+
+impl SdkEntry {
+    /// Parses a `<jdk>` element, returning `None` for anything without a usable name.
+    /// Values may be spelled as `value` attributes or text.
+    pub fn parse(node: &Node) -> Option<Self> {
+        if !node.has_tag_name("jdk") {
+            return None;
+        }
+        let name = child_value(node, "name")
+            .or_else(|| node.attribute("name").map(str::to_string))
+            .filter(|name| !name.is_empty())?;
+        Some(Self {
+            format_version: node.attribute("version").map(str::to_string),
+            name,
+            sdk_type: child_value(node, "type"),
+            version: child_value(node, "version"),
+            home_path: child_value(node, "homePath"),
+            roots: child(node, "roots").map(|roots| Roots::parse(&roots)),
+            additional: child(node, "additional").map(|additional| Additional::parse(&additional)),
+        })
+    }
+
+    pub fn write(&self, writer: &mut XmlWriter) {
+        writer.start_element("jdk");
+        if let Some(format_version) = &self.format_version {
+            xml_misc::write_attribute(writer, "version", format_version);
+        }
+        write_value(writer, "name", Some(&self.name));
+        write_value(writer, "type", self.sdk_type.as_ref());
+        write_value(writer, "version", self.version.as_ref());
+        write_value(writer, "homePath", self.home_path.as_ref());
+        if let Some(roots) = &self.roots {
+            roots.write(writer);
+        }
+        if let Some(additional) = &self.additional {
+            additional.write(writer);
+        }
+        writer.end_element();
+    }
+}
+
+impl Roots {
+    fn parse(node: &Node) -> Self {
+        let roots = |tag| {
+            child(node, tag)
+                .into_iter()
+                .flat_map(|path| path.children())
+                .filter(|root| root.has_tag_name("root"))
+                .map(|root| Root::parse(&root))
+                .collect()
+        };
+        Self {
+            class_path: roots("classPath"),
+            source_path: roots("sourcePath"),
+        }
+    }
+
+    fn write(&self, writer: &mut XmlWriter) {
+        writer.start_element("roots");
+        for (tag, roots) in [
+            ("classPath", &self.class_path),
+            ("sourcePath", &self.source_path),
+        ] {
+            writer.start_element(tag);
+            for root in roots {
+                root.write(writer);
+            }
+            writer.end_element();
+        }
+        writer.end_element();
+    }
+}
+
+impl Root {
+    /// A composite root with nothing in it, which the IDE fills in on its own
+    pub fn composite() -> Self {
+        Self {
+            root_type: "composite".to_string(),
+            url: None,
+            children: Vec::new(),
+        }
+    }
+
+    fn parse(node: &Node) -> Self {
+        Self {
+            root_type: node.attribute("type").unwrap_or_default().to_string(),
+            url: node.attribute("url").map(str::to_string),
+            children: node
+                .children()
+                .filter(|child| child.has_tag_name("root"))
+                .map(|child| Self::parse(&child))
+                .collect(),
+        }
+    }
+
+    fn write(&self, writer: &mut XmlWriter) {
+        writer.start_element("root");
+        if let Some(url) = &self.url {
+            xml_misc::write_attribute(writer, "url", url);
+        }
+        xml_misc::write_attribute(writer, "type", &self.root_type);
+        for child in &self.children {
+            child.write(writer);
+        }
+        writer.end_element();
+    }
+}
+
+impl Additional {
+    /// Adds `value` under `key`, for settings the model has no field for. Fails on
+    /// keys that aren't XML names or that are set already.
+    pub fn set(&mut self, key: &str, value: &AdditionalValue) -> Result<()> {
+        let is_name = key.chars().enumerate().all(|(index, c)| {
+            c.is_ascii_alphabetic()
+                || c == '_'
+                || (index > 0 && (c.is_ascii_digit() || "-.".contains(c)))
+        });
+        if key.is_empty() || !is_name {
+            anyhow::bail!("{:?} isn't a valid XML name", key);
+        }
+        match value {
+            AdditionalValue::Attribute(value) => {
+                let taken = MODELED_ATTRIBUTES.contains(&key)
+                    || self.attributes.iter().any(|(name, _)| name == key);
+                if taken {
+                    anyhow::bail!("{} is already set on <additional>", key);
+                }
+                self.attributes.push((key.to_string(), value.clone()));
+            }
+            AdditionalValue::Element(attributes) => {
+                let taken = key == "VERSION_MANAGER"
+                    || self.other.iter().any(|element| element.name == key);
+                if taken {
+                    anyhow::bail!("<{}> is already in <additional>", key);
+                }
+                self.other.push(Element {
+                    name: key.to_string(),
+                    attributes: attributes
+                        .iter()
+                        .map(|(name, value)| (name.clone(), value.clone()))
+                        .collect(),
+                    children: Vec::new(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn parse(node: &Node) -> Self {
+        Self {
+            version: node.attribute("version").map(str::to_string),
+            gems_bin_dir_path: node.attribute("GEMS_BIN_DIR_PATH").map(str::to_string),
+            attributes: node
+                .attributes()
+                .filter(|attr| !MODELED_ATTRIBUTES.contains(&attr.name()))
+                .map(|attr| (attr.name().to_string(), attr.value().to_string()))
+                .collect(),
+            other: node
+                .children()
+                .filter(|child| child.is_element() && !child.has_tag_name("VERSION_MANAGER"))
+                .map(|child| Element::parse(&child))
+                .collect(),
+            version_manager: child(node, "VERSION_MANAGER").map(|manager| VersionManager {
+                id: manager.attribute("ID").unwrap_or_default().to_string(),
+                custom_configurator: child(&manager, "custom-configurator").map(|configurator| {
+                    configurator
+                        .descendants()
+                        .filter(|option| option.has_tag_name("option"))
+                        .filter_map(|option| option.attribute("value"))
+                        .map(str::to_string)
+                        .collect()
+                }),
+            }),
+        }
+    }
+
+    fn write(&self, writer: &mut XmlWriter) {
+        writer.start_element("additional");
+        if let Some(version) = &self.version {
+            xml_misc::write_attribute(writer, "version", version);
+        }
+        if let Some(gems_bin_dir_path) = &self.gems_bin_dir_path {
+            xml_misc::write_attribute(writer, "GEMS_BIN_DIR_PATH", gems_bin_dir_path);
+        }
+        for (name, value) in &self.attributes {
+            xml_misc::write_attribute(writer, name, value);
+        }
+        for element in &self.other {
+            element.write(writer);
+        }
+        if let Some(manager) = &self.version_manager {
+            writer.start_element("VERSION_MANAGER");
+            xml_misc::write_attribute(writer, "ID", &manager.id);
+            if let Some(options) = &manager.custom_configurator {
+                writer.start_element("custom-configurator");
+                writer.start_element("list");
+                for option in options {
+                    writer.start_element("option");
+                    xml_misc::write_attribute(writer, "value", option);
+                    writer.end_element();
+                }
+                writer.end_element(); // list
+                writer.end_element(); // custom-configurator
+            }
+            writer.end_element(); // VERSION_MANAGER
+        }
+        writer.end_element();
+    }
+}
+
+impl Element {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn attribute(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.attributes.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn child(mut self, child: Element) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    fn parse(node: &Node) -> Self {
+        Self {
+            name: node.tag_name().name().to_string(),
+            attributes: node
+                .attributes()
+                .map(|attr| (attr.name().to_string(), attr.value().to_string()))
+                .collect(),
+            children: node
+                .children()
+                .filter(Node::is_element)
+                .map(|child| Self::parse(&child))
+                .collect(),
+        }
+    }
+
+    fn write(&self, writer: &mut XmlWriter) {
+        writer.start_element(&self.name);
+        for (name, value) in &self.attributes {
+            xml_misc::write_attribute(writer, name, value);
+        }
+        for child in &self.children {
+            child.write(writer);
+        }
+        writer.end_element();
+    }
+}
+
+fn child<'a, 'input>(node: &Node<'a, 'input>, tag: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(tag))
+}
+
+/// Value of a direct child element, from its `value` attribute or its text
+fn child_value(node: &Node, tag: &str) -> Option<String> {
+    let child = child(node, tag)?;
+    child.attribute("value").map(str::to_string).or_else(|| {
+        let text: String = child
+            .children()
+            .filter(Node::is_text)
+            .filter_map(|n| n.text())
+            .collect();
+        let text = text.trim();
+        (!text.is_empty()).then(|| text.to_string())
+    })
+}
+
+fn write_value(writer: &mut XmlWriter, tag: &str, value: Option<&String>) {
+    if let Some(value) = value {
+        writer.start_element(tag);
+        xml_misc::write_attribute(writer, "value", value);
+        writer.end_element();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REMOTE: &str = include_str!("../tests/fixtures/jdk_table/remote.xml");
+
+    fn sdk(name: &str, home_path: &str) -> SdkEntry {
+        SdkEntry {
+            format_version: Some("2".to_string()),
+            name: name.to_string(),
+            sdk_type: Some("RUBY_SDK".to_string()),
+            version: None,
+            home_path: Some(home_path.to_string()),
+            roots: None,
+            additional: None,
+        }
+    }
+
+    fn names(table: &JdkTable) -> Vec<&str> {
+        table.sdks.iter().map(|sdk| sdk.name.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_roots_remote_settings_and_the_version_manager() {
+        let table = JdkTable::parse(REMOTE).unwrap();
+        assert_eq!(
+            names(&table),
+            ["Remote ruby 3.3.0 (app)", "Ruby 3.2.2 & friends"]
+        );
+
+        let remote = &table.sdks[0];
+        let roots = remote.roots.as_ref().unwrap();
+        assert_eq!(
+            roots.class_path[0].children[0].url.as_deref(),
+            Some("file:///usr/local/lib/ruby/3.3.0")
+        );
+        assert_eq!(roots.source_path, [Root::composite()]);
+
+        let additional = remote.additional.as_ref().unwrap();
+        assert_eq!(
+            additional.gems_bin_dir_path.as_deref(),
+            Some("/usr/local/bundle/bin")
+        );
+        assert_eq!(
+            additional.attributes,
+            [("SHOW_SDK_WARNINGS".to_string(), "false".to_string())]
+        );
+        let other: Vec<&str> = additional
+            .other
+            .iter()
+            .map(|element| element.name.as_str())
+            .collect();
+        assert_eq!(other, ["PATH_MAPPINGS", "REMOTE_SDK_DATA"]);
+        assert_eq!(
+            additional.other[0].children[0],
+            Element::new("mapping")
+                .attribute("local-root", "/src/app")
+                .attribute("remote-root", "/app")
+        );
+        let manager = additional.version_manager.as_ref().unwrap();
+        assert_eq!(manager.id, "system");
+        assert_eq!(
+            manager.custom_configurator.as_deref().unwrap(),
+            ["/opt/homebrew/bin/shadowenv", "exec", "--"]
+        );
+
+        let rbenv = table.sdks[1].additional.as_ref().unwrap();
+        assert_eq!(
+            rbenv.version_manager,
+            Some(VersionManager {
+                id: "rbenv".to_string(),
+                custom_configurator: None
+            })
+        );
+    }
+
+    #[test]
+    fn to_xml_round_trips() {
+        for xml in [
+            REMOTE,
+            include_str!("../tests/fixtures/jdk_table/attribute_values.xml"),
+            include_str!("../tests/fixtures/jdk_table/text_values.xml"),
+        ] {
+            let table = JdkTable::parse(xml).unwrap();
+            let written = table.to_xml();
+            assert_eq!(JdkTable::parse(&written).unwrap(), table);
+            assert_eq!(JdkTable::parse(&written).unwrap().to_xml(), written);
+        }
+    }
+
+    #[test]
+    fn upsert_replaces_in_place_or_appends() {
+        let mut table = JdkTable::parse(REMOTE).unwrap();
+        table.upsert(sdk("Remote ruby 3.3.0 (app)", "/usr/bin/ruby"));
+        assert_eq!(
+            names(&table),
+            ["Remote ruby 3.3.0 (app)", "Ruby 3.2.2 & friends"]
+        );
+        assert_eq!(table.sdks[0].home_path.as_deref(), Some("/usr/bin/ruby"));
+        assert_eq!(table.sdks[0].additional, None);
+
+        table.upsert(sdk("Ruby 3.4.1", "/opt/rubies/3.4.1/bin/ruby"));
+        assert_eq!(
+            names(&table),
+            [
+                "Remote ruby 3.3.0 (app)",
+                "Ruby 3.2.2 & friends",
+                "Ruby 3.4.1"
+            ]
+        );
+    }
+
+    #[test]
+    fn remove_drops_every_entry_of_the_name() {
+        let mut table = JdkTable {
+            sdks: vec![
+                sdk("Ruby 3.3.0", "/first/ruby"),
+                sdk("Ruby 3.2.2", "/usr/bin/ruby"),
+                sdk("Ruby 3.3.0", "/second/ruby"),
+            ],
+        };
+        let removed = table.remove("Ruby 3.3.0").unwrap();
+        assert_eq!(removed.home_path.as_deref(), Some("/first/ruby"));
+        assert_eq!(names(&table), ["Ruby 3.2.2"]);
+        assert_eq!(table.remove("Ruby 3.3.0"), None);
+    }
+
+    #[test]
+    fn diff_lists_removed_and_changed_entries_then_added_ones() {
+        let before = JdkTable {
+            sdks: vec![
+                sdk("Gone", "/gone/ruby"),
+                sdk("Same", "/same/ruby"),
+                sdk("Moved", "/old/ruby"),
+            ],
+        };
+        let after = JdkTable {
+            sdks: vec![
+                sdk("New", "/new/ruby"),
+                sdk("Moved", "/new/ruby"),
+                sdk("Same", "/same/ruby"),
+            ],
+        };
+        assert_eq!(
+            before.diff(&after),
+            [
+                SdkChange::Removed("Gone".to_string()),
+                SdkChange::Changed("Moved".to_string()),
+                SdkChange::Added("New".to_string()),
+            ]
+        );
+        assert!(after.diff(&after).is_empty());
+    }
+
+    #[test]
+    fn additional_set_rejects_invalid_and_taken_keys() {
+        let mut additional = JdkTable::parse(REMOTE).unwrap().sdks[0]
+            .additional
+            .clone()
+            .unwrap();
+        let attribute = AdditionalValue::Attribute("true".to_string());
+        let element =
+            AdditionalValue::Element(BTreeMap::from([("ID".to_string(), "x".to_string())]));
+
+        for key in ["", "1ST", "-flag", "TWO WORDS", "a<b"] {
+            assert!(additional.set(key, &attribute).is_err(), "{:?}", key);
+        }
+        for key in ["version", "GEMS_BIN_DIR_PATH", "SHOW_SDK_WARNINGS"] {
+            assert!(additional.set(key, &attribute).is_err(), "{:?}", key);
+        }
+        for key in ["VERSION_MANAGER", "PATH_MAPPINGS"] {
+            assert!(additional.set(key, &element).is_err(), "{:?}", key);
+        }
+
+        additional.set("USE_BUNDLER", &attribute).unwrap();
+        additional.set("RUBOCOP-SETTINGS.v2", &element).unwrap();
+        assert!(additional.set("USE_BUNDLER", &attribute).is_err());
+        assert_eq!(
+            additional.attributes.last(),
+            Some(&("USE_BUNDLER".to_string(), "true".to_string()))
+        );
+        assert_eq!(
+            additional.other.last(),
+            Some(&Element::new("RUBOCOP-SETTINGS.v2").attribute("ID", "x"))
+        );
+    }
+}
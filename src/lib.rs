@@ -0,0 +1,5192 @@
+//! Configures RubyMine (or IntelliJ IDEA with the Ruby plugin) for a project: the
+//! interpreter in the IDE's jdk.table.xml, test run configurations, the debugger,
+//! declared scripts and the project's MySQL data sources.
+//!
+//! The library never prints: messages go to an `output::Output`, which the
+//! `rubymine-configurator` binary prints as they come. The binary parses its command
+//! line into `RunOptions` for `run_cli`; without the `cli` feature, the library
+//! builds without clap. Other tools drive the same steps through `api`:
+//! `api::configure_all` does what the CLI does without a command, and
+//! `api::interpreter` gives a `RubyMineInterpreter` whose `jdk_table_xml`, `render`,
+//! `create_interpreter_config` and `create_datasources_xml` produce the XML without
+//! writing anything. `RubyMineInterpreter::discover_config_dirs` finds the IDE's
+//! config dirs.
+
+pub mod api;
+mod asdf;
+mod audit;
+mod backups;
+mod bundle;
+mod completion;
+mod compose;
+pub mod config;
+mod configurator;
+mod custom_configurator;
+mod database_yml;
+mod debugger;
+mod denylist;
+mod devcontainer;
+mod direnv;
+mod docker;
+mod doctor;
+mod env_snapshot;
+mod env_sources;
+mod fs_backend;
+mod gem_probe;
+mod idea_dir;
+mod idea_properties;
+mod installs;
+mod interpreter_specs;
+mod jdbc;
+mod jdk;
+pub mod jdk_table;
+mod lockfile;
+mod markers;
+mod migrate;
+mod mise;
+mod my_cnf;
+mod network;
+mod nix;
+mod notify;
+pub mod output;
+mod output_check;
+mod project_sdk;
+mod prune;
+mod rbs;
+pub mod report;
+mod ruby_engine;
+mod ruby_plugin;
+mod ruby_version_check;
+mod ruby_wrapper;
+mod run_configs;
+mod safe_write;
+mod schema_discovery;
+mod scripts;
+mod settings_sync;
+mod shadowenv;
+mod shared_env;
+mod signals;
+mod simulate_open;
+mod ssh;
+mod state;
+mod subprojects;
+mod terminal;
+#[cfg(test)]
+mod test_support;
+mod verify;
+mod version_manager;
+pub mod warnings;
+mod worktree;
+mod xml_misc;
+mod xml_splice;
+
+pub use completion::{CompletionKind, Shell};
+pub use env_sources::EnvSource;
+pub use jdbc::Driver;
+
+use anyhow::{Context, Result};
+use api::CancellationToken;
+use chrono::prelude::*;
+use config::{Config, LayeredConfig};
+use configurator::Configurator;
+use database_yml::DatabaseConfig;
+use debugger::{AttachConfig, Debugger};
+use denylist::Denylist;
+use dirs::home_dir;
+use docker::{Container, DockerRuby};
+use env_sources::EnvChain;
+use fs_backend::FsBackend;
+use idea_properties::IdeaProperties;
+use jdbc::DriverSetting;
+use jdk::JdkEntry;
+use jdk_table::{Additional, AdditionalValue, JdkTable, Root, Roots, SdkEntry};
+use markers::MarkerStatus;
+use output::{Output, Stream};
+use regex::Regex;
+use roxmltree::Document;
+use ruby_engine::RubyInfo;
+use scripts::Script;
+use serde::{Deserialize, Serialize};
+use ssh::SshHost;
+use state::{Artifact, State};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+use uuid::Uuid;
+use version_manager::VersionManager;
+use xml_splice::Splice;
+use xmlwriter::{Options, XmlWriter};
+
+/// What a run does, as the CLI's flags and command give it. `Default` is a run
+/// without either.
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    /// What to do instead of configuring the whole project
+    pub command: Option<Commands>,
+
+    /// The arguments the CLI was started with, for reports to show how to rerun it;
+    /// `None` when the crate is used as a library
+    pub invocation: Option<Vec<String>>,
+
+    /// Output configuration to stdout instead of writing to RubyMine config
+    pub dry_run: bool,
+
+    /// Subproject opened as a project of its own that shares this project's data
+    /// sources, generated into DIR/.idea from this project's (repeatable)
+    pub datasource_subprojects: Vec<PathBuf>,
+
+    /// Read MySQL host, port and user from the [client] section of ~/.my.cnf when
+    /// MYSQL_* variables aren't set
+    pub my_cnf: bool,
+
+    /// Order in which sources of MYSQL_* settings win, highest first [default:
+    /// process,project,dotenv,my-cnf]
+    pub env_precedence: Vec<EnvSource>,
+
+    /// Show which source a setting such as MYSQL_HOST comes from, and what it overrides
+    pub explain_env: Option<String>,
+
+    /// Fail on any attempt to modify files on disk (for audits and inspection)
+    pub read_only: bool,
+
+    /// Ruby interpreter to use instead of the one found on PATH
+    pub ruby: Option<PathBuf>,
+
+    /// Use the installed ruby matching Gemfile.lock's RUBY VERSION (or the Gemfile's
+    /// `ruby file:`) instead of the one on PATH
+    pub ruby_from_lockfile: bool,
+
+    /// Keep every tool this one runs (mise, homebrew, bundler) from using the network
+    pub offline: bool,
+
+    /// Detect as if started with the KEY=VALUE variables in PATH (e.g. a teammate's or
+    /// CI's exported environment) on top of this shell's
+    pub env_file: Option<PathBuf>,
+
+    /// Keep the ruby path as found instead of resolving symlinks
+    pub no_resolve_symlinks: bool,
+
+    /// Use the current directory as the project instead of walking up to its root
+    pub no_root_detection: bool,
+
+    /// Treat warnings as errors: fail with exit status 3 (undoing what the run wrote)
+    /// on any warning, check that data sources are reachable, and fail when the
+    /// detected ruby doesn't match .ruby-version or the Gemfile
+    pub strict: bool,
+
+    /// Write the interpreter even when its homePath doesn't run as a ruby
+    pub skip_validation: bool,
+
+    /// Configure the OS's ruby (e.g. /usr/bin/ruby) when that's what detection finds
+    pub allow_system_ruby: bool,
+
+    /// Recover from a jdk.table.xml that doesn't parse: restore the newest backup that
+    /// does, or set it aside and start fresh
+    pub repair: Option<Repair>,
+
+    /// Only write to the project's .idea, referring to the interpreter by name, and
+    /// print what to add to the global IDE config
+    pub project_only: bool,
+
+    /// Update this worktree's existing interpreter under its current name instead of
+    /// replacing it with a newly named one
+    pub update_in_place: bool,
+
+    /// Write through a .idea symlink even when it leads outside the project
+    pub follow_idea_symlink: bool,
+
+    /// Record what this run creates under TAG, so `prune --tag TAG` can remove it later
+    pub tag: Option<String>,
+
+    /// Append the --tag to the interpreter name
+    pub tag_in_name: bool,
+
+    /// Apply a [profiles.NAME] section from the config files
+    pub profile: Option<String>,
+
+    /// IDE to configure (IntelliJ IDEA requires the Ruby plugin) [default: rubymine]
+    pub ide: Option<Ide>,
+
+    /// Use the config directory of a specific IDE version (e.g. 2024.1)
+    pub ide_version: Option<String>,
+
+    /// Release channel to use when both stable and EAP installs exist [default: any]
+    pub channel: Option<Channel>,
+
+    /// IDE install location, when it can't be discovered automatically
+    pub rubymine_app: Option<PathBuf>,
+
+    /// Apply the configuration to every installed IDE version
+    pub all_ide_versions: bool,
+
+    /// Configure every worktree of the current git repository (from `git worktree
+    /// list`), skipping ones that already have an interpreter
+    pub from_git_worktrees: bool,
+
+    /// Show a desktop notification with the outcome when the run finishes
+    pub notify: bool,
+
+    /// Which gem executable directory wins when several exist [default: gem]
+    pub gems_bin_dir: Option<GemsBinDir>,
+
+    /// Point the SDK at a generated shim script instead of a custom-configurator list
+    pub shim: bool,
+
+    /// How to handle jdk.table.xml when Settings Sync is enabled [default: warn]
+    pub settings_sync: Option<SettingsSync>,
+
+    /// Where the interpreter goes among the other SDKs in jdk.table.xml [default:
+    /// bottom]
+    pub sdk_position: Option<SdkPosition>,
+
+    /// Tool RubyMine runs ruby through to load the project environment [default: direnv
+    /// for projects with only an .envrc, else shadowenv]
+    pub env_manager: Option<EnvManager>,
+
+    /// Command RubyMine runs ruby through, with {dir} standing for the project dir
+    /// (e.g. "mytool env exec --root {dir} --"); replaces --env-manager
+    pub configurator_cmd: Option<String>,
+
+    /// shadowenv executable to use instead of searching homebrew, PATH and the usual
+    /// install locations
+    pub shadowenv_path: Option<PathBuf>,
+
+    /// Extra argument for `shadowenv exec` in the interpreter, placed before --dir,
+    /// e.g. --silent (repeatable)
+    pub shadowenv_args: Vec<String>,
+
+    /// Run `shadowenv trust` for the project when shadowenv doesn't trust it yet
+    pub trust: bool,
+
+    /// Write the configuration even when shadowenv can't be found, assuming it will be
+    /// on RubyMine's PATH
+    pub allow_missing_shadowenv: bool,
+
+    /// Warn when the ruby lacks the gems RubyMine needs for debugging and tests
+    pub check_gems: bool,
+
+    /// Start the IDE's integrated terminal through the configurator too, so its shell
+    /// has the project environment
+    pub terminal: bool,
+
+    /// Write .idea/rubymine-configurator-report.md summarizing what was configured
+    pub report: bool,
+
+    /// Also send the report to FILE (JSON if it ends in .json, markdown otherwise) or,
+    /// with -, a summary to stdout. Sinks are written even when the run fails;
+    /// repeatable
+    pub report_to: Vec<report::Sink>,
+
+    /// Port the debugger attach configuration connects to [default: 12345 for debug,
+    /// 1234 for ruby-debug-ide]
+    pub debug_port: Option<u16>,
+
+    /// Write a remote SDK running the ruby of this Docker image instead of a local one
+    pub docker_image: Option<String>,
+
+    /// Where the project is mounted in the container or synced on the SSH host
+    /// [default: the compose service's mount of the project, else /app; over SSH the
+    /// same path relative to the home directory]
+    pub docker_project_dir: Option<String>,
+
+    /// Write a remote SDK running the ruby of this docker-compose service instead of a
+    /// local one
+    pub compose_service: Option<String>,
+
+    /// Compose file defining the service [default: compose.yaml or docker-compose.yml
+    /// in the project]
+    pub compose_file: Option<PathBuf>,
+
+    /// Write a remote SDK for the container defined in .devcontainer/devcontainer.json
+    pub devcontainer: bool,
+
+    /// Write a remote SDK running the ruby of a cloud dev environment (e.g. Spin)
+    /// reached over SSH
+    pub ssh_host: Option<String>,
+
+    /// Remove the oldest backups once they take up more than SIZE together (e.g. 200MB)
+    pub max_backups_size: Option<String>,
+
+    /// Also write the variables the project environment sets into run configuration
+    /// templates, for configuration types that don't run through the configurator
+    pub env_snapshot: bool,
+
+    /// Write RUBY, GEM_HOME, GEM_PATH and PATH from the project environment to
+    /// .idea/rubymine-configurator.env, and have test run configurations (through the
+    /// EnvFile plugin) and File Watchers use it
+    pub shared_env: bool,
+
+    /// JDBC driver for data sources [default: mysql.8]
+    pub driver: Option<Driver>,
+
+    /// Driver version to record for data sources, e.g. 8.0.33
+    pub driver_version: Option<String>,
+
+    /// Introspect the schemas the MySQL server has (with the mysql client) instead of a
+    /// fixed list
+    pub discover_schemas: bool,
+
+    /// Only discover schemas whose name starts with PREFIX
+    pub schema_prefix: Option<String>,
+}
+
+impl RunOptions {
+    /// The config keys set on the command line
+    fn config_flags(&self) -> Config {
+        Config {
+            ide: self.ide,
+            ide_version: self.ide_version.clone(),
+            channel: self.channel,
+            rubymine_app: self.rubymine_app.clone(),
+            gems_bin_dir: self.gems_bin_dir,
+            shim: self.shim.then_some(true),
+            settings_sync: self.settings_sync,
+            sdk_position: self.sdk_position,
+            env_manager: self.env_manager,
+            offline: self.offline.then_some(true),
+            env_precedence: (!self.env_precedence.is_empty()).then(|| self.env_precedence.clone()),
+            ssh_host: self.ssh_host.clone(),
+            max_backups_size: self.max_backups_size.clone(),
+            configurator_cmd: self.configurator_cmd.clone(),
+            shadowenv_path: self.shadowenv_path.clone(),
+            shadowenv_candidates: None,
+            shadowenv_args: (!self.shadowenv_args.is_empty()).then(|| self.shadowenv_args.clone()),
+            env_snapshot: self.env_snapshot.then_some(true),
+            shared_env: self.shared_env.then_some(true),
+            driver: self.driver,
+            driver_version: self.driver_version.clone(),
+            datasource_drivers: None,
+            discover_schemas: self.discover_schemas.then_some(true),
+            schema_prefix: self.schema_prefix.clone(),
+            datasource_subprojects: (!self.datasource_subprojects.is_empty())
+                .then(|| self.datasource_subprojects.clone()),
+            project_only: self.project_only.then_some(true),
+            update_in_place: self.update_in_place.then_some(true),
+            protected_sdk_names: None,
+            protected_sdk_homes: None,
+            scripts: None,
+            sdk_additional: None,
+        }
+    }
+
+    /// Settings given on the command line that have no config key, as TOML values
+    /// for print-config to show next to the config
+    fn flag_only_settings(&self) -> Vec<(&'static str, toml::Value)> {
+        let text = |value: &str| toml::Value::String(value.to_string());
+        let path = |path: &PathBuf| text(&path.display().to_string());
+        let mut settings: Vec<(&'static str, toml::Value)> = [
+            ("dry_run", self.dry_run),
+            ("my_cnf", self.my_cnf),
+            ("read_only", self.read_only),
+            ("ruby_from_lockfile", self.ruby_from_lockfile),
+            ("no_resolve_symlinks", self.no_resolve_symlinks),
+            ("no_root_detection", self.no_root_detection),
+            ("strict", self.strict),
+            ("skip_validation", self.skip_validation),
+            ("allow_system_ruby", self.allow_system_ruby),
+            ("follow_idea_symlink", self.follow_idea_symlink),
+            ("tag_in_name", self.tag_in_name),
+            ("all_ide_versions", self.all_ide_versions),
+            ("from_git_worktrees", self.from_git_worktrees),
+            ("notify", self.notify),
+            ("trust", self.trust),
+            ("allow_missing_shadowenv", self.allow_missing_shadowenv),
+            ("check_gems", self.check_gems),
+            ("terminal", self.terminal),
+            ("report", self.report),
+            ("devcontainer", self.devcontainer),
+        ]
+        .into_iter()
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| (name, toml::Value::Boolean(true)))
+        .collect();
+        settings.extend(
+            [
+                ("ruby", self.ruby.as_ref().map(path)),
+                ("env_file", self.env_file.as_ref().map(path)),
+                ("compose_file", self.compose_file.as_ref().map(path)),
+                ("profile", self.profile.as_deref().map(text)),
+                ("tag", self.tag.as_deref().map(text)),
+                ("explain_env", self.explain_env.as_deref().map(text)),
+                ("docker_image", self.docker_image.as_deref().map(text)),
+                (
+                    "docker_project_dir",
+                    self.docker_project_dir.as_deref().map(text),
+                ),
+                ("compose_service", self.compose_service.as_deref().map(text)),
+                (
+                    "repair",
+                    self.repair.map(|repair| {
+                        text(match repair {
+                            Repair::Restore => "restore",
+                            Repair::Fresh => "fresh",
+                        })
+                    }),
+                ),
+                (
+                    "debug_port",
+                    self.debug_port
+                        .map(|port| toml::Value::Integer(port.into())),
+                ),
+            ]
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?))),
+        );
+        if !self.report_to.is_empty() {
+            let sinks = self
+                .report_to
+                .iter()
+                .map(|sink| match sink {
+                    report::Sink::Stdout => text("-"),
+                    report::Sink::File(file) => path(file),
+                })
+                .collect();
+            settings.push(("report_to", toml::Value::Array(sinks)));
+        }
+        settings
+    }
+}
+
+/// The CLI's commands
+#[derive(Clone)]
+pub enum Commands {
+    /// Check the health of the generated configuration without changing anything
+    Doctor {
+        /// Point run configurations using an SDK that no longer exists at the current
+        /// one
+        fix: bool,
+    },
+    /// Print the effective configuration and where each value comes from
+    PrintConfig,
+    /// Replace interpreters and data sources created by older versions of this tool
+    MigrateArtifacts,
+    /// Move the backups next to jdk.table.xml and in .idea into the tool's backup store
+    MigrateBackups,
+    /// Remove every interpreter, data source and run configuration created by runs with --tag
+    Prune,
+    /// Print the changes the tool made to files, oldest first, from its audit log
+    History {
+        /// Only changes to this file (a path, or its end such as jdk.table.xml)
+        file: Option<PathBuf>,
+    },
+    /// Check the jdk.table.xml this run would write the way the IDE reads it, without writing it
+    SimulateOpen,
+    /// Run the written interpreter the way RubyMine will (`ruby -v`, `gem env`)
+    Verify,
+    /// Print a single rendered XML fragment instead of writing any file
+    Render { artifact: RenderArtifact },
+    /// Print the variables the project environment sets as an `<envs>` block to paste into a run configuration
+    Env {
+        /// Print a JSON object instead of XML
+        json: bool,
+        /// Print the whole environment, not just what the project environment sets or
+        /// changes
+        all: bool,
+    },
+    /// Only create the interpreter
+    Interpreter {
+        /// Read the interpreters to create from stdin, one JSON object per line:
+        /// {"dir": ..., "ruby": ...}
+        stdin: bool,
+    },
+    /// Only configure the project's MySQL data sources
+    Datasource {
+        /// database.yml environment to add as a data source (repeatable) [default:
+        /// development and test]
+        envs: Vec<DatasourceEnv>,
+    },
+    /// Only add the attach configuration for the debugger in the Gemfile
+    Debugger,
+    /// Only add the run configurations for the scripts declared in config
+    Scripts,
+    /// Print a shell completion script
+    Completions { shell: completion::Shell },
+    /// Candidates for the completion scripts, one per line
+    Complete {
+        kind: completion::CompletionKind,
+        prefix: String,
+    },
+}
+
+/// Fragments `render` can print
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum RenderArtifact {
+    /// The `<jdk>` entry for jdk.table.xml
+    Interpreter,
+    /// The `<data-source>` entries for dataSources.xml and dataSources.local.xml
+    Datasource,
+    /// The RUBY_ARGS setting and `<envs>` of a Minitest run configuration
+    Runconfig,
+}
+
+/// database.yml environments that become data sources
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum DatasourceEnv {
+    Development,
+    Test,
+    /// Every environment in database.yml
+    All,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    Stable,
+    Eap,
+    #[default]
+    Any,
+}
+
+impl Channel {
+    /// Whether a config dir or install named `name` belongs to this channel
+    fn accepts(self, name: &str) -> bool {
+        let is_eap = name.to_lowercase().contains("eap");
+        match self {
+            Channel::Stable => !is_eap,
+            Channel::Eap => is_eap,
+            Channel::Any => true,
+        }
+    }
+}
+
+/// Candidate sources for the SDK's GEMS_BIN_DIR_PATH
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum GemsBinDir {
+    /// `Gem.bindir` as reported by the interpreter
+    #[default]
+    Gem,
+    /// `Gem.user_dir`/bin, where `gem install --user-install` puts executables
+    User,
+    /// The directory containing the ruby executable
+    Interpreter,
+}
+
+/// Tool that loads the project environment around each ruby invocation
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum EnvManager {
+    /// `shadowenv exec --dir <project> --`
+    #[default]
+    Shadowenv,
+    /// `mise exec -C <project> --`
+    Mise,
+    /// `nix develop <project> --command`, for flake, devenv and shell.nix projects
+    Nix,
+    /// `direnv exec <project>`, for projects with an .envrc
+    Direnv,
+}
+
+impl EnvManager {
+    fn name(self) -> &'static str {
+        match self {
+            EnvManager::Shadowenv => "shadowenv",
+            EnvManager::Mise => "mise",
+            EnvManager::Nix => "nix",
+            EnvManager::Direnv => "direnv",
+        }
+    }
+
+    /// The env manager the project is set up for: shadowenv when it has a
+    /// .shadowenv.d, direnv when it only has an .envrc
+    fn detect(project_dir: &Path) -> Self {
+        if !shadowenv::has_shadowenv_dir(project_dir) && direnv::has_envrc(project_dir) {
+            EnvManager::Direnv
+        } else {
+            EnvManager::Shadowenv
+        }
+    }
+}
+
+/// Where the interpreter is written when Backup & Sync is enabled
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum SettingsSync {
+    /// Write options/jdk.table.xml only, and warn that sync may overwrite it
+    #[default]
+    Warn,
+    /// Write both options/ and the settingsSync copy
+    Also,
+    /// Write only the settingsSync copy and let the IDE apply it
+    Instead,
+}
+
+/// Where the interpreter is inserted among the SDKs in jdk.table.xml. Tools that
+/// pick the first Ruby SDK depend on it.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum SdkPosition {
+    /// After every other SDK
+    #[default]
+    Bottom,
+    /// Before every other SDK
+    Top,
+    /// Among the other SDKs, sorted by name
+    Alphabetical,
+    /// Where the entry it replaces was, else at the bottom
+    Keep,
+}
+
+/// What to do with a jdk.table.xml that doesn't parse, e.g. after a truncated write
+/// or a merge conflict
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Repair {
+    /// Start over from the newest backup that parses
+    Restore,
+    /// Set the broken file aside and write one with only this interpreter
+    Fresh,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Ide {
+    #[default]
+    Rubymine,
+    Idea,
+}
+
+impl Ide {
+    fn display_name(self) -> &'static str {
+        match self {
+            Ide::Rubymine => "RubyMine",
+            Ide::Idea => "IntelliJ IDEA",
+        }
+    }
+
+    /// Prefix of the versioned config directories, e.g. RubyMine2024.1 or IntelliJIdea2024.1
+    fn config_dir_prefix(self) -> &'static str {
+        match self {
+            Ide::Rubymine => "RubyMine",
+            Ide::Idea => "IntelliJIdea",
+        }
+    }
+
+    /// macOS application bundle names
+    fn app_names(self) -> &'static [&'static str] {
+        match self {
+            Ide::Rubymine => &["RubyMine.app", "RubyMine EAP.app"],
+            Ide::Idea => &[
+                "IntelliJ IDEA.app",
+                "IntelliJ IDEA Ultimate.app",
+                "IntelliJ IDEA EAP.app",
+                "IntelliJ IDEA Ultimate EAP.app",
+            ],
+        }
+    }
+
+    /// Environment variable that points the IDE at a custom idea.properties
+    fn properties_env_var(self) -> &'static str {
+        match self {
+            Ide::Rubymine => "RUBYMINE_PROPERTIES",
+            Ide::Idea => "IDEA_PROPERTIES",
+        }
+    }
+
+    /// Per-user directory in $HOME holding an idea.properties override
+    fn user_properties_dir(self) -> &'static str {
+        match self {
+            Ide::Rubymine => ".rubymine",
+            Ide::Idea => ".intellijidea",
+        }
+    }
+
+    /// Lowercase prefixes of Linux tarball and Toolbox install directories
+    fn linux_install_prefixes(self) -> &'static [&'static str] {
+        match self {
+            Ide::Rubymine => &["rubymine"],
+            Ide::Idea => &["idea-iu", "idea-u", "intellij-idea-ultimate"],
+        }
+    }
+
+    fn snap_name(self) -> &'static str {
+        match self {
+            Ide::Rubymine => "rubymine",
+            Ide::Idea => "intellij-idea-ultimate",
+        }
+    }
+
+    fn flatpak_id(self) -> &'static str {
+        match self {
+            Ide::Rubymine => "com.jetbrains.RubyMine",
+            Ide::Idea => "com.jetbrains.IntelliJ-IDEA-Ultimate",
+        }
+    }
+
+    /// Prefix of the per-version install directories under Program Files\JetBrains
+    fn windows_install_prefix(self) -> &'static str {
+        match self {
+            Ide::Rubymine => "RubyMine",
+            Ide::Idea => "IntelliJ IDEA",
+        }
+    }
+}
+
+/// Connection settings from the environment: MYSQL_* or PG* variables
+#[derive(Debug)]
+struct ServerConfig {
+    host: String,
+    port: String,
+    user: String,
+    password: String,
+}
+
+/// A data source for the Database tool window, from `data_sources`
+pub struct DataSource {
+    name: String,
+    host: String,
+    port: String,
+    user: String,
+    password: String,
+    /// Schemas to introspect
+    schemas: Vec<String>,
+    driver: Driver,
+    driver_version: String,
+}
+
+/// A project's interpreter as the IDE will run it, along with everything else the
+/// tool configures for the project
+pub struct RubyMineInterpreter {
+    ruby_wrapper_path: String,
+    ruby_interpreter_path: String,
+    ruby: RubyInfo,
+    gems_bin_dir: String,
+    project_env: HashMap<String, String>,
+    /// Write the project environment's delta into run configuration templates
+    env_snapshot: bool,
+    /// Write the env file IDE-spawned processes share
+    shared_env: bool,
+    /// Driver for data sources without an entry in `datasource_drivers`
+    driver: DriverSetting,
+    datasource_drivers: BTreeMap<String, DriverSetting>,
+    /// Ask reachable MySQL servers for their schemas, and only those starting with this
+    discover_schemas: Option<String>,
+    /// Never go online, not even to the project's database servers
+    offline: bool,
+    version_manager: VersionManager,
+    interpreter_name: String,
+    current_dir: String,
+    project_name: String,
+    /// Subproject dirs sharing the project's data sources, as configured
+    datasource_subprojects: Vec<PathBuf>,
+    my_cnf: bool,
+    env_precedence: Vec<EnvSource>,
+    check_gems: bool,
+    debug_port: Option<u16>,
+    dry_run: bool,
+    ide: Ide,
+    ide_version: Option<String>,
+    channel: Channel,
+    rubymine_app: Option<PathBuf>,
+    config_dir_override: Option<PathBuf>,
+    shim: bool,
+    settings_sync: SettingsSync,
+    sdk_position: SdkPosition,
+    env_manager: EnvManager,
+    /// A bespoke configurator command replacing the env manager's, `{dir}` unexpanded
+    configurator_cmd: Option<Vec<String>>,
+    shadowenv_path: String,
+    /// Extra `shadowenv exec` arguments, between `exec` and `--dir`
+    shadowenv_args: Vec<String>,
+    /// Why the interpreter can't be written with `shadowenv_path`, and what to do about it
+    shadowenv_problem: Option<String>,
+    /// Also replace this project's interpreters that were named by an older release
+    replace_legacy: bool,
+    tag: Option<String>,
+    strict: bool,
+    /// Write the interpreter without checking that its homePath runs
+    skip_validation: bool,
+    /// Configure the OS's ruby when detection ends up there
+    allow_system_ruby: bool,
+    /// How to recover from a jdk.table.xml that doesn't parse
+    repair: Option<Repair>,
+    /// Leave the global IDE config alone, and only refer to the interpreter from .idea
+    project_only: bool,
+    /// Keep the name of the existing interpreter the new one replaces
+    update_in_place: bool,
+    /// SDKs never replaced or removed
+    protected: Denylist,
+    /// Run configurations declared in config
+    scripts: Vec<Script>,
+    /// Passed through to the SDK's `<additional>`
+    sdk_additional: BTreeMap<String, AdditionalValue>,
+    /// Disk budget for backups, in bytes
+    max_backups_size: Option<u64>,
+    /// Set when the SDK runs ruby in a container instead of on this machine
+    docker: Option<DockerRuby>,
+    fs: FsBackend,
+    /// Where messages go instead of being printed
+    out: Output,
+}
+
+/// What run configurations are added to when there's no workspace.xml yet
+const EMPTY_WORKSPACE: &str = r#"<project version="4"></project>"#;
+
+impl RubyMineInterpreter {
+    /// Detects the ruby and environment of the project in `project_dir`, with the
+    /// CLI's `args` and the effective `config`, sending messages to `out`
+    pub fn new(
+        args: &RunOptions,
+        config: &Config,
+        project_dir: &Path,
+        out: &Output,
+    ) -> Result<Self> {
+        let current_dir = project_dir.to_string_lossy().to_string();
+        let env_manager = config
+            .env_manager
+            .unwrap_or_else(|| EnvManager::detect(project_dir));
+        let docker = match Self::container(args, config, Path::new(&current_dir), out)? {
+            Some(_) if config.shim.unwrap_or_default() => {
+                anyhow::bail!("--shim can't be combined with a container interpreter")
+            }
+            Some((container, container_dir)) => Some(DockerRuby::probe(
+                container,
+                &current_dir,
+                &container_dir,
+                config.offline.unwrap_or_default(),
+            )?),
+            None => None,
+        };
+        let (ruby_wrapper_path, ruby_interpreter_path, ruby) = match &docker {
+            Some(docker) => (
+                docker.container.kind().to_string(),
+                docker.ruby_path.clone(),
+                docker.ruby.clone(),
+            ),
+            None => Self::detect_ruby_environment(args.ruby.as_deref(), project_dir, env_manager)?,
+        };
+        let project_name = Self::detect_project_name(&current_dir);
+
+        let mut interpreter = Self {
+            ruby_wrapper_path,
+            ruby_interpreter_path,
+            ruby,
+            gems_bin_dir: String::new(),
+            project_env: HashMap::new(),
+            env_snapshot: config.env_snapshot.unwrap_or_default(),
+            shared_env: config.shared_env.unwrap_or_default(),
+            driver: DriverSetting {
+                driver: config.driver,
+                version: config.driver_version.clone(),
+            },
+            datasource_drivers: config.datasource_drivers.clone().unwrap_or_default(),
+            discover_schemas: config
+                .discover_schemas
+                .unwrap_or_default()
+                .then(|| config.schema_prefix.clone().unwrap_or_default()),
+            offline: config.offline.unwrap_or_default(),
+            version_manager: VersionManager::System,
+            interpreter_name: String::new(),
+            current_dir,
+            project_name,
+            datasource_subprojects: config.datasource_subprojects.clone().unwrap_or_default(),
+            my_cnf: args.my_cnf,
+            env_precedence: config
+                .env_precedence
+                .clone()
+                .unwrap_or(env_sources::DEFAULT_PRECEDENCE.to_vec()),
+            check_gems: args.check_gems,
+            debug_port: args.debug_port,
+            dry_run: args.dry_run,
+            ide: config.ide.unwrap_or_default(),
+            ide_version: config.ide_version.clone(),
+            channel: config.channel.unwrap_or_default(),
+            rubymine_app: config.rubymine_app.clone(),
+            config_dir_override: None,
+            shim: config.shim.unwrap_or_default(),
+            settings_sync: config.settings_sync.unwrap_or_default(),
+            sdk_position: config.sdk_position.unwrap_or_default(),
+            env_manager,
+            configurator_cmd: config
+                .configurator_cmd
+                .as_deref()
+                .map(custom_configurator::parse)
+                .transpose()
+                .context("Invalid configurator_cmd")?
+                .map(|mut command| {
+                    // RubyMine doesn't run it with the shell's PATH
+                    if !command[0].contains('/') {
+                        if let Ok(Some(program)) = Self::which(&command[0]) {
+                            command[0] = program;
+                        }
+                    }
+                    command
+                }),
+            shadowenv_path: "shadowenv".to_string(),
+            shadowenv_args: config.shadowenv_args.clone().unwrap_or_default(),
+            shadowenv_problem: None,
+            replace_legacy: false,
+            tag: args.tag.clone(),
+            strict: args.strict,
+            skip_validation: args.skip_validation,
+            // A ruby given with --ruby was picked on purpose
+            allow_system_ruby: args.allow_system_ruby || args.ruby.is_some(),
+            repair: args.repair,
+            project_only: config.project_only.unwrap_or_default(),
+            update_in_place: config.update_in_place.unwrap_or_default(),
+            protected: Denylist::from_config(config)?,
+            scripts: config.scripts.clone().unwrap_or_default(),
+            sdk_additional: config.sdk_additional.clone().unwrap_or_default(),
+            max_backups_size: config
+                .max_backups_size
+                .as_deref()
+                .map(backups::parse_size)
+                .transpose()
+                .context("Invalid max_backups_size")?,
+            docker,
+            fs: FsBackend::new(args.read_only, out),
+            out: out.clone(),
+        };
+        interpreter.guard_idea_symlinks(args.follow_idea_symlink);
+        if interpreter.project_only && interpreter.shim {
+            anyhow::bail!(
+                "The shim lives outside the project, so it can't be used with --project-only"
+            );
+        }
+
+        // A container brings its own ruby and environment, so none of this applies to it
+        if interpreter.docker.is_none() {
+            let project_dir = Path::new(&interpreter.current_dir);
+            // A custom configurator command replaces the env manager entirely
+            let env_manager = Some(env_manager).filter(|_| interpreter.configurator_cmd.is_none());
+            if env_manager == Some(EnvManager::Shadowenv) {
+                let candidates = config.shadowenv_candidates.clone().unwrap_or_else(|| {
+                    shadowenv::DEFAULT_CANDIDATES
+                        .iter()
+                        .map(|candidate| candidate.to_string())
+                        .collect()
+                });
+                let (path, missing) =
+                    Self::resolve_shadowenv_path(config.shadowenv_path.as_deref(), &candidates);
+                interpreter.shadowenv_path = path;
+                match missing {
+                    Some(problem) if args.allow_missing_shadowenv => warnings::warn(
+                        out,
+                        format!(
+                            "{}; RubyMine will need it at {}",
+                            problem, interpreter.shadowenv_path
+                        ),
+                    ),
+                    Some(problem) => {
+                        interpreter.shadowenv_problem = Some(format!(
+                        "{}; install it, pass --shadowenv-path, or pass --allow-missing-shadowenv",
+                        problem
+                    ))
+                    }
+                    None => {
+                        interpreter.shadowenv_problem = interpreter.outdated_shadowenv();
+                        interpreter.check_shadowenv_trust(args.trust, args.read_only)?;
+                    }
+                }
+            }
+            if env_manager == Some(EnvManager::Nix) && !nix::has_dev_shell(project_dir) {
+                anyhow::bail!(
+                    "--env-manager nix needs a flake.nix, shell.nix or default.nix in the project"
+                );
+            }
+            if env_manager == Some(EnvManager::Shadowenv)
+                && nix::has_dev_shell(project_dir)
+                && !shadowenv::has_shadowenv_dir(project_dir)
+            {
+                out.status("This project has a Nix dev shell; rerun with --env-manager nix to use its ruby");
+            }
+            // These depend on the configurator command, so they're resolved once the rest is known
+            if args.ruby.is_none() {
+                if let Some((ruby_path, ruby)) = interpreter.resolve_ruby_in_project_env() {
+                    interpreter.ruby_interpreter_path = ruby_path;
+                    interpreter.ruby = ruby;
+                }
+            }
+            // Symlinks into e.g. a homebrew Cellar break when the ruby is upgraded
+            if !args.no_resolve_symlinks {
+                interpreter.ruby_interpreter_path =
+                    Self::canonical_path(&interpreter.ruby_interpreter_path);
+            }
+            interpreter.ruby_interpreter_path = interpreter
+                .ruby
+                .engine
+                .home_path(&interpreter.ruby_interpreter_path);
+            interpreter.project_env = interpreter.capture_project_env();
+            if interpreter.env_snapshot {
+                interpreter.check_env_snapshot();
+            }
+            interpreter.version_manager = VersionManager::detect(
+                Path::new(&interpreter.ruby_interpreter_path),
+                &interpreter.project_env,
+                Path::new(&interpreter.current_dir),
+            );
+        }
+        interpreter.interpreter_name = Self::generate_interpreter_name(
+            &interpreter.current_dir,
+            &interpreter.project_name,
+            &interpreter.ruby,
+            interpreter.version_manager.gemset(),
+            &match (&interpreter.docker, &interpreter.configurator_cmd) {
+                (Some(docker), _) => docker.container.kind().to_string(),
+                (None, Some(command)) => custom_configurator::name(command),
+                (None, None) => env_manager.name().to_string(),
+            },
+        );
+        if let Some(tag) = args.tag.as_deref().filter(|_| args.tag_in_name) {
+            interpreter.interpreter_name = format!("{} [{}]", interpreter.interpreter_name, tag);
+        }
+        interpreter.gems_bin_dir = match &interpreter.docker {
+            Some(docker) => docker.gems_bin_dir.clone(),
+            None => interpreter.detect_gems_bin_dir(config.gems_bin_dir.unwrap_or_default()),
+        };
+        if interpreter.update_in_place && !interpreter.project_only {
+            if let Some(existing) = interpreter.in_place_target() {
+                interpreter.interpreter_name = existing.name;
+                interpreter.sdk_position = SdkPosition::Keep;
+            }
+        }
+
+        Ok(interpreter)
+    }
+
+    /// The container from --docker-image, --compose-service or --devcontainer (or the
+    /// SSH host of a cloud dev environment), and where the project is mounted in it
+    fn container(
+        args: &RunOptions,
+        config: &Config,
+        project_dir: &Path,
+        out: &Output,
+    ) -> Result<Option<(Container, String)>> {
+        let default_dir = || {
+            args.docker_project_dir
+                .clone()
+                .unwrap_or("/app".to_string())
+        };
+        if let Some(image) = &args.docker_image {
+            return Ok(Some((Container::Image(image.clone()), default_dir())));
+        }
+        let devcontainer_file = devcontainer::find(project_dir);
+        if args.devcontainer {
+            let file =
+                devcontainer_file.context("No .devcontainer/devcontainer.json in the project")?;
+            let (container, workspace_folder) = devcontainer::container(
+                &file,
+                project_dir,
+                config.offline.unwrap_or_default(),
+                out,
+            )?;
+            let container_dir = args.docker_project_dir.clone().unwrap_or(workspace_folder);
+            return Ok(Some((container, container_dir)));
+        }
+        // Can also come from a config file or profile, for machines that always use one
+        let ssh_host = config
+            .ssh_host
+            .as_ref()
+            .filter(|_| args.ruby.is_none() && args.compose_service.is_none());
+        if let Some(destination) = ssh_host {
+            let host = SshHost::resolve(destination)?;
+            let remote_dir = args
+                .docker_project_dir
+                .clone()
+                .unwrap_or_else(|| SshHost::default_project_dir(project_dir));
+            return Ok(Some((Container::Ssh(host), remote_dir)));
+        }
+        let Some(service) = &args.compose_service else {
+            if args.ruby.is_none() && devcontainer_file.is_some() {
+                out.status("This project has a devcontainer; rerun with --devcontainer to use its ruby instead");
+            }
+            return Ok(None);
+        };
+
+        let file = match &args.compose_file {
+            Some(file) => project_dir.join(file),
+            None => compose::find_file(project_dir)
+                .context("No compose.yaml or docker-compose.yml in the project")?,
+        };
+        let mount = compose::project_mount(&file, service, project_dir)?;
+        let container_dir = args
+            .docker_project_dir
+            .clone()
+            .or(mount)
+            .unwrap_or_else(default_dir);
+        let container = Container::Compose {
+            file,
+            service: service.clone(),
+        };
+        Ok(Some((container, container_dir)))
+    }
+
+    fn create_interpreter(&self) -> Result<()> {
+        // Every ruby invocation in the IDE would fail with a configurator that doesn't exist
+        if let Some(problem) = &self.shadowenv_problem {
+            anyhow::bail!("{}", problem);
+        }
+        self.check_system_ruby()?;
+        self.check_ruby_version()?;
+        if self.check_gems {
+            self.warn_about_missing_gems();
+        }
+        if self.project_only {
+            self.validate_interpreter()?;
+            return self.refer_to_interpreter();
+        }
+        let config_files = self.interpreter_config_files()?;
+        self.warn_about_settings_sync()?;
+        // A file that doesn't parse is dealt with when it's written
+        if !self.replace_legacy && !self.legacy_interpreters().unwrap_or_default().is_empty() {
+            warnings::warn(&self.out,
+                "this project has interpreters from an older version of this tool; run `migrate-artifacts` to replace them"
+            );
+        }
+
+        if self.dry_run {
+            for config_file in &config_files {
+                self.out.line(format!(
+                    "# Configuration file location: {}",
+                    config_file.display()
+                ));
+            }
+            self.out
+                .line(format!("# Interpreter name: {}", self.interpreter_name));
+            self.out
+                .line(format!("# Ruby wrapper: {}", self.ruby_wrapper_path));
+            self.out.line(format!(
+                "# Ruby interpreter: {}",
+                self.ruby_interpreter_path
+            ));
+            self.out
+                .line(format!("# Ruby version: {}", self.ruby.sdk_version()));
+            self.out
+                .line(format!("# Gems bin dir: {}", self.gems_bin_dir));
+            self.out
+                .line(format!("# Current directory: {}", self.current_dir));
+            self.out
+                .line(format!("# Project name: {}", self.project_name));
+            if self.shim {
+                self.out
+                    .line(format!("# Shim: {}", self.shim_path()?.display()));
+                self.out.line("#");
+                for line in self.shim_content().lines() {
+                    self.out.line(format!("#   {}", line));
+                }
+            }
+            self.out.line(format!("# {}", "=".repeat(50)));
+            self.out.line("");
+        } else {
+            self.ensure_rubymine_config_exists(&config_files)?;
+            if self.shim {
+                self.write_shim()?;
+            }
+            self.out.line(format!(
+                "Creating RubyMine interpreter: {}",
+                self.interpreter_name
+            ));
+            self.out
+                .line(format!("Ruby wrapper: {}", self.ruby_wrapper_path));
+            self.out
+                .line(format!("Ruby interpreter: {}", self.ruby_interpreter_path));
+            self.out
+                .line(format!("Ruby version: {}", self.ruby.sdk_version()));
+            self.out
+                .line(format!("Gems bin dir: {}", self.gems_bin_dir));
+            self.out
+                .line(format!("Current directory: {}", self.current_dir));
+            for config_file in &config_files {
+                self.out
+                    .line(format!("Config file: {}", config_file.display()));
+            }
+        }
+
+        self.validate_interpreter()?;
+        let mut written = false;
+        for config_file in &config_files {
+            if self.dry_run {
+                let config_content = self.create_interpreter_config(config_file)?;
+                self.check_opens(config_file, &config_content)?;
+                if self.is_interpreter_unchanged(config_file, &config_content) {
+                    self.out
+                        .line(format!("# Already up to date: {}", config_file.display()));
+                } else {
+                    self.out.line(config_content);
+                }
+                continue;
+            }
+            let mut file_written = false;
+            safe_write::read_modify_write(
+                config_file,
+                || {
+                    let config_content = self.create_interpreter_config(config_file)?;
+                    self.check_opens(config_file, &config_content)?;
+                    if self.is_interpreter_unchanged(config_file, &config_content) {
+                        self.out
+                            .line(format!("Already up to date: {}", config_file.display()));
+                        return Ok(None);
+                    }
+                    Ok(Some(config_content))
+                },
+                |config_content| {
+                    self.set_aside_if_malformed(config_file)?;
+                    self.write_config_file(config_file, config_content)?;
+                    file_written = true;
+                    Ok(())
+                },
+                &self.out,
+            )?;
+            written |= file_written;
+            // Also when up to date, for entries created before the state recorded them all
+            if let Ok(content) = fs::read_to_string(config_file) {
+                self.record_artifacts(
+                    "interpreter",
+                    config_file,
+                    &[self.interpreter_name.as_str()],
+                    &content,
+                )?;
+            }
+        }
+
+        if !self.dry_run {
+            if self.shim {
+                self.remove_orphaned_shims()?;
+            }
+            if written {
+                self.out.line("Interpreter created successfully!");
+                self.out.line("Restart RubyMine to see the new interpreter in Project Settings > Project Interpreter");
+            } else {
+                self.out.line("Interpreter already up to date");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds the ruby wrapper on PATH and the interpreter behind it for `project_dir`,
+    /// or uses `explicit_ruby` as both when given
+    fn detect_ruby_environment(
+        explicit_ruby: Option<&Path>,
+        project_dir: &Path,
+        env_manager: EnvManager,
+    ) -> Result<(String, String, RubyInfo)> {
+        let (ruby_wrapper_path, ruby_interpreter_path) = match explicit_ruby {
+            Some(ruby) => {
+                if !ruby.is_file() {
+                    anyhow::bail!("Ruby interpreter not found: {}", ruby.display());
+                }
+                let ruby = ruby.to_string_lossy().to_string();
+                (ruby.clone(), ruby)
+            }
+            None => {
+                let ruby_wrapper_path =
+                    Self::which("ruby")?.context("Could not find ruby in PATH")?;
+                let ruby_interpreter_path =
+                    Self::discover_actual_ruby_path(&ruby_wrapper_path, project_dir, env_manager)?;
+                (ruby_wrapper_path, ruby_interpreter_path)
+            }
+        };
+
+        // Version manager shims pick the ruby from the directory they run in
+        let output = Command::new(&ruby_wrapper_path)
+            .arg("-e")
+            .arg(ruby_engine::PROBE)
+            .current_dir(project_dir)
+            .output()
+            .context("Failed to get Ruby version")?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let ruby = RubyInfo::parse(stdout.lines()).context("Could not determine Ruby version")?;
+
+        Ok((ruby_wrapper_path, ruby_interpreter_path, ruby))
+    }
+
+    /// Path and version of the ruby RubyMine will actually run, asked from inside the
+    /// project environment (`shadowenv exec ... -- ruby -e ...`). `None` when the
+    /// configurator isn't available, in which case the wrapper heuristics stand.
+    fn resolve_ruby_in_project_env(&self) -> Option<(String, RubyInfo)> {
+        let options = self.configurator_options();
+        let output = Command::new(&options[0])
+            .args(&options[1..])
+            .args([
+                "ruby",
+                "-e",
+                &format!("puts RbConfig.ruby; {}", ruby_engine::PROBE),
+            ])
+            .current_dir(&self.current_dir)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let mut lines = stdout.lines();
+        let ruby_path = lines
+            .next()
+            .map(|path| path.trim().to_string())
+            .filter(|path| Path::new(path).is_file())?;
+        let ruby = RubyInfo::parse(lines)?;
+        Some((ruby_path, ruby))
+    }
+
+    /// Environment of the project as the IDE will see it (`shadowenv exec ... -- env`),
+    /// or an empty map when it can't be captured
+    fn capture_project_env(&self) -> HashMap<String, String> {
+        let options = self.configurator_options();
+        let Ok(output) = Command::new(&options[0])
+            .args(&options[1..])
+            .arg("env")
+            .current_dir(&self.current_dir)
+            .output()
+        else {
+            return HashMap::new();
+        };
+        if !output.status.success() {
+            return HashMap::new();
+        }
+
+        let key_re = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .filter(|(key, _)| key_re.is_match(key) && !network::is_offline_setting(key))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// The snapshot would silently be empty or partial in these cases
+    fn check_env_snapshot(&self) {
+        let clean: HashMap<String, String> = env::vars().collect();
+        if self.project_env.is_empty() {
+            warnings::warn(
+                &self.out,
+                "couldn't capture the project environment, so --env-snapshot has nothing to write",
+            );
+        } else if env_snapshot::already_activated(&clean) {
+            warnings::warn(&self.out,
+                "shadowenv is already active in this shell, so --env-snapshot only sees what differs from it; run from a shell outside the project for a complete snapshot"
+            );
+        }
+    }
+
+    /// Variables a run configuration gets: test configurations always, and with
+    /// --env-snapshot every template too
+    fn run_config_env(&self, configuration: &roxmltree::Node) -> BTreeMap<String, String> {
+        let is_test = configuration.attribute("type") == Some("TestUnitRunConfigurationType");
+        let is_template = configuration.attribute("default") == Some("true");
+        if is_test || (self.env_snapshot && is_template) {
+            self.test_env()
+        } else {
+            BTreeMap::new()
+        }
+    }
+
+    /// Test configurations, and the templates every new run configuration starts from
+    fn is_test_or_template(configuration: &roxmltree::Node) -> bool {
+        configuration.attribute("type") == Some("TestUnitRunConfigurationType")
+            || configuration.attribute("default") == Some("true")
+    }
+
+    fn shared_env_file(&self) -> PathBuf {
+        self.idea_dir().join(shared_env::FILE_NAME)
+    }
+
+    /// Writes the env file IDE-spawned processes share, and sets its variables in
+    /// File Watchers, which can't load env files
+    fn write_shared_env(&self) -> Result<()> {
+        if self.docker.is_some() {
+            warnings::warn(
+                &self.out,
+                "--shared-env doesn't apply to an interpreter in a container",
+            );
+            return Ok(());
+        }
+        if self.project_env.is_empty() {
+            warnings::warn(
+                &self.out,
+                format!(
+                    "couldn't capture the project environment, so {} only has RUBY",
+                    shared_env::FILE_NAME
+                ),
+            );
+        }
+        let variables = shared_env::variables(&self.ruby_interpreter_path, &self.project_env);
+        let env_file = self.shared_env_file();
+        let content = shared_env::render(&variables);
+        let watchers_file = self.idea_dir().join("watcherTasks.xml");
+        let set_watcher_envs = |xml: Option<&str>| match xml {
+            Some(xml) => self.splice_watcher_envs(xml, &variables),
+            None => Ok(None),
+        };
+
+        if self.dry_run {
+            self.out
+                .line(format!("# Shared environment: {}", env_file.display()));
+            self.out.write(Stream::Stdout, &content);
+            let watchers = set_watcher_envs(fs::read_to_string(&watchers_file).ok().as_deref())?;
+            if let Some(watchers) = &watchers {
+                self.out
+                    .line(format!("# File Watchers: {}", watchers_file.display()));
+                self.out.line(watchers);
+            }
+            self.out.line(format!("# {}", "=".repeat(50)));
+            self.out.line("");
+            return Ok(());
+        }
+
+        if fs::read_to_string(&env_file).ok().as_deref() != Some(content.as_str()) {
+            self.fs.create_dir_all(&self.idea_dir())?;
+            self.fs.write(&env_file, &content)?;
+            self.out.line(format!(
+                "Shared environment written: {}",
+                env_file.display()
+            ));
+        }
+        if self
+            .update_config_file(&watchers_file, set_watcher_envs)?
+            .is_some()
+        {
+            self.out.line(format!(
+                "File Watchers updated: {}",
+                watchers_file.display()
+            ));
+        }
+        Ok(())
+    }
+
+    /// watcherTasks.xml with `variables` set in every File Watcher's environment, or
+    /// `None` when they all have them already
+    fn splice_watcher_envs(
+        &self,
+        xml_content: &str,
+        variables: &BTreeMap<String, String>,
+    ) -> Result<Option<String>> {
+        let doc = Document::parse(xml_content)?;
+        let mut splice = Splice::new(xml_content);
+        let mut updated = false;
+        for task in doc
+            .descendants()
+            .filter(|node| node.has_tag_name("TaskOptions"))
+        {
+            let existing = task.children().find(|child| child.has_tag_name("envs"));
+            let up_to_date = existing.is_some_and(|envs| {
+                variables.iter().all(|(name, value)| {
+                    envs.children().any(|env| {
+                        env.attribute("name") == Some(name.as_str())
+                            && env.attribute("value") == Some(value.as_str())
+                    })
+                })
+            });
+            if up_to_date {
+                continue;
+            }
+            let block = xml_splice::fragment(|writer| {
+                Self::write_merged_envs(writer, existing.as_ref(), variables);
+                Ok(())
+            })?;
+            match existing {
+                Some(existing) => splice.replace(&existing, &block),
+                None => splice.append_child(&task, &block),
+            }
+            updated = true;
+        }
+        Ok(updated.then(|| splice.finish()))
+    }
+
+    /// The project environment's delta with --env-snapshot, else just the bundler settings
+    fn test_env(&self) -> BTreeMap<String, String> {
+        if self.env_snapshot {
+            let clean: HashMap<String, String> = env::vars().collect();
+            env_snapshot::delta(&self.project_env, &clean)
+        } else {
+            self.bundler_env()
+        }
+    }
+
+    /// Gem and bundler settings (GEM_HOME, BUNDLE_PATH, ...) from the project environment
+    fn bundler_env(&self) -> BTreeMap<String, String> {
+        self.project_env
+            .iter()
+            .filter(|(key, _)| key.starts_with("GEM_") || key.starts_with("BUNDLE_"))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    /// bin directories of gems installed under GEM_HOME or a bundler install path
+    /// (BUNDLE_PATH from the environment or .bundle/config, e.g. vendor/bundle)
+    fn bundler_bin_dirs(&self) -> Vec<String> {
+        let mut dirs = Vec::new();
+        if let Some(gem_home) = self.project_env.get("GEM_HOME") {
+            dirs.push(Path::new(gem_home).join("bin"));
+        }
+
+        let bundle_path = self.project_env.get("BUNDLE_PATH").cloned().or_else(|| {
+            let config = fs::read_to_string(Path::new(&self.current_dir).join(".bundle/config"));
+            config.ok()?.lines().find_map(|line| {
+                let value = line.trim().strip_prefix("BUNDLE_PATH:")?;
+                Some(value.trim().trim_matches(['"', '\'']).to_string())
+            })
+        });
+        if let Some(bundle_path) = bundle_path {
+            // Bundler installs into <path>/<engine>/<abi version>, e.g. vendor/bundle/ruby/3.3.0
+            let bundle_path = Path::new(&self.current_dir).join(bundle_path);
+            let mut abi_dirs: Vec<PathBuf> = fs::read_dir(bundle_path.join("ruby"))
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|entry| entry.path().join("bin"))
+                .collect();
+            abi_dirs.sort();
+            dirs.extend(abi_dirs.into_iter().rev());
+        }
+
+        dirs.into_iter()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .collect()
+    }
+
+    /// Picks the gem executable directory, asking the interpreter (through the wrapper, so the
+    /// project environment applies) where gems put their binaries
+    fn detect_gems_bin_dir(&self, preference: GemsBinDir) -> String {
+        let interpreter_dir = Path::new(&self.ruby_interpreter_path)
+            .parent()
+            .map(|dir| dir.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut gem_dir = None;
+        let mut user_dir = None;
+        if let Ok(output) = Command::new(&self.ruby_wrapper_path)
+            .arg("-e")
+            .arg("puts Gem.bindir; puts File.join(Gem.user_dir, 'bin')")
+            .current_dir(&self.current_dir)
+            .output()
+        {
+            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+            let mut lines = stdout.lines().map(|line| line.trim().to_string());
+            gem_dir = lines.next().filter(|dir| !dir.is_empty());
+            user_dir = lines.next().filter(|dir| !dir.is_empty());
+        }
+
+        // GEM_HOME and bundler paths from the project environment are where gems really
+        // go, even when this process doesn't run inside that environment
+        let mut gem_dirs = Vec::from_iter(
+            self.version_manager
+                .gemset_bin_dir()
+                .map(|dir| dir.to_string_lossy().to_string()),
+        );
+        gem_dirs.extend(self.bundler_bin_dirs());
+        gem_dirs.extend(gem_dir);
+        gem_dirs.extend(
+            self.ruby
+                .engine
+                .bundled_gems_bin_dir(&self.ruby_interpreter_path)
+                .map(|dir| dir.to_string_lossy().to_string()),
+        );
+        if let Some(install_dir) = asdf::install_dir(Path::new(&self.ruby_interpreter_path)) {
+            gem_dirs.push(install_dir.join("bin").to_string_lossy().to_string());
+        }
+
+        let candidates = match preference {
+            GemsBinDir::Gem => [
+                gem_dirs,
+                Vec::from_iter(user_dir),
+                vec![interpreter_dir.clone()],
+            ],
+            GemsBinDir::User => [
+                Vec::from_iter(user_dir),
+                gem_dirs,
+                vec![interpreter_dir.clone()],
+            ],
+            GemsBinDir::Interpreter => [
+                vec![interpreter_dir.clone()],
+                gem_dirs,
+                Vec::from_iter(user_dir),
+            ],
+        };
+
+        candidates
+            .into_iter()
+            .flatten()
+            .find(|dir| Path::new(dir).is_dir())
+            .unwrap_or(interpreter_dir)
+    }
+
+    /// Locates `program` on PATH using `which` (or `where` on Windows)
+    fn which(program: &str) -> Result<Option<String>> {
+        let finder = if cfg!(windows) { "where" } else { "which" };
+        let output = Command::new(finder)
+            .arg(program)
+            .output()
+            .with_context(|| format!("Failed to execute '{} {}'", finder, program))?;
+
+        // `where` lists every match, one per line; the first one wins
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.trim().to_string())
+            .find(|line| !line.is_empty()))
+    }
+
+    /// `path` with every symlink resolved, or unchanged if it can't be resolved
+    fn canonical_path(path: &str) -> String {
+        let Ok(canonical) = fs::canonicalize(path) else {
+            return path.to_string();
+        };
+        let canonical = canonical.to_string_lossy().to_string();
+        // Windows canonical paths are verbatim (\\?\C:\...), which the IDE doesn't expect
+        canonical
+            .strip_prefix(r"\\?\")
+            .map(|rest| rest.to_string())
+            .unwrap_or(canonical)
+    }
+
+    /// Normalizes a filesystem path the way JetBrains stores it in XML (forward slashes only)
+    fn xml_path(path: &str) -> String {
+        if cfg!(windows) {
+            path.replace('\\', "/")
+        } else {
+            path.to_string()
+        }
+    }
+
+    fn discover_actual_ruby_path(
+        ruby_wrapper_path: &str,
+        project_dir: &Path,
+        env_manager: EnvManager,
+    ) -> Result<String> {
+        // asdf shims exec asdf itself, so their content doesn't name the interpreter
+        if asdf::is_shim(Path::new(ruby_wrapper_path)) {
+            if let Some(ruby_path) = asdf::which("ruby", project_dir)? {
+                return Ok(ruby_path);
+            }
+        }
+        // A mise install elsewhere on the machine says nothing about this project's ruby
+        if env_manager == EnvManager::Mise || mise::manages(project_dir) {
+            if let Some(ruby_path) = mise::which("ruby", project_dir) {
+                return Ok(ruby_path);
+            }
+        }
+
+        if Path::new(ruby_wrapper_path).exists() {
+            let content = match fs::read_to_string(ruby_wrapper_path) {
+                Ok(content) => content,
+                Err(_) => {
+                    // If we can't read as UTF-8, try reading as bytes and convert lossy
+                    let bytes = fs::read(ruby_wrapper_path)?;
+                    String::from_utf8_lossy(&bytes).to_string()
+                }
+            };
+
+            if let Some(ruby_path) = ruby_wrapper::exec_target(&content) {
+                return Ok(ruby_path);
+            }
+        }
+
+        // Fallback to which ruby result
+        Ok(ruby_wrapper_path.to_string())
+    }
+
+    /// The IDE-visible project name: `.idea/.name`, then the module declared in
+    /// `.idea/modules.xml`, then the directory name
+    fn detect_project_name(current_dir: &str) -> String {
+        let idea_dir = idea_dir::path(Path::new(current_dir));
+
+        if let Ok(name) = fs::read_to_string(idea_dir.join(".name")) {
+            let name = name.trim();
+            if !name.is_empty() {
+                return name.to_string();
+            }
+        }
+
+        if let Ok(content) = fs::read_to_string(idea_dir.join("modules.xml")) {
+            if let Ok(doc) = Document::parse(&content) {
+                // <module fileurl="..." filepath="$PROJECT_DIR$/.idea/{name}.iml" />
+                let module_name = doc
+                    .descendants()
+                    .filter(|node| node.tag_name().name() == "module")
+                    .filter_map(|node| node.attribute("filepath"))
+                    .filter_map(|filepath| {
+                        Path::new(filepath)
+                            .file_stem()
+                            .and_then(|stem| stem.to_str())
+                            .map(|stem| stem.to_string())
+                    })
+                    .next();
+                if let Some(module_name) = module_name {
+                    return module_name;
+                }
+            }
+        }
+
+        Path::new(current_dir)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("unknown")
+            .to_string()
+    }
+
+    /// "{worktree}/{project}" when the dir follows a known checkout layout (see
+    /// `worktree`), otherwise just the project name
+    fn project_label(current_dir: &str, project_name: &str) -> String {
+        match worktree::qualifier(Path::new(current_dir)) {
+            Some(qualifier) => format!("{}/{}", qualifier, project_name),
+            None => project_name.to_string(),
+        }
+    }
+
+    fn generate_interpreter_name(
+        current_dir: &str,
+        project_name: &str,
+        ruby: &RubyInfo,
+        gemset: Option<&str>,
+        configurator: &str,
+    ) -> String {
+        let name_part = Self::project_label(current_dir, project_name);
+        let date_str = Local::now().format("%Y-%m-%d");
+        let ruby_label = match gemset {
+            Some(gemset) => format!("{}@{}", ruby.engine_version, gemset),
+            None => ruby.engine_version.clone(),
+        };
+        format!(
+            "{} {} ({}) + {} {}",
+            ruby.engine.label(),
+            ruby_label,
+            name_part,
+            configurator,
+            date_str
+        )
+    }
+
+    fn is_same_worktree_interpreter(&self, interpreter_name: &str) -> bool {
+        // Pattern: "Ruby {version} ({worktree}/{project}) + {env manager} {date}"
+        if let Some(start) = interpreter_name.find('(') {
+            if let Some(end) = interpreter_name[start..].find(')') {
+                let path_part = &interpreter_name[start + 1..start + end]; // Skip "("
+                return path_part == Self::project_label(&self.current_dir, &self.project_name);
+            }
+        }
+
+        false
+    }
+
+    /// An interpreter generated for this project dir whose name doesn't follow the current
+    /// scheme (e.g. created before checkout layouts were recognized), so a normal run
+    /// would add a second one next to it instead of replacing it
+    fn is_legacy_interpreter(&self, entry: &JdkEntry) -> bool {
+        if self.is_same_worktree_interpreter(&entry.name) {
+            return false;
+        }
+
+        let project_dir = Self::xml_path(&self.current_dir);
+        let configured_dir = entry
+            .configurator
+            .iter()
+            .position(|option| option == "--dir" || option == "-C")
+            .and_then(|index| entry.configurator.get(index + 1));
+        let uses_project_shim = match (&entry.home_path, self.shim_path()) {
+            (Some(home_path), Ok(shim)) => *home_path == Self::xml_path(&shim.to_string_lossy()),
+            _ => false,
+        };
+        configured_dir == Some(&project_dir) || uses_project_shim
+    }
+
+    /// An entry that runs the same ruby (compared by canonical path) through the same
+    /// configurator command as ours, whatever its name
+    fn is_duplicate_interpreter(&self, entry: &JdkEntry) -> bool {
+        let (Some(home_path), Ok(our_home_path)) = (&entry.home_path, self.home_path()) else {
+            return false;
+        };
+        let our_configurator = if self.shim || self.docker.is_some() {
+            Vec::new()
+        } else {
+            self.configurator_options()
+        };
+        Self::xml_path(&Self::canonical_path(home_path))
+            == Self::xml_path(&Self::canonical_path(&our_home_path))
+            && entry.configurator == our_configurator
+    }
+
+    /// Every SDK in jdk.table.xml
+    fn interpreter_entries(&self) -> Result<Vec<JdkEntry>> {
+        let config_file = self.interpreter_config_file()?;
+        let Ok(content) = fs::read_to_string(&config_file) else {
+            return Ok(Vec::new());
+        };
+        let doc = Document::parse(&content)
+            .with_context(|| format!("Failed to parse {}", config_file.display()))?;
+        Ok(doc
+            .descendants()
+            .filter_map(|node| JdkEntry::parse(&node))
+            .collect())
+    }
+
+    /// The interpreter --update-in-place rewrites: this worktree's, else one running
+    /// the same ruby through the same configurator (so another project's interpreter
+    /// with the same ruby is left alone)
+    fn in_place_target(&self) -> Option<JdkEntry> {
+        let entries: Vec<JdkEntry> = self
+            .interpreter_entries()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|entry| !self.protected.protects(entry))
+            .collect();
+        let same_worktree = entries
+            .iter()
+            .find(|entry| self.is_same_worktree_interpreter(&entry.name));
+        let duplicate = || {
+            entries
+                .iter()
+                .find(|entry| self.is_duplicate_interpreter(entry))
+        };
+        same_worktree.or_else(duplicate).cloned()
+    }
+
+    fn legacy_interpreters(&self) -> Result<Vec<JdkEntry>> {
+        Ok(self
+            .interpreter_entries()?
+            .into_iter()
+            .filter(|entry| self.is_legacy_interpreter(entry) && !self.protected.protects(entry))
+            .collect())
+    }
+
+    /// The SDK this tool manages for the project, if it has been written already
+    fn managed_interpreter(&self) -> Result<Option<JdkEntry>> {
+        Ok(self.interpreter_entries()?.into_iter().find(|entry| {
+            entry.name == self.interpreter_name || self.is_duplicate_interpreter(entry)
+        }))
+    }
+
+    /// Data sources in dataSources.xml that the current configuration no longer produces,
+    /// such as the single `@host` source written before database.yml was read
+    fn legacy_datasources(&self) -> Result<Vec<String>> {
+        let current: Vec<String> = self
+            .data_sources(&[])?
+            .into_iter()
+            .map(|data_source| data_source.name)
+            .collect();
+        if current.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut legacy: Vec<String> = self
+            .existing_datasource_uuids()?
+            .into_keys()
+            .filter(|name| !current.contains(name))
+            .collect();
+        legacy.sort();
+        Ok(legacy)
+    }
+
+    /// Directories that hold the versioned JetBrains config directories
+    fn jetbrains_config_roots(home: &Path) -> Vec<PathBuf> {
+        // macOS - Application Support (newer location)
+        let mut roots = vec![home
+            .join("Library")
+            .join("Application Support")
+            .join("JetBrains")];
+
+        // Windows - %APPDATA%\JetBrains, Linux - ~/.config/JetBrains
+        if let Some(config_dir) = dirs::config_dir() {
+            let jetbrains_dir = config_dir.join("JetBrains");
+            if !roots.contains(&jetbrains_dir) {
+                roots.push(jetbrains_dir);
+            }
+        }
+
+        roots
+    }
+
+    /// Versioned config directories for `ide`, most recently modified first
+    pub fn discover_config_dirs(ide: Ide, channel: Channel) -> Result<Vec<PathBuf>> {
+        let home = home_dir().context("Could not find home directory")?;
+
+        // Look for versioned RubyMine directories
+        let prefix = ide.config_dir_prefix().to_lowercase();
+        let mut rubymine_dirs = Vec::new();
+        for jetbrains_dir in Self::jetbrains_config_roots(&home) {
+            if !jetbrains_dir.exists() {
+                continue;
+            }
+            for entry in fs::read_dir(&jetbrains_dir)? {
+                let entry = entry?;
+                let name = entry.file_name();
+                let name_str = name.to_string_lossy();
+                if name_str.to_lowercase().starts_with(&prefix)
+                    && name_str.chars().any(|c| c.is_ascii_digit())
+                    && channel.accepts(&name_str)
+                {
+                    rubymine_dirs.push(entry.path());
+                }
+            }
+        }
+
+        // Sort by modification time to get the most recent
+        rubymine_dirs.sort_by_key(|path| {
+            fs::metadata(path)
+                .and_then(|m| m.modified())
+                .unwrap_or(std::time::UNIX_EPOCH)
+        });
+        rubymine_dirs.reverse(); // Most recent first
+
+        if !rubymine_dirs.is_empty() {
+            return Ok(rubymine_dirs);
+        }
+
+        // Try Library/Preferences as fallback (older location)
+        let library_prefs = home.join("Library").join("Preferences");
+        if library_prefs.exists() {
+            for entry in fs::read_dir(&library_prefs)? {
+                let entry = entry?;
+                let name = entry.file_name();
+                let name_str = name.to_string_lossy();
+                if name_str.starts_with(ide.config_dir_prefix()) && channel.accepts(&name_str) {
+                    rubymine_dirs.push(entry.path());
+                }
+            }
+            rubymine_dirs.sort();
+            rubymine_dirs.reverse();
+        }
+
+        Ok(rubymine_dirs)
+    }
+
+    /// The version part of a config directory name, e.g. "2024.1" for RubyMine2024.1
+    /// or "RubyMine2024.3 EAP"
+    fn config_dir_version(ide: Ide, config_dir: &Path) -> String {
+        let name = config_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let prefix_len = ide.config_dir_prefix().len();
+        if name.len() > prefix_len && name.is_char_boundary(prefix_len) {
+            name[prefix_len..]
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        } else {
+            name
+        }
+    }
+
+    fn rubymine_config_dir(&self) -> Result<PathBuf> {
+        if let Some(dir) = &self.config_dir_override {
+            return Ok(dir.clone());
+        }
+
+        // A relocated config dir (idea.config.path) replaces discovery entirely
+        if let Some(dir) = self.idea_properties().path("idea.config.path") {
+            return Ok(dir);
+        }
+
+        let config_dirs = Self::discover_config_dirs(self.ide, self.channel)?;
+
+        if let Some(version) = &self.ide_version {
+            if let Some(dir) = config_dirs
+                .iter()
+                .find(|dir| Self::config_dir_version(self.ide, dir) == *version)
+            {
+                return Ok(dir.clone());
+            }
+
+            let mut available: Vec<String> = config_dirs
+                .iter()
+                .map(|dir| Self::config_dir_version(self.ide, dir))
+                .collect();
+            available.sort();
+            anyhow::bail!(
+                "No {} configuration directory for version {} (available: {})",
+                self.ide.display_name(),
+                version,
+                if available.is_empty() {
+                    "none".to_string()
+                } else {
+                    available.join(", ")
+                }
+            );
+        }
+
+        config_dirs.into_iter().next().with_context(|| {
+            format!(
+                "No {} configuration directory found",
+                self.ide.display_name()
+            )
+        })
+    }
+
+    /// The IDE's system directory (caches, and workspace files of some projects): the
+    /// relocated `idea.system.path`, else the caches dir named like the config dir
+    fn rubymine_system_dir(&self, config_dir: &Path) -> Option<PathBuf> {
+        if let Some(dir) = self.idea_properties().path("idea.system.path") {
+            return Some(dir);
+        }
+        // ~/Library/Caches on macOS, ~/.cache on Linux, %LOCALAPPDATA% on Windows
+        let name = config_dir.file_name()?;
+        Some(dirs::cache_dir()?.join("JetBrains").join(name))
+    }
+
+    /// idea.properties files in precedence order: the one named by RUBYMINE_PROPERTIES,
+    /// the user's ~/.rubymine/idea.properties, then the one shipped with the IDE
+    fn idea_properties(&self) -> IdeaProperties {
+        let mut files = Vec::new();
+        if let Ok(path) = env::var(self.ide.properties_env_var()) {
+            files.push(PathBuf::from(path));
+        }
+        if let Some(home) = home_dir() {
+            files.push(
+                home.join(self.ide.user_properties_dir())
+                    .join("idea.properties"),
+            );
+        }
+        if let Ok(app_path) = self.rubymine_app_path() {
+            files.push(idea_properties::bundled_properties_file(&app_path));
+        }
+        IdeaProperties::load(&files)
+    }
+
+    fn options_dir(&self) -> Result<PathBuf> {
+        Ok(self.rubymine_config_dir()?.join("options"))
+    }
+
+    fn interpreter_config_file(&self) -> Result<PathBuf> {
+        Ok(self.options_dir()?.join("jdk.table.xml"))
+    }
+
+    /// The jdk.table.xml files to write, depending on Settings Sync
+    fn interpreter_config_files(&self) -> Result<Vec<PathBuf>> {
+        let config_dir = self.rubymine_config_dir()?;
+        let options_file = self.interpreter_config_file()?;
+        if !settings_sync::is_enabled(&config_dir) {
+            return Ok(vec![options_file]);
+        }
+
+        let synced_file = settings_sync::jdk_table_file(&config_dir);
+        Ok(match self.settings_sync {
+            SettingsSync::Warn => vec![options_file],
+            SettingsSync::Also => vec![options_file, synced_file],
+            SettingsSync::Instead => vec![synced_file],
+        })
+    }
+
+    fn warn_about_settings_sync(&self) -> Result<()> {
+        let config_dir = self.rubymine_config_dir()?;
+        if self.settings_sync == SettingsSync::Warn && settings_sync::is_enabled(&config_dir) {
+            warnings::warn(
+                &self.out,
+                format!(
+                    "Settings Sync is enabled for {}; the next sync may overwrite jdk.table.xml",
+                    config_dir.display()
+                ),
+            );
+            self.out.status(
+                "         Rerun with --settings-sync also to update the synced copy as well",
+            );
+        }
+        Ok(())
+    }
+
+    /// Versions required by .ruby-version or the Gemfile that the detected ruby doesn't satisfy
+    fn ruby_version_mismatches(&self) -> Vec<ruby_version_check::Requirement> {
+        ruby_version_check::requirements(Path::new(&self.current_dir))
+            .into_iter()
+            .filter(|requirement| {
+                !ruby_version_check::satisfied_by(&self.ruby, &requirement.version)
+            })
+            .collect()
+    }
+
+    fn check_ruby_version(&self) -> Result<()> {
+        let mismatches = self.ruby_version_mismatches();
+        if mismatches.is_empty() {
+            return Ok(());
+        }
+        for requirement in &mismatches {
+            warnings::warn(
+                &self.out,
+                format!(
+                    "detected ruby {} but {} asks for {}",
+                    self.ruby.sdk_version(),
+                    requirement.source,
+                    requirement.version
+                ),
+            );
+        }
+        // Under --strict, fails the run before the interpreter is written
+        warnings::check(&self.out, self.strict)?;
+        self.out.status("         RubyMine test runs will likely fail; pass --ruby or fix the version manager setup");
+        Ok(())
+    }
+
+    /// Required gems missing from the project's ruby, or `None` when that can't be checked
+    fn missing_gems(&self) -> Option<Vec<&'static gem_probe::RequiredGem>> {
+        // The image's gems are only known once RubyMine builds the container
+        if self.docker.is_some() {
+            return None;
+        }
+        gem_probe::missing(&self.configurator_options())
+    }
+
+    fn warn_about_missing_gems(&self) {
+        let Some(missing) = self.missing_gems() else {
+            warnings::warn(&self.out, "could not check the project's gems");
+            return;
+        };
+        for gem in missing {
+            warnings::warn(
+                &self.out,
+                format!(
+                    "{} is not installed, so {} from RubyMine will fail",
+                    gem.alternatives.join(" or "),
+                    gem.feature
+                ),
+            );
+            self.out.status(format!(
+                "         Install it with: {}",
+                gem_probe::install_command(gem, Path::new(&self.current_dir))
+            ));
+        }
+    }
+
+    fn ensure_rubymine_config_exists(&self, config_files: &[PathBuf]) -> Result<()> {
+        for dir in config_files.iter().filter_map(|file| file.parent()) {
+            if !dir.exists() {
+                self.fs.create_dir_all(dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Problems the IDE would have loading `content` that are ours to fix: the file's
+    /// structure and our own entry, not other SDKs already in it
+    fn open_problems(&self, content: &str) -> Vec<simulate_open::Problem> {
+        // The shim is written alongside the config, so it can't be there yet in a dry run
+        let check_paths = !(self.dry_run && self.shim);
+        simulate_open::validate(content, check_paths)
+            .into_iter()
+            .filter(|problem| {
+                problem
+                    .sdk
+                    .as_ref()
+                    .is_none_or(|sdk| *sdk == self.interpreter_name)
+            })
+            .collect()
+    }
+
+    /// Refuses to write a jdk.table.xml the IDE wouldn't load our interpreter from,
+    /// or that lost SDKs the current one has
+    fn check_opens(&self, config_file: &Path, content: &str) -> Result<()> {
+        let original = fs::read_to_string(config_file).ok();
+        if let Err(err) = output_check::check_jdk_table(
+            original.as_deref(),
+            content,
+            &self.interpreter_name,
+            |entry| self.replaces_interpreter(entry),
+        ) {
+            if self.dry_run {
+                self.out.status(format!("Error: {:#}", err));
+            } else {
+                return Err(err.context(format!(
+                    "Not writing {}, which is left as it was",
+                    config_file.display()
+                )));
+            }
+        }
+
+        let problems = self.open_problems(content);
+        if problems.is_empty() {
+            return Ok(());
+        }
+        for problem in &problems {
+            self.out.status(format!("Error: {}", problem));
+        }
+        if self.dry_run {
+            return Ok(());
+        }
+        anyhow::bail!(
+            "Not writing {}: the IDE would not load it as generated",
+            config_file.display()
+        )
+    }
+
+    /// Detection lands on the OS's ruby when the project environment wasn't loaded, e.g.
+    /// when shadowenv isn't hooked into the shell, which is never what the project wants
+    fn check_system_ruby(&self) -> Result<()> {
+        if self.allow_system_ruby
+            || self.docker.is_some()
+            || !ruby_engine::is_system_ruby(Path::new(&self.ruby_interpreter_path))
+        {
+            return Ok(());
+        }
+        let message = format!(
+            "the detected ruby is the system ruby ({}). This usually means the project environment wasn't loaded:\n  \
+             - check that the shadowenv shell hook is installed (eval \"$(shadowenv init zsh)\") and the project is trusted (shadowenv trust)\n  \
+             - run this from inside the project directory\n  \
+             - check that your PATH puts the project's ruby before {}",
+            self.ruby_interpreter_path,
+            Path::new(&self.ruby_interpreter_path)
+                .parent()
+                .unwrap_or(Path::new("/"))
+                .display()
+        );
+        if self.dry_run {
+            warnings::warn(&self.out, message);
+            return Ok(());
+        }
+        anyhow::bail!(
+            "Not writing the interpreter: {}\nPass --allow-system-ruby to configure it anyway",
+            message
+        )
+    }
+
+    /// A homePath that doesn't run (e.g. from a bad `which` match) would give the IDE a
+    /// broken SDK with no hint why, so it's refused unless --skip-validation is given
+    fn validate_interpreter(&self) -> Result<()> {
+        // A remote interpreter was already run when its container or host was probed
+        if self.docker.is_some() {
+            return Ok(());
+        }
+        // A shim's homePath is the shim, which runs this ruby
+        let Some(problem) = ruby_engine::check_interpreter(Path::new(&self.ruby_interpreter_path))
+        else {
+            return Ok(());
+        };
+        if self.dry_run || self.skip_validation {
+            warnings::warn(&self.out, problem);
+            return Ok(());
+        }
+        anyhow::bail!(
+            "Not writing the interpreter: {}; pass --ruby to pick another one, or --skip-validation to write it anyway",
+            problem
+        )
+    }
+
+    /// The `simulate-open` command
+    fn simulate_open(&self) -> Result<()> {
+        let mut problems = 0;
+        for config_file in self.interpreter_config_files()? {
+            let content = self.create_interpreter_config(&config_file)?;
+            self.out.line(format!("{}:", config_file.display()));
+            let file_problems = self.open_problems(&content);
+            if file_problems.is_empty() {
+                self.out.line("  opens cleanly");
+            }
+            for problem in &file_problems {
+                self.out.line(format!("  {}", problem));
+            }
+            problems += file_problems.len();
+        }
+        if problems > 0 {
+            anyhow::bail!("simulate-open found {} problem(s)", problems);
+        }
+        Ok(())
+    }
+
+    /// Points the project, and its module when it picks its own SDK, at the interpreter
+    /// by name, then prints the SDK the global config needs for that name to resolve
+    fn refer_to_interpreter(&self) -> Result<()> {
+        let misc_file = self.idea_dir().join("misc.xml");
+        let set_misc = |misc: Option<&str>| {
+            let updated = project_sdk::set_project_sdk(misc, &self.interpreter_name)?;
+            if let (Some(misc), Some(_)) = (misc, &updated) {
+                self.warn_if_block_edited(&misc_file, misc, &project_sdk::PROJECT_SDK);
+            }
+            Ok(updated)
+        };
+        let module_file = self.module_file();
+        let set_module = |iml: Option<&str>| {
+            let Some(iml) = iml else {
+                return Ok(None);
+            };
+            let updated = project_sdk::set_module_sdk(iml, &self.interpreter_name)?;
+            if updated.is_some() {
+                self.warn_if_block_edited(&module_file, iml, &project_sdk::MODULE_SDK);
+            }
+            Ok(updated)
+        };
+
+        if self.dry_run {
+            let misc = set_misc(fs::read_to_string(&misc_file).ok().as_deref())?;
+            let module = set_module(fs::read_to_string(&module_file).ok().as_deref())?;
+            for (file, content) in [(&misc_file, &misc), (&module_file, &module)] {
+                if let Some(content) = content {
+                    self.out
+                        .line(format!("# Project SDK reference: {}", file.display()));
+                    self.out.line(content);
+                }
+            }
+            self.out.line(format!("# {}", "=".repeat(50)));
+            self.out.line("");
+        } else {
+            self.update_config_file(&misc_file, set_misc)?;
+            self.update_config_file(&module_file, set_module)?;
+            self.out.line(format!(
+                "Project SDK set to {} in {}",
+                self.interpreter_name,
+                self.idea_dir().display()
+            ));
+        }
+
+        let jdk_table = self
+            .interpreter_config_file()
+            .map(|file| file.display().to_string())
+            .unwrap_or_else(|_| "jdk.table.xml".to_string());
+        let jdk = xml_splice::fragment(|writer| {
+            self.sdk_entry()?.write(writer);
+            Ok(())
+        })?;
+        self.out.line("");
+        self.out.line(format!(
+            "--project-only left the global {} config alone. Until an SDK named {} exists",
+            self.ide.display_name(),
+            self.interpreter_name
+        ));
+        self.out.line(
+            "there, the project has no interpreter. Add this to the ProjectJdkTable component of",
+        );
+        self.out.line(format!(
+            "{}, or have the tooling managing it do so:",
+            jdk_table
+        ));
+        self.out.line("");
+        for line in jdk.lines() {
+            self.out.line(format!("  {}", line));
+        }
+        self.out.line("");
+        Ok(())
+    }
+
+    /// The jdk.table.xml `config_file` should hold: its current content with this
+    /// interpreter written in (replacing the entries a run replaces), or a new table
+    /// with only the interpreter when the file doesn't exist. A file that doesn't
+    /// parse is an error unless --repair says how to recover. Reads, never writes.
+    pub fn create_interpreter_config(&self, config_file: &Path) -> Result<String> {
+        let Ok(xml_content) = fs::read_to_string(config_file) else {
+            return self.create_new_config_content();
+        };
+        let err = match Document::parse(&xml_content) {
+            Ok(_) => {
+                self.announce_replacements(&xml_content);
+                return self.update_existing_config(&xml_content);
+            }
+            Err(err) => err,
+        };
+        let prefix = if self.dry_run { "# " } else { "" };
+        match self.repair {
+            None => Err(self.malformed_config_error(config_file, &xml_content, err)),
+            Some(Repair::Restore) => {
+                let (backup, content) = Self::newest_parsing_backup(config_file)
+                    .with_context(|| {
+                        format!(
+                            "{} doesn't parse, and neither does any backup of it; use --repair fresh",
+                            config_file.display()
+                        )
+                    })?;
+                self.out
+                    .line(format!("{}Starting over from {}", prefix, backup.display()));
+                self.announce_replacements(&content);
+                self.update_existing_config(&content)
+            }
+            Some(Repair::Fresh) => {
+                self.out.line(format!(
+                    "{}Replacing {}, which doesn't parse, with only this interpreter",
+                    prefix,
+                    config_file.display()
+                ));
+                self.create_new_config_content()
+            }
+        }
+    }
+
+    /// The newest backup of `config_file` that parses, with its content
+    fn newest_parsing_backup(config_file: &Path) -> Option<(PathBuf, String)> {
+        backups::of(config_file).into_iter().find_map(|backup| {
+            let content = fs::read_to_string(&backup).ok()?;
+            Document::parse(&content).ok()?;
+            Some((backup, content))
+        })
+    }
+
+    /// Why `config_file` can't be updated, and the ways `--repair` can recover it
+    fn malformed_config_error(
+        &self,
+        config_file: &Path,
+        content: &str,
+        err: roxmltree::Error,
+    ) -> anyhow::Error {
+        let conflicted = content
+            .lines()
+            .any(|line| line.starts_with("<<<<<<<") || line.starts_with(">>>>>>>"));
+        let restore = match Self::newest_parsing_backup(config_file) {
+            Some((backup, _)) => format!("start over from {}", backup.display()),
+            None => "start over from the newest backup (there is none that parses)".to_string(),
+        };
+        anyhow::anyhow!(
+            "{} doesn't parse ({}){}. Rerun with:\n  \
+             --repair restore  to {}\n  \
+             --repair fresh    to set it aside and write one with only this interpreter",
+            config_file.display(),
+            err,
+            if conflicted {
+                "; it has merge conflict markers"
+            } else {
+                ""
+            },
+            restore
+        )
+    }
+
+    /// Keeps a copy of `config_file` next to it when it doesn't parse and is about to
+    /// be replaced, under a name backup pruning and `--repair restore` leave alone
+    fn set_aside_if_malformed(&self, config_file: &Path) -> Result<()> {
+        let Ok(content) = fs::read_to_string(config_file) else {
+            return Ok(());
+        };
+        if Document::parse(&content).is_ok() {
+            return Ok(());
+        }
+        let timestamp = Local::now().format("%Y%m%d_%H%M%S");
+        let broken = config_file.with_extension(format!("broken.{}.xml", timestamp));
+        self.fs.copy(config_file, &broken)?;
+        self.out
+            .line(format!("Set the broken file aside: {}", broken.display()));
+        Ok(())
+    }
+
+    /// Backs up and replaces `config_file`, leaving it alone when it has `content`
+    /// already, and refusing when `content` no longer parses or has a different root
+    /// element, which only a bug here could cause
+    fn write_config_file(&self, config_file: &Path, content: &str) -> Result<()> {
+        if let Ok(original) = fs::read_to_string(config_file) {
+            if original == content {
+                return Ok(());
+            }
+            output_check::check_same_root(&original, content).with_context(|| {
+                format!(
+                    "Not writing {}, which is left as it was",
+                    config_file.display()
+                )
+            })?;
+        }
+        self.backup_file(config_file)?;
+        self.fs.write(config_file, content)?;
+        Ok(())
+    }
+
+    /// Reads `config_file` (`None` when it doesn't exist), has `modify` update it and
+    /// writes the result like `write_config_file`, through `safe_write::read_modify_write`
+    /// so that a save by the IDE in between isn't lost. `modify` returns `None` when
+    /// there's nothing to change. Returns what was written.
+    fn update_config_file(
+        &self,
+        config_file: &Path,
+        mut modify: impl FnMut(Option<&str>) -> Result<Option<String>>,
+    ) -> Result<Option<String>> {
+        let mut written = None;
+        safe_write::read_modify_write(
+            config_file,
+            || modify(fs::read_to_string(config_file).ok().as_deref()),
+            |content| {
+                if let Some(dir) = config_file.parent() {
+                    self.fs.create_dir_all(dir)?;
+                }
+                self.write_config_file(config_file, content)?;
+                written = Some(content.to_string());
+                Ok(())
+            },
+            &self.out,
+        )?;
+        Ok(written)
+    }
+
+    /// Removes the oldest backups, next to jdk.table.xml, in .idea and in the backup
+    /// store, once they exceed --max-backups-size together
+    fn enforce_backup_budget(&self) -> Result<()> {
+        let Some(budget) = self.max_backups_size else {
+            return Ok(());
+        };
+        let mut dirs: Vec<PathBuf> = self
+            .fs
+            .backups()
+            .iter()
+            .filter_map(|backup| backup.parent().map(Path::to_path_buf))
+            .collect();
+        dirs.push(self.idea_dir());
+        if let (false, Ok(options_dir)) = (self.project_only, self.options_dir()) {
+            dirs.push(options_dir);
+        }
+        backups::enforce(&self.fs, dirs, budget, &self.out)
+    }
+
+    /// Copies `file` next to itself with a timestamped `.backup.*.xml` extension
+    fn backup_file(&self, file: &Path) -> Result<()> {
+        if let Some(backup_file) = self.fs.backup(file)? {
+            self.out
+                .line(format!("Backup created: {}", backup_file.display()));
+        }
+        Ok(())
+    }
+
+    /// Prints what writing the interpreter into `xml_content` does to the entries
+    /// already there
+    fn announce_replacements(&self, xml_content: &str) {
+        let Ok(doc) = Document::parse(xml_content) else {
+            return;
+        };
+        let prefix = if self.dry_run { "# " } else { "" };
+        for entry in doc.descendants().filter_map(|node| JdkEntry::parse(&node)) {
+            if self.replaces_interpreter(&entry) {
+                let action = if self.update_in_place && entry.name == self.interpreter_name {
+                    "Updating interpreter in place"
+                } else {
+                    "Replacing interpreter"
+                };
+                self.out.line(format!(
+                    "{}{}: {} (Ruby {}, {})",
+                    prefix,
+                    action,
+                    entry.name,
+                    entry.version.as_deref().unwrap_or("unknown version"),
+                    entry.home_path.as_deref().unwrap_or("no homePath")
+                ));
+            } else if self.protected.protects(&entry) && self.would_replace_interpreter(&entry) {
+                self.out.line(format!(
+                    "{}Leaving protected interpreter: {}",
+                    prefix, entry.name
+                ));
+            }
+        }
+    }
+
+    /// jdk.table.xml with this project's earlier interpreters replaced by the current
+    /// one, spliced in so that the rest of the file is left exactly as it was
+    fn update_existing_config(&self, xml_content: &str) -> Result<String> {
+        let doc = Document::parse(xml_content)?;
+        let interpreter = xml_splice::fragment(|writer| {
+            self.sdk_entry()?.write(writer);
+            Ok(())
+        })?;
+        let mut splice = Splice::new(xml_content);
+
+        let tables: Vec<roxmltree::Node> = doc
+            .descendants()
+            .filter(|node| {
+                node.has_tag_name("component")
+                    && node.attribute("name") == Some(jdk_table::COMPONENT)
+            })
+            .collect();
+        if tables.is_empty() {
+            let component = xml_splice::fragment(|writer| {
+                writer.start_element("component");
+                writer.write_attribute("name", jdk_table::COMPONENT);
+                self.sdk_entry()?.write(writer);
+                writer.end_element();
+                Ok(())
+            })?;
+            splice.append_child(&doc.root_element(), &component);
+        }
+        for table in &tables {
+            let mut replaced = Vec::new();
+            let mut kept = Vec::new();
+            for child in table.children() {
+                let Some(entry) = JdkEntry::parse(&child) else {
+                    continue;
+                };
+                if self.protected.protects(&entry) && entry.name == self.interpreter_name {
+                    anyhow::bail!(
+                        "The interpreter {} is protected (protected_sdk_names or protected_sdk_homes); not replacing it",
+                        entry.name
+                    );
+                }
+                if self.replaces_interpreter(&entry) {
+                    replaced.push(child);
+                } else {
+                    kept.push((child, entry.name));
+                }
+            }
+
+            let our_name = self.interpreter_name.to_lowercase();
+            let before = match self.sdk_position {
+                SdkPosition::Bottom | SdkPosition::Keep => None,
+                SdkPosition::Top => kept.first(),
+                SdkPosition::Alphabetical => {
+                    kept.iter().find(|(_, name)| name.to_lowercase() > our_name)
+                }
+            };
+            let mut replaced = replaced.iter();
+            let in_place = if self.sdk_position == SdkPosition::Keep {
+                replaced.next()
+            } else {
+                None
+            };
+            match (in_place, before) {
+                (Some(node), _) => splice.replace(node, &interpreter),
+                (None, Some((node, _))) => splice.insert_before(node, &interpreter),
+                (None, None) => splice.append_child(table, &interpreter),
+            }
+            for node in replaced {
+                splice.remove(node);
+            }
+        }
+
+        Ok(splice.finish())
+    }
+
+    /// Whether `content` is what `config_file` has already, but for the date in the
+    /// name of this project's interpreter, which alone isn't worth a rewrite and a backup
+    fn is_interpreter_unchanged(&self, config_file: &Path, content: &str) -> bool {
+        let Ok(current) = fs::read_to_string(config_file) else {
+            return false;
+        };
+        if current == content {
+            return true;
+        }
+        let date_re = Regex::new(r"\d{4}-\d{2}-\d{2}").unwrap();
+        let undated = |name: &str| date_re.replace_all(name, "").to_string();
+        let our_name = undated(&self.interpreter_name);
+        let Ok(doc) = Document::parse(&current) else {
+            return false;
+        };
+        doc.descendants()
+            .filter_map(|node| JdkEntry::parse(&node))
+            .find(|entry| entry.name != self.interpreter_name && undated(&entry.name) == our_name)
+            .is_some_and(|entry| current.replace(&entry.name, &self.interpreter_name) == content)
+    }
+
+    /// Whether writing the interpreter replaces `entry`
+    fn replaces_interpreter(&self, entry: &JdkEntry) -> bool {
+        !self.protected.protects(entry) && self.would_replace_interpreter(entry)
+    }
+
+    /// Whether writing the interpreter would replace `entry` if it weren't protected
+    fn would_replace_interpreter(&self, entry: &JdkEntry) -> bool {
+        entry.name == self.interpreter_name
+            || self.is_same_worktree_interpreter(&entry.name)
+            || self.is_duplicate_interpreter(entry)
+            || (self.replace_legacy && self.is_legacy_interpreter(entry))
+    }
+
+    fn create_new_config_content(&self) -> Result<String> {
+        Ok(JdkTable {
+            sdks: vec![self.sdk_entry()?],
+        }
+        .to_xml())
+    }
+
+    /// The interpreter's jdk.table.xml entry
+    fn sdk_entry(&self) -> Result<SdkEntry> {
+        let other = self
+            .docker
+            .as_ref()
+            .map(|docker| docker.remote_data(&Self::xml_path(&self.current_dir)))
+            .unwrap_or_default();
+        let mut additional = Additional {
+            version: Some("1".to_string()),
+            gems_bin_dir_path: Some(Self::xml_path(&self.gems_bin_dir)),
+            attributes: Vec::new(),
+            other,
+            version_manager: Some(jdk_table::VersionManager {
+                id: self.version_manager.id().to_string(),
+                // The shim already runs ruby inside the project environment, and a
+                // container doesn't need one
+                custom_configurator: (!self.shim && self.docker.is_none())
+                    .then(|| self.configurator_options()),
+            }),
+        };
+        for (key, value) in &self.sdk_additional {
+            additional
+                .set(key, value)
+                .context("Invalid sdk_additional")?;
+        }
+        Ok(SdkEntry {
+            format_version: Some("2".to_string()),
+            name: self.interpreter_name.clone(),
+            sdk_type: Some("RUBY_SDK".to_string()),
+            version: Some(self.ruby.sdk_version()),
+            home_path: Some(Self::xml_path(&self.home_path()?)),
+            roots: Some(Roots {
+                class_path: vec![Root::composite()],
+                source_path: vec![Root::composite()],
+            }),
+            additional: Some(additional),
+        })
+    }
+
+    /// The SDK's homePath: the shim in shim mode, otherwise the interpreter itself
+    fn home_path(&self) -> Result<String> {
+        if let Some(docker) = &self.docker {
+            Ok(docker.home_path())
+        } else if self.shim {
+            Ok(self.shim_path()?.to_string_lossy().to_string())
+        } else {
+            Ok(self.ruby_interpreter_path.clone())
+        }
+    }
+
+    /// Command prefix RubyMine runs ruby through, e.g. `shadowenv exec --dir <dir> --`
+    fn configurator_options(&self) -> Vec<String> {
+        if let Some(command) = &self.configurator_cmd {
+            return custom_configurator::expand(command, &Self::xml_path(&self.current_dir));
+        }
+        let (program, dir_flag) = match self.env_manager {
+            EnvManager::Shadowenv => (self.shadowenv_path.clone(), "--dir"),
+            EnvManager::Mise => (
+                mise::find_path().unwrap_or_else(|| "mise".to_string()),
+                "-C",
+            ),
+            // direnv takes the command right after the directory, without a `--`
+            EnvManager::Direnv => {
+                let program = direnv::find_path().unwrap_or_else(|| "direnv".to_string());
+                return vec![
+                    Self::xml_path(&program),
+                    "exec".to_string(),
+                    Self::xml_path(&self.current_dir),
+                ];
+            }
+            EnvManager::Nix => {
+                let program = nix::find_path().unwrap_or_else(|| "nix".to_string());
+                let mut options = vec![Self::xml_path(&program)];
+                options.extend(
+                    nix::develop_args(Path::new(&self.current_dir))
+                        .iter()
+                        .map(|arg| Self::xml_path(arg)),
+                );
+                options.push("--command".to_string());
+                return options;
+            }
+        };
+        let mut options = vec![Self::xml_path(&program), "exec".to_string()];
+        if self.env_manager == EnvManager::Shadowenv {
+            options.extend(self.shadowenv_args.iter().cloned());
+        }
+        options.extend([
+            dir_flag.to_string(),
+            Self::xml_path(&self.current_dir),
+            "--".to_string(),
+        ]);
+        options
+    }
+
+    fn shims_dir() -> Result<PathBuf> {
+        Ok(state::data_dir()?.join("shims"))
+    }
+
+    fn shim_path(&self) -> Result<PathBuf> {
+        let label = Self::project_label(&self.current_dir, &self.project_name);
+        let file_name: String = label
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        Ok(Self::shims_dir()?.join(format!("{}.sh", file_name)))
+    }
+
+    fn shim_content(&self) -> String {
+        let command = self
+            .configurator_options()
+            .iter()
+            .chain(std::iter::once(&self.ruby_interpreter_path))
+            .map(|arg| Self::shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        format!(
+            "#!/bin/sh\n# Generated by rubymine-configurator for {}\nexec {} \"$@\"\n",
+            self.current_dir, command
+        )
+    }
+
+    /// Writes the shim, leaving it untouched when the wrapper and project dir haven't changed
+    fn write_shim(&self) -> Result<()> {
+        let shim_path = self.shim_path()?;
+        let content = self.shim_content();
+        if fs::read_to_string(&shim_path).ok().as_deref() == Some(content.as_str()) {
+            self.out
+                .line(format!("Shim up to date: {}", shim_path.display()));
+            return Ok(());
+        }
+
+        let shims_dir = Self::shims_dir()?;
+        if !shims_dir.exists() {
+            self.fs.create_dir_all(&shims_dir)?;
+        }
+        self.fs.write(&shim_path, &content)?;
+        self.fs.set_executable(&shim_path)?;
+        self.out
+            .line(format!("Shim written: {}", shim_path.display()));
+        Ok(())
+    }
+
+    /// All shim scripts in the shims directory
+    fn existing_shims() -> Result<Vec<PathBuf>> {
+        let shims_dir = Self::shims_dir()?;
+        let mut shims = Vec::new();
+        if shims_dir.exists() {
+            for entry in fs::read_dir(&shims_dir)? {
+                let path = entry?.path();
+                if path.extension().and_then(|ext| ext.to_str()) == Some("sh") {
+                    shims.push(path);
+                }
+            }
+        }
+        shims.sort();
+        Ok(shims)
+    }
+
+    /// homePath of every SDK across all installed IDE versions, so a shim that's
+    /// still used by another IDE version is never considered orphaned
+    fn referenced_home_paths(&self) -> Result<Vec<String>> {
+        let mut config_dirs = Self::discover_config_dirs(self.ide, Channel::Any)?;
+        if let Ok(config_dir) = self.rubymine_config_dir() {
+            if !config_dirs.contains(&config_dir) {
+                config_dirs.push(config_dir);
+            }
+        }
+
+        let mut home_paths = Vec::new();
+        for config_dir in config_dirs {
+            let jdk_table = config_dir.join("options").join("jdk.table.xml");
+            let Ok(content) = fs::read_to_string(&jdk_table) else {
+                continue;
+            };
+            let doc = Document::parse(&content)
+                .with_context(|| format!("Failed to parse {}", jdk_table.display()))?;
+            home_paths.extend(
+                doc.descendants()
+                    .filter_map(|node| JdkEntry::parse(&node))
+                    .filter_map(|entry| entry.home_path),
+            );
+        }
+        Ok(home_paths)
+    }
+
+    /// Shims no interpreter points at anymore
+    fn orphaned_shims(&self) -> Result<Vec<PathBuf>> {
+        let home_paths = self.referenced_home_paths()?;
+        Ok(Self::existing_shims()?
+            .into_iter()
+            .filter(|shim| {
+                let shim_path = Self::xml_path(&shim.to_string_lossy());
+                !home_paths.contains(&shim_path)
+            })
+            .collect())
+    }
+
+    fn remove_orphaned_shims(&self) -> Result<()> {
+        for shim in self.orphaned_shims()? {
+            self.fs.remove_file(&shim)?;
+            self.out
+                .line(format!("Removed unused shim: {}", shim.display()));
+        }
+        Ok(())
+    }
+
+    /// Single-quotes `arg` for /bin/sh unless it is made of safe characters only
+    fn shell_quote(arg: &str) -> String {
+        if !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./=:@%+".contains(c))
+        {
+            arg.to_string()
+        } else {
+            format!("'{}'", arg.replace('\'', "'\\''"))
+        }
+    }
+
+    /// The shadowenv the configurator runs: the configured one, else the one found,
+    /// else the bare name. `None` alongside it when it doesn't exist.
+    fn resolve_shadowenv_path(
+        configured: Option<&Path>,
+        candidates: &[String],
+    ) -> (String, Option<String>) {
+        if let Some(path) = configured {
+            let missing =
+                (!path.is_file()).then(|| format!("shadowenv not found at {}", path.display()));
+            return (path.to_string_lossy().to_string(), missing);
+        }
+        match shadowenv::find(candidates) {
+            Some(path) => (path, None),
+            None => (
+                "shadowenv".to_string(),
+                Some(format!(
+                    "no executable shadowenv in {}",
+                    candidates.join(", ")
+                )),
+            ),
+        }
+    }
+
+    /// A shadowenv too old for `exec --dir` would fail every ruby invocation in the IDE
+    fn outdated_shadowenv(&self) -> Option<String> {
+        match shadowenv::version(&self.shadowenv_path) {
+            Some(version) if version < shadowenv::MINIMUM_VERSION => Some(format!(
+                "shadowenv {} at {} is too old: RubyMine runs ruby through `shadowenv exec --dir <dir> --`, which needs {} or later; upgrade it or pass --shadowenv-path",
+                version,
+                self.shadowenv_path,
+                shadowenv::MINIMUM_VERSION
+            )),
+            Some(_) => None,
+            None => {
+                warnings::warn(&self.out, format!(
+                    "couldn't tell which version of shadowenv {} is; RubyMine needs {} or later",
+                    self.shadowenv_path,
+                    shadowenv::MINIMUM_VERSION
+                ));
+                None
+            }
+        }
+    }
+
+    /// An untrusted project gets no environment from shadowenv, so RubyMine would run the
+    /// wrong ruby without any error. Trusts it with `trust`, otherwise warns.
+    fn check_shadowenv_trust(&self, trust: bool, read_only: bool) -> Result<()> {
+        let project_dir = Path::new(&self.current_dir);
+        if !shadowenv::has_shadowenv_dir(project_dir)
+            || shadowenv::is_trusted(&self.shadowenv_path, project_dir) != Some(false)
+        {
+            return Ok(());
+        }
+        if !trust {
+            warnings::warn(&self.out, format!(
+                "shadowenv doesn't trust {}, so RubyMine would run ruby without the project environment; rerun with --trust or run `shadowenv trust` there",
+                self.current_dir
+            ));
+            return Ok(());
+        }
+        if self.dry_run {
+            self.out.line(format!(
+                "# Would run: shadowenv trust (in {})",
+                self.current_dir
+            ));
+            return Ok(());
+        }
+        if read_only {
+            anyhow::bail!("Refusing to run shadowenv trust: running in read-only mode");
+        }
+        shadowenv::trust(&self.shadowenv_path, project_dir)?;
+        self.out
+            .line(format!("Trusted {} with shadowenv", self.current_dir));
+        Ok(())
+    }
+
+    fn find_rubymine_app_path(ide: Ide, channel: Channel) -> Result<PathBuf> {
+        for app_name in ide.app_names().iter().filter(|name| channel.accepts(name)) {
+            // Check user Applications first
+            if let Some(home) = home_dir() {
+                let user_app = home.join("Applications").join(app_name);
+                if user_app.exists() {
+                    return Ok(user_app);
+                }
+            }
+
+            // Check system Applications
+            let system_app = Path::new("/Applications").join(app_name);
+            if system_app.exists() {
+                return Ok(system_app);
+            }
+        }
+
+        // Windows installer puts each version in Program Files\JetBrains\RubyMine <version>
+        let mut windows_installs = Vec::new();
+        for var in ["ProgramFiles", "ProgramFiles(x86)"] {
+            let Ok(program_files) = env::var(var) else {
+                continue;
+            };
+            let jetbrains_dir = Path::new(&program_files).join("JetBrains");
+            if let Ok(entries) = fs::read_dir(&jetbrains_dir) {
+                for entry in entries.flatten() {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    if name.starts_with(ide.windows_install_prefix()) && channel.accepts(&name) {
+                        windows_installs.push(entry.path());
+                    }
+                }
+            }
+        }
+        windows_installs.sort();
+        if let Some(install) = windows_installs.pop() {
+            return Ok(install);
+        }
+
+        // Installs elsewhere, as the installer registered them or Toolbox recorded them
+        for install in installs::registry(ide, channel)
+            .into_iter()
+            .rev()
+            .chain(installs::toolbox_state(ide, channel).into_iter().rev())
+        {
+            if let Some(root) = Self::install_root(&install) {
+                return Ok(root);
+            }
+        }
+
+        if let Some(install) = Self::find_linux_install(ide, channel) {
+            return Ok(install);
+        }
+
+        anyhow::bail!(
+            "{} not found in ~/Applications, /Applications, Program Files\\JetBrains, \
+             the uninstall registry, /opt, Toolbox, snap or flatpak; pass --rubymine-app",
+            ide.display_name()
+        )
+    }
+
+    fn rubymine_app_path(&self) -> Result<PathBuf> {
+        match &self.rubymine_app {
+            Some(path) => Ok(path.clone()),
+            None => Self::find_rubymine_app_path(self.ide, self.channel),
+        }
+    }
+
+    /// Linux installs: Toolbox, tarballs unpacked in /opt or $HOME, snap and flatpak
+    fn find_linux_install(ide: Ide, channel: Channel) -> Option<PathBuf> {
+        let home = home_dir()?;
+        let mut candidates = Vec::new();
+
+        // Toolbox: apps/RubyMine/ch-0/<build> (1.x) or apps/rubymine (2.x)
+        let toolbox_apps = home
+            .join(".local")
+            .join("share")
+            .join("JetBrains")
+            .join("Toolbox")
+            .join("apps");
+        for app_dir in Self::matching_install_dirs(&toolbox_apps, ide, channel) {
+            candidates.extend(Self::toolbox_builds(&app_dir));
+        }
+
+        for tarball_root in [Path::new("/opt"), home.as_path()] {
+            candidates.extend(Self::matching_install_dirs(tarball_root, ide, channel));
+        }
+
+        candidates.push(Path::new("/snap").join(ide.snap_name()).join("current"));
+
+        for flatpak_root in [
+            PathBuf::from("/var/lib/flatpak/app"),
+            home.join(".local/share/flatpak/app"),
+        ] {
+            candidates.push(
+                flatpak_root
+                    .join(ide.flatpak_id())
+                    .join("current")
+                    .join("active")
+                    .join("files"),
+            );
+        }
+
+        candidates
+            .into_iter()
+            .find_map(|dir| Self::install_root(&dir))
+    }
+
+    /// Children of `dir` named like an install of `ide`, newest version first
+    fn matching_install_dirs(dir: &Path, ide: Ide, channel: Channel) -> Vec<PathBuf> {
+        let mut matches: Vec<PathBuf> = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().to_lowercase();
+                ide.linux_install_prefixes()
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+                    && channel.accepts(&name)
+            })
+            .map(|entry| entry.path())
+            .collect();
+        matches.sort();
+        matches.reverse();
+        matches
+    }
+
+    /// Build directories of a Toolbox 1.x app (under ch-*), or the app dir itself
+    fn toolbox_builds(app_dir: &Path) -> Vec<PathBuf> {
+        let mut builds: Vec<PathBuf> = fs::read_dir(app_dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("ch-"))
+            .flat_map(|channel_dir| fs::read_dir(channel_dir.path()).into_iter().flatten())
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect();
+        builds.sort();
+        builds.reverse();
+        builds.push(app_dir.to_path_buf());
+        builds
+    }
+
+    /// `dir` if it looks like an IDE install (has a plugins dir), or a direct child that does
+    fn install_root(dir: &Path) -> Option<PathBuf> {
+        let is_install = |path: &Path| Self::app_plugins_dir(path).is_dir();
+        if is_install(dir) {
+            return Some(dir.to_path_buf());
+        }
+        fs::read_dir(dir)
+            .ok()?
+            .flatten()
+            .map(|entry| entry.path())
+            .find(|path| is_install(path))
+    }
+
+    /// The Ruby plugin's root. A marketplace install (how IDEA gets the plugin, and how
+    /// RubyMine gets updates to it) wins over the one bundled with the IDE.
+    fn ruby_plugin_dir(&self) -> Result<PathBuf> {
+        let config_dir = self.rubymine_config_dir()?;
+
+        // macOS and Windows keep marketplace plugins in the config dir,
+        // Linux keeps them under ~/.local/share/JetBrains
+        let mut plugins_dirs = vec![config_dir.join("plugins")];
+        if let (Some(data_dir), Some(dir_name)) = (dirs::data_dir(), config_dir.file_name()) {
+            plugins_dirs.push(data_dir.join("JetBrains").join(dir_name).join("plugins"));
+        }
+        let app_path = match self.ide {
+            Ide::Rubymine => Some(self.rubymine_app_path()),
+            Ide::Idea => None,
+        };
+        if let Some(Ok(app_path)) = &app_path {
+            plugins_dirs.push(Self::app_plugins_dir(app_path));
+        }
+
+        if let Some(plugin_dir) = ruby_plugin::find(&plugins_dirs) {
+            return Ok(plugin_dir);
+        }
+        match app_path {
+            // Keep the bundled location, which the IDE fills in once it's installed
+            Some(app_path) => Ok(Self::app_plugins_dir(&app_path?).join("ruby")),
+            None => anyhow::bail!(
+                "Ruby plugin not found for {}; install it from the marketplace",
+                config_dir.display()
+            ),
+        }
+    }
+
+    /// Plugins live under Contents/ in macOS app bundles and at the top level elsewhere
+    fn app_plugins_dir(rubymine_app_path: &Path) -> PathBuf {
+        let contents_dir = rubymine_app_path.join("Contents");
+        if contents_dir.is_dir() {
+            contents_dir.join("plugins")
+        } else {
+            rubymine_app_path.join("plugins")
+        }
+    }
+
+    fn find_workspace_files(&self) -> Result<Vec<PathBuf>> {
+        let mut workspace_files = Vec::new();
+
+        // 1. Check for project-specific .idea/workspace.xml
+        let project_workspace = self.idea_dir().join("workspace.xml");
+        if project_workspace.exists() {
+            workspace_files.push(project_workspace);
+        }
+
+        // 2. Find global workspace files in the RubyMine config and system directories
+        let rubymine_config_dir = self.rubymine_config_dir()?;
+        let mut workspace_dirs = vec![rubymine_config_dir.join("workspace")];
+        if let Some(system_dir) = self.rubymine_system_dir(&rubymine_config_dir) {
+            let workspace_dir = system_dir.join("workspace");
+            if !workspace_dirs.contains(&workspace_dir) {
+                workspace_dirs.push(workspace_dir);
+            }
+        }
+
+        for workspace_dir in workspace_dirs.iter().filter(|dir| dir.exists()) {
+            for entry in fs::read_dir(workspace_dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                if path.extension().and_then(|s| s.to_str()) == Some("xml") {
+                    // Check if this workspace file contains our project
+                    if self.workspace_contains_project(&path)? {
+                        workspace_files.push(path);
+                    }
+                }
+            }
+        }
+
+        Ok(workspace_files)
+    }
+
+    fn workspace_contains_project(&self, workspace_file: &Path) -> Result<bool> {
+        let content = fs::read_to_string(workspace_file)?;
+
+        // Look for project references in the workspace XML
+        // This is a simple heuristic - could be made more robust
+        Ok(content.contains(&self.current_dir)
+            || content.contains("$PROJECT_DIR$")
+            || content.contains(&self.project_name))
+    }
+
+    fn create_minitest_config(&self) -> Result<()> {
+        let ruby_plugin_dir = self.ruby_plugin_dir()?;
+        let workspace_files = self.find_workspace_files()?;
+
+        if workspace_files.is_empty() {
+            if self.dry_run {
+                self.out
+                    .line("# No workspace files found for the current project");
+            } else {
+                self.out
+                    .line("No workspace files found for the current project");
+            }
+            return Ok(());
+        }
+
+        let patch_dir = ruby_plugin::patch_dir(&ruby_plugin_dir);
+        if !patch_dir.is_dir() {
+            warnings::warn(
+                &self.out,
+                format!(
+                    "{} doesn't exist, so test runs won't report their results to {}",
+                    patch_dir.display(),
+                    self.ide.display_name()
+                ),
+            );
+        }
+        let ruby_args = self.generate_ruby_args(&ruby_plugin_dir);
+        let mut updated = false;
+
+        if self.dry_run {
+            self.out.line("# Minitest Configuration Updates:");
+            self.out
+                .line(format!("# Ruby plugin path: {}", ruby_plugin_dir.display()));
+            self.out.line(format!("# Updated RUBY_ARGS: {}", ruby_args));
+            self.out.line(format!("# {}", "=".repeat(50)));
+            self.out.line("");
+        } else {
+            self.out.line("Updating Minitest configuration...");
+            self.out
+                .line(format!("Ruby plugin path: {}", ruby_plugin_dir.display()));
+        }
+
+        for workspace_file in &workspace_files {
+            if self.dry_run {
+                self.out
+                    .line(format!("# Workspace file: {}", workspace_file.display()));
+
+                // Show what the updated configuration would look like
+                if let Ok(content) =
+                    self.preview_minitest_config_changes(workspace_file, &ruby_args)
+                {
+                    self.out.line(content);
+                } else {
+                    self.out.line("# Unable to preview changes for this file");
+                }
+                self.out.line("");
+            } else {
+                self.out
+                    .line(format!("Updating: {}", workspace_file.display()));
+                if self.update_workspace_minitest_config(workspace_file, &ruby_args)? {
+                    updated = true;
+                } else {
+                    self.out
+                        .line(format!("Already up to date: {}", workspace_file.display()));
+                }
+            }
+        }
+
+        if !self.dry_run {
+            if updated {
+                self.out
+                    .line("Minitest configuration updated successfully!");
+                self.out
+                    .line("Restart RubyMine to see the updated test template configuration");
+            } else {
+                self.out.line("Minitest configuration already up to date");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn generate_ruby_args(&self, ruby_plugin_dir: &Path) -> String {
+        let plugin_path = ruby_plugin::patch_dir(ruby_plugin_dir);
+
+        [
+            plugin_path.join("common"),
+            plugin_path.join("bdd"),
+            plugin_path.join("rake"),
+            plugin_path.join("testunit"),
+        ]
+        .iter()
+        .map(|path| {
+            let arg = format!("-I{}", Self::xml_path(&path.to_string_lossy()));
+            // Program Files paths contain spaces; quote them so the IDE keeps them whole
+            if arg.contains(' ') {
+                format!("\"{}\"", arg)
+            } else {
+                arg
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+    }
+
+    /// Sets RUBY_ARGS in the Minitest template and merges the test environment into
+    /// test run configurations of the workspace.xml at `workspace_file`, writing it
+    /// back (with a backup) when that changes it. Returns whether it did.
+    pub fn update_workspace_minitest_config(
+        &self,
+        workspace_file: &Path,
+        ruby_args: &str,
+    ) -> Result<bool> {
+        let mut written = false;
+        safe_write::read_modify_write(
+            workspace_file,
+            || {
+                let xml_content = fs::read_to_string(workspace_file)?;
+                self.splice_workspace(&xml_content, ruby_args)
+            },
+            |content| {
+                written = true;
+                self.write_config_file(workspace_file, content)
+            },
+            &self.out,
+        )?;
+        Ok(written)
+    }
+
+    /// workspace.xml with RUBY_ARGS set and the test environment merged into test
+    /// configurations, spliced in so that the rest of the file is left exactly as it
+    /// was, or `None` when there's nothing to update
+    fn splice_workspace(&self, xml_content: &str, ruby_args: &str) -> Result<Option<String>> {
+        let doc = Document::parse(xml_content)?;
+        let mut splice = Splice::new(xml_content);
+        let mut updated = false;
+
+        for node in doc.descendants().filter(|node| node.is_element()) {
+            if node.has_tag_name("RTEST_RUN_CONFIG_SETTINGS_ID")
+                && node.attribute("NAME") == Some("RUBY_ARGS")
+            {
+                splice.set_attribute(&node, "VALUE", ruby_args);
+                updated = true;
+            }
+
+            // Test configurations also get the project's gem/bundler environment
+            if !node.has_tag_name("configuration") {
+                continue;
+            }
+            if self.shared_env && Self::is_test_or_template(&node) {
+                let path = self.shared_env_file().to_string_lossy().to_string();
+                updated |= shared_env::reference(&mut splice, &node, &path)?;
+            }
+            let envs = self.run_config_env(&node);
+            if envs.is_empty() {
+                continue;
+            }
+            let existing = node.children().find(|child| child.has_tag_name("envs"));
+            let block = xml_splice::fragment(|writer| {
+                Self::write_merged_envs(writer, existing.as_ref(), &envs);
+                Ok(())
+            })?;
+            match existing {
+                Some(existing) => splice.replace(&existing, &block),
+                None => splice.append_child(&node, &block),
+            }
+            updated = true;
+        }
+
+        Ok(Some(splice.finish()).filter(|content| updated && content != xml_content))
+    }
+
+    /// Writes an `<envs>` block with the existing variables plus `envs` (which win on conflict)
+    fn write_merged_envs(
+        writer: &mut XmlWriter,
+        existing: Option<&roxmltree::Node>,
+        envs: &BTreeMap<String, String>,
+    ) {
+        writer.start_element("envs");
+        if let Some(existing) = existing {
+            for env in existing.children().filter(|n| n.tag_name().name() == "env") {
+                let name = env.attribute("name").unwrap_or_default();
+                if !envs.contains_key(name) {
+                    writer.start_element("env");
+                    xml_misc::copy_attributes(writer, &env);
+                    writer.end_element();
+                }
+            }
+        }
+        for (name, value) in envs {
+            writer.start_element("env");
+            xml_misc::write_attribute(writer, "name", name);
+            xml_misc::write_attribute(writer, "value", value);
+            writer.end_element();
+        }
+        writer.end_element(); // envs
+    }
+
+    fn preview_minitest_config_changes(
+        &self,
+        workspace_file: &Path,
+        ruby_args: &str,
+    ) -> Result<String> {
+        let xml_content = fs::read_to_string(workspace_file)?;
+        let doc = Document::parse(&xml_content)?;
+
+        // Check if there are any Minitest configurations
+        let has_minitest_config = doc.descendants().any(|node| {
+            node.tag_name().name() == "configuration"
+                && (node.attribute("type") == Some("TestUnitRunConfigurationType")
+                    || (self.env_snapshot && node.attribute("default") == Some("true")))
+        });
+
+        if !has_minitest_config {
+            return Ok("# No Minitest configurations found in this workspace file".to_string());
+        }
+
+        Ok(self
+            .splice_workspace(&xml_content, ruby_args)?
+            .unwrap_or(xml_content))
+    }
+
+    /// Where MYSQL_* settings are looked up, in the configured precedence
+    fn env_chain(&self) -> EnvChain {
+        EnvChain::new(
+            &self.env_precedence,
+            Path::new(&self.current_dir),
+            &self.project_env,
+            self.my_cnf,
+        )
+    }
+
+    /// Connection settings from the MYSQL_* variables, each taken from the first source
+    /// in the configured precedence that sets it. With `--my-cnf`, ~/.my.cnf is one of
+    /// those sources and the host and port default like the mysql client's.
+    fn read_mysql_config(&self) -> Option<ServerConfig> {
+        let chain = self.env_chain();
+
+        // Like the mysql client, ~/.my.cnf users get the default host and port
+        let host = chain
+            .get("MYSQL_HOST")
+            .or_else(|| self.my_cnf.then(|| "localhost".to_string()))?;
+        let port = chain
+            .get("MYSQL_PORT")
+            .or_else(|| self.my_cnf.then(|| "3306".to_string()))?;
+        let user = chain.get("MYSQL_USER")?;
+        let password = chain.get("MYSQL_PASSWORD").unwrap_or_default();
+
+        Some(ServerConfig {
+            host,
+            port,
+            user,
+            password,
+        })
+    }
+
+    /// Connection settings from the PG* variables libpq reads, looked up like the
+    /// MYSQL_* ones, or `None` when none is set
+    fn read_postgres_config(&self) -> Option<ServerConfig> {
+        let chain = self.env_chain();
+        let vars = ["PGHOST", "PGPORT", "PGUSER", "PGPASSWORD"];
+        if vars.iter().all(|var| chain.get(var).is_none()) {
+            return None;
+        }
+        let (port, user) = Driver::Postgresql.defaults();
+        Some(ServerConfig {
+            host: chain
+                .get("PGHOST")
+                .unwrap_or_else(|| "localhost".to_string()),
+            port: chain.get("PGPORT").unwrap_or_else(|| port.to_string()),
+            user: chain.get("PGUSER").unwrap_or_else(|| user.to_string()),
+            password: chain.get("PGPASSWORD").unwrap_or_default(),
+        })
+    }
+
+    /// Prints every source of `var` in precedence order and which one wins
+    fn explain_env(&self, var: &str) {
+        let order: Vec<String> = self.env_precedence.iter().map(|s| s.to_string()).collect();
+        self.out
+            .line(format!("{} (precedence: {})", var, order.join(", ")));
+        let mut winner = None;
+        for candidate in self.env_chain().explain(var) {
+            let status = match (&candidate.skipped, &candidate.value) {
+                (Some(reason), _) => format!("skipped: {}", reason),
+                (None, None) => "not set".to_string(),
+                (None, Some(value)) => {
+                    let origin = candidate
+                        .origin
+                        .map(|origin| format!(" ({})", origin.display()))
+                        .unwrap_or_default();
+                    match winner {
+                        None => {
+                            winner = Some(candidate.source);
+                            format!("{:?}{}  <- used", value, origin)
+                        }
+                        Some(winner) => {
+                            format!("{:?}{}, overridden by {}", value, origin, winner)
+                        }
+                    }
+                }
+            };
+            self.out
+                .line(format!("  {:<10} {}", candidate.source.to_string(), status));
+        }
+        if winner.is_none() {
+            self.out.line(format!("No source sets {}", var));
+        }
+    }
+
+    /// Data sources for the selected database.yml environments. Connection settings
+    /// missing from database.yml come from the MYSQL_* variables, or the PG* ones for
+    /// PostgreSQL data sources. Without a usable database.yml, a single data source is
+    /// built from the variables of the configured driver alone.
+    pub fn data_sources(&self, envs: &[DatasourceEnv]) -> Result<Vec<DataSource>> {
+        let mysql_config = self.read_mysql_config();
+        let postgres_config = self.read_postgres_config();
+        let database_yml = Path::new(&self.current_dir)
+            .join("config")
+            .join("database.yml");
+        // Each entry is kept when its adapter is one its data source's driver connects to
+        let databases: Vec<DatabaseConfig> = database_yml::load(&database_yml)?
+            .into_iter()
+            .filter(|database| {
+                let (driver, _) = self.driver_for(&database.label());
+                driver.reads_adapter(&database.adapter)
+            })
+            .collect();
+
+        if databases.is_empty() {
+            let default_driver = self.driver.driver.unwrap_or_default();
+            if default_driver == Driver::Postgresql {
+                // Only a server named in PGHOST, like MYSQL_HOST for MySQL
+                let postgres_config =
+                    postgres_config.filter(|_| self.env_chain().get("PGHOST").is_some());
+                return Ok(Vec::from_iter(postgres_config.map(|config| {
+                    let name = format!("@{}", config.host);
+                    let (driver, driver_version) = self.driver_for(&name);
+                    DataSource {
+                        name,
+                        host: config.host,
+                        port: config.port,
+                        user: config.user,
+                        password: config.password,
+                        schemas: Vec::from_iter(self.env_chain().get("PGDATABASE")),
+                        driver,
+                        driver_version,
+                    }
+                })));
+            }
+            return Ok(Vec::from_iter(mysql_config.map(|mysql_config| {
+                let name = format!("@{}", mysql_config.host);
+                let (driver, driver_version) = self.driver_for(&name);
+                DataSource {
+                    name,
+                    host: mysql_config.host,
+                    port: mysql_config.port,
+                    user: mysql_config.user,
+                    password: mysql_config.password,
+                    schemas: [
+                        "@",
+                        "storefront_renderer_test_master",
+                        "storefront_renderer_test_shard",
+                        "storefront_renderer_dev_shard",
+                    ]
+                    .map(String::from)
+                    .to_vec(),
+                    driver,
+                    driver_version,
+                }
+            })));
+        }
+
+        let selected = |environment: &str| {
+            if envs.is_empty() {
+                return environment == "development" || environment == "test";
+            }
+            envs.iter().any(|env| match env {
+                DatasourceEnv::Development => environment == "development",
+                DatasourceEnv::Test => environment == "test",
+                DatasourceEnv::All => true,
+            })
+        };
+
+        Ok(databases
+            .into_iter()
+            .filter(|database| selected(&database.environment))
+            .map(|database| {
+                let name = database.label();
+                let (driver, driver_version) = self.driver_for(&name);
+                let fallback = match driver {
+                    Driver::Postgresql => postgres_config.as_ref(),
+                    Driver::Mysql8 | Driver::Mariadb => mysql_config.as_ref(),
+                };
+                let (default_port, default_user) = driver.defaults();
+                DataSource {
+                    name,
+                    host: database
+                        .host
+                        .clone()
+                        .or_else(|| fallback.map(|config| config.host.clone()))
+                        .unwrap_or_else(|| "localhost".to_string()),
+                    port: database
+                        .port
+                        .clone()
+                        .or_else(|| fallback.map(|config| config.port.clone()))
+                        .unwrap_or_else(|| default_port.to_string()),
+                    user: database
+                        .username
+                        .clone()
+                        .or_else(|| fallback.map(|config| config.user.clone()))
+                        .unwrap_or_else(|| default_user.to_string()),
+                    password: database
+                        .password
+                        .clone()
+                        .or_else(|| fallback.map(|config| config.password.clone()))
+                        .unwrap_or_default(),
+                    schemas: Vec::from_iter(database.database.clone()),
+                    driver,
+                    driver_version,
+                }
+            })
+            .collect())
+    }
+
+    /// The driver and driver version for the data source named `name`: its entry in
+    /// datasource_drivers, then driver and driver_version, then MySQL 8
+    fn driver_for(&self, name: &str) -> (Driver, String) {
+        let setting = self.datasource_drivers.get(name);
+        let driver = setting
+            .and_then(|setting| setting.driver)
+            .or(self.driver.driver)
+            .unwrap_or_default();
+        let version = setting
+            .and_then(|setting| setting.version.clone())
+            .or_else(|| self.driver.version.clone())
+            .unwrap_or_else(|| driver.default_version().to_string());
+        (driver, version)
+    }
+
+    /// Adds a run configuration that attaches to `debugger` to .idea/workspace.xml
+    fn configure_debugger(&self, debugger: Debugger) -> Result<()> {
+        let port = self.debug_port.unwrap_or(debugger.default_port());
+        let remote_host = match self.docker.as_ref().map(|docker| &docker.container) {
+            Some(Container::Ssh(host)) => host.hostname.as_str(),
+            _ => "localhost",
+        };
+        let config = AttachConfig {
+            name: format!("Attach to {} ({})", debugger.name(), self.project_name),
+            module: &self.project_name,
+            debugger,
+            remote_host,
+            port,
+            remote_root: self
+                .docker
+                .as_ref()
+                .map_or("$PROJECT_DIR$", |docker| docker.container_dir.as_str()),
+        };
+
+        let workspace_file = self.idea_dir().join("workspace.xml");
+
+        if self.dry_run {
+            self.out
+                .line(format!("# Debugger configuration: {}", config.name));
+            self.out
+                .line(format!("# Workspace file: {}", workspace_file.display()));
+            self.out.line(format!(
+                "# Start the app with: {}",
+                debugger.start_command(port)
+            ));
+            self.out.line(format!("# {}", "=".repeat(50)));
+            self.out.line("");
+            return Ok(());
+        }
+
+        let Some(content) = self.update_config_file(&workspace_file, |existing| {
+            let existing = existing.unwrap_or(EMPTY_WORKSPACE);
+            let content = config.apply(existing)?;
+            Ok((content != existing).then_some(content))
+        })?
+        else {
+            return Ok(());
+        };
+        self.record_artifacts(
+            "runconfig",
+            &workspace_file,
+            &[config.name.as_str()],
+            &content,
+        )?;
+        self.out
+            .line(format!("Debugger configuration written: {}", config.name));
+        self.out.line(format!(
+            "Start the app with: {}",
+            debugger.start_command(port)
+        ));
+        Ok(())
+    }
+
+    /// Adds a run configuration for each script declared in config to .idea/workspace.xml
+    fn configure_scripts(&self) -> Result<()> {
+        scripts::check_names(&self.scripts)?;
+        let configurator = match &self.docker {
+            None => self.configurator_options(),
+            Some(docker) => {
+                let shell_scripts: Vec<&str> = self
+                    .scripts
+                    .iter()
+                    .filter(|script| script.kind() == scripts::ScriptKind::Shell)
+                    .map(|script| script.name.as_str())
+                    .collect();
+                if !shell_scripts.is_empty() {
+                    warnings::warn(&self.out, format!(
+                        "shell scripts run on this machine, outside the {} interpreter's environment: {}",
+                        docker.container.kind(),
+                        shell_scripts.join(", ")
+                    ));
+                }
+                Vec::new()
+            }
+        };
+
+        let workspace_file = self.idea_dir().join("workspace.xml");
+        let names: Vec<&str> = self
+            .scripts
+            .iter()
+            .map(|script| script.name.as_str())
+            .collect();
+
+        if self.dry_run {
+            self.out
+                .line(format!("# Script configurations: {}", names.join(", ")));
+            self.out
+                .line(format!("# Workspace file: {}", workspace_file.display()));
+            self.out.line(format!("# {}", "=".repeat(50)));
+            self.out.line("");
+            return Ok(());
+        }
+
+        let Some(content) = self.update_config_file(&workspace_file, |existing| {
+            let existing = existing.unwrap_or(EMPTY_WORKSPACE);
+            let content =
+                scripts::apply(existing, &self.scripts, &self.project_name, &configurator)?;
+            Ok((content != existing).then_some(content))
+        })?
+        else {
+            return Ok(());
+        };
+        self.record_artifacts("runconfig", &workspace_file, &names, &content)?;
+        self.out.line(format!(
+            "Script configurations written: {}",
+            names.join(", ")
+        ));
+        Ok(())
+    }
+
+    /// The project's module file, as listed in .idea/modules.xml
+    fn module_file(&self) -> PathBuf {
+        let modules = fs::read_to_string(self.idea_dir().join("modules.xml")).unwrap_or_default();
+        Document::parse(&modules)
+            .ok()
+            .and_then(|doc| {
+                doc.descendants()
+                    .filter(|node| node.has_tag_name("module"))
+                    .find_map(|node| node.attribute("filepath").map(|path| path.to_string()))
+            })
+            .map(|path| PathBuf::from(path.replace("$PROJECT_DIR$", &self.current_dir)))
+            .unwrap_or_else(|| self.idea_dir().join(format!("{}.iml", self.project_name)))
+    }
+
+    /// Turns on the RBS-aware features and marks sig/ as a source root
+    fn configure_rbs(&self) -> Result<()> {
+        let project_dir = Path::new(&self.current_dir);
+        let settings_file = self.idea_dir().join("rbs.xml");
+        let settings = markers::stamp(&rbs::settings_xml(project_dir))?;
+        let module_file = self.module_file();
+        let mark_sig_dir = |iml: Option<&str>| match iml {
+            Some(iml) => rbs::mark_source_root(iml),
+            None => Ok(None),
+        };
+
+        if self.dry_run {
+            self.out
+                .line(format!("# RBS settings: {}", settings_file.display()));
+            self.out.line(settings);
+            match mark_sig_dir(fs::read_to_string(&module_file).ok().as_deref())? {
+                Some(_) => self.out.line(format!(
+                    "# Marking {} as a source root in {}",
+                    rbs::SIG_DIR,
+                    module_file.display()
+                )),
+                None => self.out.line(format!(
+                    "# {} is already a source root (or there is no module file yet)",
+                    rbs::SIG_DIR
+                )),
+            }
+            self.out.line(format!("# {}", "=".repeat(50)));
+            self.out.line("");
+            return Ok(());
+        }
+
+        let settings_written = self.update_config_file(&settings_file, |existing| {
+            if existing == Some(settings.as_str()) {
+                return Ok(None);
+            }
+            self.warn_if_edited(&settings_file);
+            Ok(Some(settings.clone()))
+        })?;
+        if settings_written.is_some() {
+            self.out
+                .line(format!("RBS settings written: {}", settings_file.display()));
+        }
+        if self
+            .update_config_file(&module_file, mark_sig_dir)?
+            .is_some()
+        {
+            self.out.line(format!(
+                "Marked {} as a source root in {}",
+                rbs::SIG_DIR,
+                module_file.display()
+            ));
+        } else if !module_file.exists() {
+            self.out.line(format!(
+                "Open the project in {} once, then rerun to mark {} as a source root",
+                self.ide.display_name(),
+                rbs::SIG_DIR
+            ));
+        }
+        Ok(())
+    }
+
+    /// Points the integrated terminal at the user's login shell, started through the
+    /// configurator, so `bundle exec` there sees what the interpreter sees
+    fn configure_terminal(&self) -> Result<()> {
+        if let Some(docker) = &self.docker {
+            warnings::warn(&self.out, format!(
+                "--terminal doesn't apply to a {} interpreter; the terminal keeps running on this machine",
+                docker.container.kind()
+            ));
+            return Ok(());
+        }
+        let shell =
+            env::var("SHELL").context("Set SHELL to the shell the terminal should start")?;
+        let command = terminal::shell_command(&self.configurator_options(), &shell);
+        let workspace_file = self.idea_dir().join("workspace.xml");
+
+        if self.dry_run {
+            self.out.line(format!(
+                "# Terminal shell in {}: {}",
+                workspace_file.display(),
+                command
+            ));
+            self.out.line("");
+            return Ok(());
+        }
+
+        safe_write::read_modify_write(
+            &workspace_file,
+            || match fs::read_to_string(&workspace_file) {
+                Ok(xml) if terminal::shell_path(&xml)?.as_deref() == Some(command.as_str()) => {
+                    Ok(None)
+                }
+                Ok(xml) => terminal::set_shell_path(&xml, &command).map(Some),
+                Err(_) => Ok(Some(terminal::new_workspace(&command))),
+            },
+            |content| {
+                self.fs.create_dir_all(&self.idea_dir())?;
+                self.write_config_file(&workspace_file, content)?;
+                self.out.line(format!("Terminal shell set to: {}", command));
+                Ok(())
+            },
+            &self.out,
+        )
+    }
+
+    fn idea_dir(&self) -> PathBuf {
+        idea_dir::path(Path::new(&self.current_dir))
+    }
+
+    /// Keeps writes from following a .idea symlink out of the project or a directory
+    /// a subproject sharing its data sources, where they'd change settings other
+    /// checkouts share
+    fn guard_idea_symlinks(&mut self, follow: bool) {
+        let mut project_dirs = vec![PathBuf::from(&self.current_dir)];
+        project_dirs.extend(
+            self.datasource_subprojects
+                .iter()
+                .map(|dir| Path::new(&self.current_dir).join(dir)),
+        );
+        for project_dir in project_dirs {
+            let Some(target) = idea_dir::outside_target(&project_dir) else {
+                continue;
+            };
+            let link = idea_dir::path(&project_dir);
+            if follow {
+                self.out.status(format!(
+                    "Writing through {}, a symlink to {}",
+                    link.display(),
+                    target.display()
+                ));
+                continue;
+            }
+            let reason = format!(
+                "{} is a symlink to {}, outside the project; pass --follow-idea-symlink to write there anyway",
+                link.display(),
+                target.display()
+            );
+            self.fs.protect(link, reason.clone());
+            self.fs.protect(target, reason);
+        }
+    }
+
+    fn datasources_xml_path(&self) -> PathBuf {
+        self.idea_dir().join("dataSources.xml")
+    }
+
+    /// UUIDs of the data sources already in dataSources.xml, by name, so that
+    /// regenerating the file keeps the IDE's per-source state attached
+    fn existing_datasource_uuids(&self) -> Result<HashMap<String, String>> {
+        let datasources_path = self.datasources_xml_path();
+        let mut uuids = HashMap::new();
+
+        if datasources_path.exists() {
+            let content = fs::read_to_string(&datasources_path)?;
+            let doc = Document::parse(&content)?;
+
+            for node in doc.descendants() {
+                if node.tag_name().name() == "data-source" {
+                    if let (Some(name), Some(uuid)) =
+                        (node.attribute("name"), node.attribute("uuid"))
+                    {
+                        uuids.insert(name.to_string(), uuid.to_string());
+                    }
+                }
+            }
+        }
+
+        Ok(uuids)
+    }
+
+    /// Pairs each data source with its existing UUID, or a new one
+    pub fn with_uuids(&self, data_sources: Vec<DataSource>) -> Result<Vec<(DataSource, String)>> {
+        let existing_uuids = self.existing_datasource_uuids()?;
+        Ok(data_sources
+            .into_iter()
+            .map(|data_source| {
+                let uuid = existing_uuids
+                    .get(&data_source.name)
+                    .cloned()
+                    .unwrap_or_else(|| Uuid::new_v4().to_string());
+                (data_source, uuid)
+            })
+            .collect())
+    }
+
+    /// dataSources.xml for `data_sources` (from `with_uuids`): the shared half of
+    /// each data source, without credentials
+    pub fn create_datasources_xml(&self, data_sources: &[(DataSource, String)]) -> String {
+        let mut writer = XmlWriter::new(Options::default());
+        writer.write_declaration();
+
+        writer.start_element("project");
+        writer.write_attribute("version", "4");
+
+        writer.start_element("component");
+        writer.write_attribute("name", "DataSourceManagerImpl");
+        writer.write_attribute("format", "xml");
+        writer.write_attribute("multifile-model", "true");
+        Self::write_datasource_elements(&mut writer, data_sources);
+        writer.end_element(); // component
+        writer.end_element(); // project
+
+        writer.end_document()
+    }
+
+    fn write_datasource_elements(writer: &mut XmlWriter, data_sources: &[(DataSource, String)]) {
+        for (data_source, uuid) in data_sources {
+            writer.start_element("data-source");
+            writer.write_attribute("source", "LOCAL");
+            xml_misc::write_attribute(writer, "name", &data_source.name);
+            xml_misc::write_attribute(writer, "uuid", uuid);
+
+            writer.start_element("driver-ref");
+            writer.write_text(data_source.driver.driver_ref());
+            writer.end_element();
+
+            writer.start_element("synchronize");
+            writer.write_text("true");
+            writer.end_element();
+
+            writer.start_element("jdbc-driver");
+            writer.write_text(data_source.driver.jdbc_class());
+            writer.end_element();
+
+            writer.start_element("jdbc-url");
+            writer.write_text(
+                &data_source
+                    .driver
+                    .jdbc_url(&data_source.host, &data_source.port),
+            );
+            writer.end_element();
+
+            writer.start_element("jdbc-additional-properties");
+
+            writer.start_element("property");
+            writer.write_attribute("name", "com.intellij.clouds.kubernetes.db.enabled");
+            writer.write_attribute("value", "false");
+            writer.end_element();
+
+            writer.end_element(); // jdbc-additional-properties
+
+            writer.start_element("working-dir");
+            writer.write_text("$ProjectFileDir$");
+            writer.end_element();
+
+            writer.end_element(); // data-source
+        }
+    }
+
+    /// dataSources.local.xml for `data_sources`: the half of each one that stays on
+    /// this machine (user, server details, schemas to introspect)
+    pub fn create_datasources_local_xml(&self, data_sources: &[(DataSource, String)]) -> String {
+        let mut writer = XmlWriter::new(Options::default());
+        writer.write_declaration();
+
+        writer.start_element("project");
+        writer.write_attribute("version", "4");
+
+        writer.start_element("component");
+        writer.write_attribute("name", "dataSourceStorageLocal");
+        writer.write_attribute("created-in", "RM-233.15026.15");
+        Self::write_datasource_local_elements(&mut writer, data_sources);
+        writer.end_element(); // component
+        writer.end_element(); // project
+
+        writer.end_document()
+    }
+
+    fn write_datasource_local_elements(
+        writer: &mut XmlWriter,
+        data_sources: &[(DataSource, String)],
+    ) {
+        for (data_source, uuid) in data_sources {
+            writer.start_element("data-source");
+            xml_misc::write_attribute(writer, "name", &data_source.name);
+            xml_misc::write_attribute(writer, "uuid", uuid);
+
+            let driver = data_source.driver;
+            let (product, dbms) = driver.product();
+            // The server version is only known for MySQL; the IDE fills it in for the
+            // others on the first connection
+            let server_version = (driver == Driver::Mysql8).then_some("8.0.11");
+            writer.start_element("database-info");
+            writer.write_attribute("product", product);
+            if let Some(version) = server_version {
+                writer.write_attribute("version", version);
+            }
+            writer.write_attribute("jdbc-version", "4.2");
+            writer.write_attribute("driver-name", driver.driver_name());
+            xml_misc::write_attribute(writer, "driver-version", &data_source.driver_version);
+            writer.write_attribute("dbms", dbms);
+            if let Some(version) = server_version {
+                writer.write_attribute("exact-version", version);
+            }
+            xml_misc::write_attribute(
+                writer,
+                "exact-driver-version",
+                &jdbc::exact_version(&data_source.driver_version),
+            );
+
+            let (extra_name_characters, quote) = driver.identifier_syntax();
+            if !extra_name_characters.is_empty() {
+                writer.start_element("extra-name-characters");
+                writer.write_text(extra_name_characters);
+                writer.end_element();
+            }
+
+            writer.start_element("identifier-quote-string");
+            writer.write_text(quote);
+            writer.end_element();
+
+            writer.end_element(); // database-info
+
+            writer.start_element("case-sensitivity");
+            writer.write_attribute("plain-identifiers", "lower");
+            writer.write_attribute("quoted-identifiers", "lower");
+            writer.end_element();
+
+            writer.start_element("secret-storage");
+            writer.write_text("master_key");
+            writer.end_element();
+
+            writer.start_element("user-name");
+            writer.write_text(&data_source.user);
+            writer.end_element();
+
+            writer.start_element("schema-mapping");
+            writer.start_element("introspection-scope");
+
+            for schema in &data_source.schemas {
+                writer.start_element("node");
+                writer.write_attribute("kind", "schema");
+                xml_misc::write_attribute(writer, "qname", schema);
+                writer.end_element();
+            }
+
+            writer.end_element(); // introspection-scope
+            writer.end_element(); // schema-mapping
+
+            writer.end_element(); // data-source
+        }
+    }
+
+    /// The directories of the subprojects sharing the project's data sources, which
+    /// must exist
+    fn datasource_subproject_dirs(&self) -> Result<Vec<PathBuf>> {
+        self.datasource_subprojects
+            .iter()
+            .map(|dir| {
+                let dir = Path::new(&self.current_dir).join(dir);
+                if !dir.is_dir() {
+                    anyhow::bail!(
+                        "Not a directory to share data sources with: {}",
+                        dir.display()
+                    );
+                }
+                Ok(dir)
+            })
+            .collect()
+    }
+
+    /// The root project's data source files, when this project is a subproject whose
+    /// dataSources.xml refers to them and the root still lists it in
+    /// `datasource_subprojects`. A subproject is never configured on its own then, so
+    /// that its definitions can't drift from the root's.
+    fn shared_datasource_root(&self) -> Result<Option<(PathBuf, PathBuf)>> {
+        let idea_dir = self.idea_dir();
+        let Ok(content) = fs::read_to_string(idea_dir.join("dataSources.xml")) else {
+            return Ok(None);
+        };
+        let Some(root_file) = subprojects::root_file(&content) else {
+            return Ok(None);
+        };
+        let Some(root_dir) = root_file.parent().and_then(Path::parent) else {
+            return Ok(None);
+        };
+        let listed = Config::project_file(root_dir)?
+            .datasource_subprojects
+            .unwrap_or_default()
+            .iter()
+            .any(|dir| same_file(&root_dir.join(dir), Path::new(&self.current_dir)));
+        if !listed || !root_file.is_file() {
+            warnings::warn(
+                &self.out,
+                format!(
+                    "{} no longer shares its data sources with this project; configuring its own",
+                    root_dir.display()
+                ),
+            );
+            return Ok(None);
+        }
+        let root_local_file = root_file.with_file_name("dataSources.local.xml");
+        Ok(Some((root_file, root_local_file)))
+    }
+
+    /// Records the `kind` entries called `names` in `file`, which holds `content`, as
+    /// the tool's own in the state file, and forgets recorded ones `content` no
+    /// longer has
+    fn record_artifacts(
+        &self,
+        kind: &str,
+        file: &Path,
+        names: &[&str],
+        content: &str,
+    ) -> Result<()> {
+        if self.project_only {
+            return Ok(());
+        }
+        let doc = Document::parse(content)?;
+        let present: Vec<String> = match kind {
+            "interpreter" => doc
+                .descendants()
+                .filter_map(|node| JdkEntry::parse(&node))
+                .map(|entry| entry.name)
+                .collect(),
+            _ => {
+                let element = if kind == "datasource" {
+                    "data-source"
+                } else {
+                    "configuration"
+                };
+                doc.descendants()
+                    .filter(|node| node.has_tag_name(element))
+                    .filter_map(|node| node.attribute("name"))
+                    .map(str::to_string)
+                    .collect()
+            }
+        };
+        let mut state = State::load()?;
+        let mut changed = state.forget_missing(kind, file, &present);
+        for name in names
+            .iter()
+            .filter(|name| present.iter().any(|p| p == *name))
+        {
+            changed |= state.record(Artifact {
+                tag: self.tag.clone(),
+                created_at: Local::now().to_rfc3339(),
+                project_dir: self.current_dir.clone(),
+                kind: kind.to_string(),
+                name: name.to_string(),
+                file: file.to_path_buf(),
+            });
+        }
+        if !changed {
+            return Ok(());
+        }
+        state.save(&self.fs)
+    }
+
+    /// Warns when a file stamped by `markers::stamp` was changed by hand since it was
+    /// generated. Files without a marker pass.
+    fn warn_if_edited(&self, path: &Path) {
+        let Ok(content) = fs::read_to_string(path) else {
+            return;
+        };
+        if markers::check(&content) == MarkerStatus::Edited {
+            warnings::warn(&self.out, format!(
+                "{} was edited since it was generated; overwriting it (the backup keeps your changes)",
+                path.display()
+            ));
+        }
+    }
+
+    /// `warn_if_edited` for `block` in `content`, the file at `path`, which the IDE
+    /// shares with the tool
+    fn warn_if_block_edited(&self, path: &Path, content: &str, block: &markers::Block) {
+        if markers::check_block(content, block) == MarkerStatus::Edited {
+            warnings::warn(&self.out, format!(
+                "the {} in {} was edited since it was written; overwriting it (the backup keeps your changes)",
+                block.name,
+                path.display()
+            ));
+        }
+    }
+
+    fn write_datasource_files(
+        &self,
+        idea_dir: &Path,
+        datasources_xml: &str,
+        datasources_local_xml: &str,
+    ) -> Result<()> {
+        // Ensure .idea directory exists
+        if !idea_dir.exists() {
+            self.fs.create_dir_all(idea_dir)?;
+        }
+
+        // Write dataSources.xml
+        let datasources_path = idea_dir.join("dataSources.xml");
+        self.replace_datasource_file(&datasources_path, datasources_xml)?;
+        let doc = Document::parse(datasources_xml)?;
+        let names: Vec<&str> = doc
+            .descendants()
+            .filter(|node| node.has_tag_name("data-source"))
+            .filter_map(|node| node.attribute("name"))
+            .collect();
+        self.record_artifacts("datasource", &datasources_path, &names, datasources_xml)?;
+
+        // Write dataSources.local.xml
+        let datasources_local_path = idea_dir.join("dataSources.local.xml");
+        self.replace_datasource_file(&datasources_local_path, datasources_local_xml)?;
+
+        Ok(())
+    }
+
+    /// Writes `content` to the data source file at `path` unless it has it already
+    fn replace_datasource_file(&self, path: &Path, content: &str) -> Result<()> {
+        let written = self.update_config_file(path, |existing| {
+            if existing == Some(content) {
+                return Ok(None);
+            }
+            self.warn_if_edited(path);
+            Ok(Some(content.to_string()))
+        })?;
+        match written {
+            Some(_) => self.out.line(format!("Created: {}", path.display())),
+            None => self
+                .out
+                .line(format!("Already up to date: {}", path.display())),
+        }
+        Ok(())
+    }
+
+    /// Warns about data sources whose server doesn't accept connections, which the
+    /// IDE would only report once someone opened them. Skipped with --offline.
+    fn check_reachable(&self, data_sources: &[DataSource]) {
+        if self.offline {
+            return;
+        }
+        let mut checked = Vec::new();
+        for data_source in data_sources {
+            let address = (data_source.host.as_str(), data_source.port.as_str());
+            if checked.contains(&address) {
+                continue;
+            }
+            checked.push(address);
+            if !network::is_listening(&data_source.host, &data_source.port) {
+                warnings::warn(
+                    &self.out,
+                    format!(
+                        "{}: nothing accepts connections on {}:{}",
+                        data_source.name, data_source.host, data_source.port
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Replaces each data source's schema list with what its server has, when
+    /// discovery is on. Servers that can't be asked keep the list with a warning.
+    /// Fails with --offline, which discovery contradicts.
+    fn discover_schemas(&self, data_sources: &mut [DataSource]) -> Result<()> {
+        let Some(prefix) = &self.discover_schemas else {
+            return Ok(());
+        };
+        network::ensure_online(self.offline, "discovering schemas on the database servers")?;
+        for data_source in data_sources {
+            if data_source.driver == Driver::Postgresql {
+                warnings::warn(
+                    &self.out,
+                    format!(
+                        "{}: schema discovery only supports MySQL servers",
+                        data_source.name
+                    ),
+                );
+                continue;
+            }
+            match schema_discovery::show_databases(
+                &data_source.host,
+                &data_source.port,
+                &data_source.user,
+                &data_source.password,
+                prefix,
+            ) {
+                Ok(live) => {
+                    data_source.schemas = schema_discovery::scope(&data_source.schemas, live)
+                }
+                Err(err) => warnings::warn(
+                    &self.out,
+                    format!(
+                        "{}: couldn't discover schemas, keeping the configured ones: {:#}",
+                        data_source.name, err
+                    ),
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    fn configure_datasources(&self, envs: &[DatasourceEnv]) -> Result<()> {
+        if let Some((root_file, root_local_file)) = self.shared_datasource_root()? {
+            return self.configure_shared_datasources(&root_file, &root_local_file);
+        }
+        let mut data_sources = self.data_sources(envs)?;
+        self.discover_schemas(&mut data_sources)?;
+        if data_sources.is_empty() {
+            let message = "No database.yml environments or environment variables for the data source driver found, skipping datasource configuration";
+            if self.dry_run {
+                self.out.line(format!("# {}", message));
+            } else {
+                self.out.line(message);
+            }
+            return Ok(());
+        }
+
+        if self.strict {
+            self.check_reachable(&data_sources);
+        }
+        if self.dry_run {
+            self.out.line("# Data Source Configuration:");
+        } else {
+            self.out.line("Configuring datasources...");
+        }
+        let prefix = if self.dry_run { "# " } else { "" };
+        for data_source in &data_sources {
+            self.out.line(format!(
+                "{}{}: {}@{}:{}{} (password {})",
+                prefix,
+                data_source.name,
+                data_source.user,
+                data_source.host,
+                data_source.port,
+                data_source
+                    .schemas
+                    .first()
+                    .filter(|schema| *schema != "@")
+                    .map(|schema| format!("/{}", schema))
+                    .unwrap_or_default(),
+                if data_source.password.is_empty() {
+                    "empty"
+                } else {
+                    "set"
+                }
+            ));
+        }
+        if self.dry_run {
+            self.out.line(format!("# {}", "=".repeat(50)));
+            self.out.line("");
+        }
+
+        let data_sources = self.with_uuids(data_sources)?;
+
+        let datasources_xml = markers::stamp(&self.create_datasources_xml(&data_sources))?;
+        let datasources_local_xml =
+            markers::stamp(&self.create_datasources_local_xml(&data_sources))?;
+
+        if self.dry_run {
+            self.out.line("# dataSources.xml:");
+            self.out.line(datasources_xml);
+            self.out.line("");
+            self.out.line("# dataSources.local.xml:");
+            self.out.line(datasources_local_xml);
+            for dir in self.datasource_subproject_dirs()? {
+                self.out.line(format!("# Shared with: {}", dir.display()));
+            }
+        } else {
+            self.write_datasource_files(
+                &self.idea_dir(),
+                &datasources_xml,
+                &datasources_local_xml,
+            )?;
+
+            let root_file = self.idea_dir().join("dataSources.xml");
+            for dir in self.datasource_subproject_dirs()? {
+                self.out
+                    .line(format!("Sharing data sources with: {}", dir.display()));
+                self.write_datasource_files(
+                    &idea_dir::path(&dir),
+                    &subprojects::referencing(&datasources_xml, &root_file),
+                    &subprojects::referencing(&datasources_local_xml, &root_file),
+                )?;
+            }
+
+            self.out
+                .line("Datasource configuration completed successfully!");
+        }
+
+        Ok(())
+    }
+
+    /// Regenerates a subproject's data source files from the root project's
+    fn configure_shared_datasources(&self, root_file: &Path, root_local_file: &Path) -> Result<()> {
+        let read = |path: &Path| {
+            fs::read_to_string(path).with_context(|| {
+                format!(
+                    "Failed to read {}, which this project shares data sources with",
+                    path.display()
+                )
+            })
+        };
+        let datasources_xml = subprojects::referencing(&read(root_file)?, root_file);
+        let datasources_local_xml = subprojects::referencing(&read(root_local_file)?, root_file);
+        let root_dir = root_file
+            .parent()
+            .and_then(Path::parent)
+            .unwrap_or(root_file);
+
+        if self.dry_run {
+            self.out.line(format!(
+                "# Data sources shared from: {}",
+                root_dir.display()
+            ));
+            self.out.line("# dataSources.xml:");
+            self.out.line(datasources_xml);
+            self.out.line("");
+            self.out.line("# dataSources.local.xml:");
+            self.out.line(datasources_local_xml);
+        } else {
+            self.out.line(format!(
+                "Data sources are shared from: {}",
+                root_dir.display()
+            ));
+            self.write_datasource_files(
+                &self.idea_dir(),
+                &datasources_xml,
+                &datasources_local_xml,
+            )?;
+            self.out
+                .line("Datasource configuration completed successfully!");
+        }
+        Ok(())
+    }
+}
+
+/// What the CLI writes, without writing or printing it
+impl RubyMineInterpreter {
+    /// The SDK name, e.g. "Ruby 3.3.1 (proj) + shadowenv 2024-01-01"
+    pub fn name(&self) -> &str {
+        &self.interpreter_name
+    }
+
+    /// The IDE config directory the interpreter goes into
+    pub fn config_dir(&self) -> Result<PathBuf> {
+        self.rubymine_config_dir()
+    }
+
+    /// The jdk.table.xml the interpreter goes into
+    pub fn jdk_table_path(&self) -> Result<PathBuf> {
+        self.interpreter_config_file()
+    }
+
+    /// jdk.table.xml with the interpreter written into `existing`, replacing the
+    /// entries a run would replace, or a new file holding only the interpreter
+    pub fn jdk_table_xml(&self, existing: Option<&str>) -> Result<String> {
+        match existing {
+            Some(existing) => self.update_existing_config(existing),
+            None => self.create_new_config_content(),
+        }
+    }
+
+    /// The fragment for `artifact` on its own, for pasting into an existing file
+    pub fn render(&self, artifact: RenderArtifact) -> Result<String> {
+        let mut writer = XmlWriter::new(Options::default());
+        match artifact {
+            RenderArtifact::Interpreter => self.sdk_entry()?.write(&mut writer),
+            RenderArtifact::Datasource => {
+                let mut data_sources = self.data_sources(&[])?;
+                self.discover_schemas(&mut data_sources)?;
+                let data_sources = self.with_uuids(data_sources)?;
+                if data_sources.is_empty() {
+                    anyhow::bail!("No MySQL data sources configured for this project");
+                }
+                writer.write_comment(" dataSources.xml ");
+                Self::write_datasource_elements(&mut writer, &data_sources);
+                writer.write_comment(" dataSources.local.xml ");
+                Self::write_datasource_local_elements(&mut writer, &data_sources);
+            }
+            RenderArtifact::Runconfig => {
+                let ruby_args = self.generate_ruby_args(&self.ruby_plugin_dir()?);
+                writer.start_element("RTEST_RUN_CONFIG_SETTINGS_ID");
+                writer.write_attribute("NAME", "RUBY_ARGS");
+                xml_misc::write_attribute(&mut writer, "VALUE", &ruby_args);
+                writer.end_element();
+
+                let envs = self.test_env();
+                if !envs.is_empty() {
+                    Self::write_merged_envs(&mut writer, None, &envs);
+                }
+            }
+        }
+        Ok(writer.end_document())
+    }
+}
+
+/// The project environment, as RubyMine would see it through the configurator
+fn env_text(interpreter: &RubyMineInterpreter, json: bool, all: bool) -> Result<String> {
+    if let Some(docker) = &interpreter.docker {
+        anyhow::bail!(
+            "env doesn't apply to a {} interpreter, whose environment comes from the container",
+            docker.container.kind()
+        );
+    }
+    if interpreter.project_env.is_empty() {
+        anyhow::bail!(
+            "Couldn't capture the project environment through {}",
+            interpreter.configurator_options().join(" ")
+        );
+    }
+    let envs: BTreeMap<String, String> = if all {
+        interpreter.project_env.clone().into_iter().collect()
+    } else {
+        let clean: HashMap<String, String> = env::vars().collect();
+        env_snapshot::delta(&interpreter.project_env, &clean)
+    };
+
+    if json {
+        return Ok(serde_json::to_string_pretty(&envs)?);
+    }
+    let mut writer = XmlWriter::new(Options::default());
+    RubyMineInterpreter::write_merged_envs(&mut writer, None, &envs);
+    Ok(writer.end_document())
+}
+
+/// Files or directories whose presence marks a project root
+const PROJECT_ROOT_MARKERS: &[&str] = &[".git", "Gemfile", ".shadowenv.d"];
+
+/// The nearest ancestor of `start` (including itself) containing a root marker
+fn find_project_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| {
+            PROJECT_ROOT_MARKERS
+                .iter()
+                .any(|marker| dir.join(marker).exists())
+        })
+        .map(Path::to_path_buf)
+}
+
+/// Whether `a` and `b` are the same directory, however each is spelled
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Runs the CLI with `args`: whatever `main` does but parse the command line, print
+/// `out` and exit. Under --strict, warnings fail the run with a `warnings::Strict` error.
+pub fn run_cli(args: RunOptions, out: &Output) -> Result<()> {
+    let strict = args.strict;
+    if !args.notify {
+        return run(args, out).and_then(|()| Ok(warnings::check(out, strict)?));
+    }
+
+    let started = Instant::now();
+    let project = env::current_dir()
+        .ok()
+        .and_then(|dir| Some(dir.file_name()?.to_string_lossy().to_string()))
+        .unwrap_or_default();
+    let result = run(args, out).and_then(|()| Ok(warnings::check(out, strict)?));
+    notify::send(
+        &match &result {
+            Ok(()) => format!("{}: done in {}s", project, started.elapsed().as_secs()),
+            Err(err) => format!("{}: failed: {}", project, err),
+        },
+        out,
+    );
+    result
+}
+
+fn run(mut args: RunOptions, out: &Output) -> Result<()> {
+    // Whatever the command, Ctrl-C stops it between steps rather than mid-write, and
+    // what it already wrote is undone
+    let cancellation = CancellationToken::default();
+    signals::cancel_on_interrupt(&cancellation, out)?;
+
+    let invocation_dir = env::current_dir()?;
+    // Before anything reads the environment or spawns a probe
+    if let Some(env_file) = &args.env_file {
+        let env_file = invocation_dir.join(env_file);
+        let count = env_sources::apply_file(&env_file)?;
+        out.status(format!(
+            "Using {} variable(s) from {}",
+            count,
+            env_file.display()
+        ));
+    }
+    // --ruby is relative to where the tool was started, not the project root
+    if let Some(ruby) = args.ruby.take() {
+        args.ruby = Some(invocation_dir.join(ruby));
+    }
+
+    // So are --report-to files
+    for sink in &mut args.report_to {
+        if let report::Sink::File(path) = sink {
+            *path = invocation_dir.join(&*path);
+        }
+    }
+
+    // Everything downstream (shadowenv, .idea, config files) works off the project
+    // root rather than the directory the tool was started in
+    let mut project_dir = invocation_dir.clone();
+    if !args.no_root_detection {
+        if let Some(root) =
+            find_project_root(&invocation_dir).filter(|root| *root != invocation_dir)
+        {
+            out.status(format!("Using project root {}", root.display()));
+            project_dir = root;
+        }
+    }
+
+    if args.ruby_from_lockfile {
+        let version = lockfile::required_ruby(&project_dir)?
+            .context("Neither Gemfile.lock nor the Gemfile says which ruby to use")?;
+        let ruby = version_manager::find_installed(&version)
+            .with_context(|| format!("Ruby {} (from the lockfile) is not installed", version))?;
+        out.status(format!(
+            "Using ruby {} from the lockfile: {}",
+            version,
+            ruby.display()
+        ));
+        args.ruby = Some(ruby);
+    }
+
+    let layered_config = LayeredConfig::load(
+        &args.config_flags(),
+        &project_dir,
+        args.profile.as_deref(),
+        out,
+    )?;
+
+    match &args.command {
+        Some(Commands::PrintConfig) => {
+            out.write(Stream::Stdout, &layered_config.describe());
+            for (name, value) in args.flag_only_settings() {
+                let line = config::describe_setting(
+                    name.to_string(),
+                    value,
+                    "command-line flag, not a config key",
+                );
+                out.write(Stream::Stdout, &line);
+            }
+            return Ok(());
+        }
+        Some(Commands::Prune) => {
+            let tag = args.tag.as_deref().context("prune needs --tag")?;
+            let config = layered_config.effective()?;
+            let protected = Denylist::from_config(&config)?;
+            let fs = FsBackend::new(args.read_only, out);
+            prune::run(&fs, tag, &protected, args.dry_run, out)?;
+            abort_if_cancelled(&fs, &cancellation)?;
+            if !args.dry_run && fs.changed() {
+                enforce_backup_budget(&fs, &config, &project_dir, out)?;
+            }
+            return Ok(());
+        }
+        Some(Commands::History { file }) => return audit::history(file.as_deref(), out),
+        Some(Commands::Completions { shell }) => {
+            out.write(Stream::Stdout, completion::script(*shell));
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let config = layered_config.effective()?;
+    if let Some(Commands::Complete { kind, prefix }) = &args.command {
+        completion::complete(&config, &project_dir, *kind, prefix, out);
+        return Ok(());
+    }
+    if let Some(Commands::MigrateBackups) = &args.command {
+        let fs = FsBackend::new(args.read_only, out);
+        backups::migrate(&fs, &backup_dirs(&config, &project_dir), args.dry_run, out)?;
+        abort_if_cancelled(&fs, &cancellation)?;
+        if !args.dry_run && fs.changed() {
+            enforce_backup_budget(&fs, &config, &project_dir, out)?;
+        }
+        return Ok(());
+    }
+    if config.offline.unwrap_or_default() {
+        network::go_offline();
+    }
+    if args.from_git_worktrees {
+        if args.command.is_some() {
+            anyhow::bail!("--from-git-worktrees can't be combined with a command");
+        }
+        return configure_git_worktrees(&args, &project_dir, &cancellation, out);
+    }
+    if let Some(Commands::Interpreter { stdin: true }) = &args.command {
+        return configure_interpreters_from_stdin(&args, &invocation_dir, &cancellation, out);
+    }
+    if args.command.is_none() && args.explain_env.is_none() {
+        let options = api::ConfigureOptions::from_run_options(args.clone(), &project_dir)
+            .config(config)
+            .cancellation(cancellation)
+            .output(out.clone());
+        return api::configure_all(&options).map(|_| ());
+    }
+    let mut interpreter = RubyMineInterpreter::new(&args, &config, &project_dir, out)?;
+    if let Some(var) = &args.explain_env {
+        interpreter.explain_env(var);
+        return Ok(());
+    }
+
+    let result = match &args.command {
+        Some(Commands::Doctor { fix }) => doctor::run(&interpreter, *fix),
+        Some(Commands::Interpreter { .. }) => {
+            configurator::run(&configurator::Interpreter, &mut interpreter)
+        }
+        Some(Commands::Datasource { envs }) => {
+            configurator::run(&configurator::Datasources(envs.clone()), &mut interpreter)
+        }
+        Some(Commands::Debugger) => {
+            let debugger = Debugger::detect(Path::new(&interpreter.current_dir))
+                .context("No debug or ruby-debug-ide gem in the Gemfile")?;
+            configurator::run(&configurator::DebuggerAttach(debugger), &mut interpreter)
+        }
+        Some(Commands::Scripts) => {
+            if interpreter.scripts.is_empty() {
+                anyhow::bail!("No [[scripts]] in the config");
+            }
+            configurator::run(&configurator::Scripts, &mut interpreter)
+        }
+        Some(Commands::Render { artifact }) => {
+            out.line(interpreter.render(*artifact)?);
+            Ok(())
+        }
+        Some(Commands::SimulateOpen) => interpreter.simulate_open(),
+        Some(Commands::Verify) => verify::run(&interpreter),
+        Some(Commands::Env { json, all }) => {
+            out.line(env_text(&interpreter, *json, *all)?);
+            Ok(())
+        }
+        Some(Commands::MigrateArtifacts) => migrate::run(&mut interpreter),
+        _ => unreachable!("handled before the interpreter is built"),
+    };
+    abort_if_cancelled(&interpreter.fs, &cancellation)?;
+    if result.is_ok() && !args.dry_run && interpreter.fs.changed() {
+        interpreter.enforce_backup_budget()?;
+    }
+    result
+}
+
+/// The directories with backups next to the files they are copies of: the
+/// project's .idea and every IDE config directory's options
+fn backup_dirs(config: &Config, project_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = RubyMineInterpreter::discover_config_dirs(
+        config.ide.unwrap_or_default(),
+        config.channel.unwrap_or_default(),
+    )
+    .unwrap_or_default()
+    .into_iter()
+    .map(|dir| dir.join("options"))
+    .collect();
+    dirs.push(idea_dir::path(project_dir));
+    dirs
+}
+
+/// `--max-backups-size` for the commands that run without an interpreter
+fn enforce_backup_budget(
+    fs: &FsBackend,
+    config: &Config,
+    project_dir: &Path,
+    out: &Output,
+) -> Result<()> {
+    let Some(budget) = config.max_backups_size.as_deref() else {
+        return Ok(());
+    };
+    let budget = backups::parse_size(budget).context("Invalid max_backups_size")?;
+    backups::enforce(fs, backup_dirs(config, project_dir), budget, out)
+}
+
+/// Everything a run without a command configures for the project, then the
+/// reports, which are written whether or not it succeeded
+fn configure_project(
+    interpreter: &mut RubyMineInterpreter,
+    args: &RunOptions,
+    cancellation: &CancellationToken,
+) -> Result<()> {
+    let result = configure_project_files(interpreter, args, cancellation);
+    match result {
+        Err(err) if err.downcast_ref::<warnings::Strict>().is_some() => {
+            return abort(&interpreter.fs).map_err(|aborted| err.context(aborted.to_string()));
+        }
+        Err(_) if cancellation.is_cancelled() => return abort(&interpreter.fs),
+        _ => {}
+    }
+    if let Err(err) = write_reports(interpreter, args, result.as_ref().err()) {
+        match &result {
+            Ok(()) => return Err(err),
+            Err(_) => warnings::warn(
+                &interpreter.out,
+                format!("failed to write the report: {:#}", err),
+            ),
+        }
+    }
+    if result.is_ok() && !args.dry_run {
+        interpreter.enforce_backup_budget()?;
+    }
+    result
+}
+
+/// Undoes what a cancelled or --strict run already wrote through `fs`, so it ends
+/// with the files as they were
+fn abort(fs: &FsBackend) -> Result<()> {
+    let left = fs.roll_back()?;
+    if left.is_empty() {
+        anyhow::bail!("Aborted, no changes made");
+    }
+    let files: Vec<String> = left.iter().map(|file| file.display().to_string()).collect();
+    anyhow::bail!(
+        "Aborted; these files changed again since the run wrote them, so they were left as they are: {}",
+        files.join(", ")
+    )
+}
+
+/// `abort` once `cancellation` was cancelled, for commands that run to the end of
+/// what they started
+fn abort_if_cancelled(fs: &FsBackend, cancellation: &CancellationToken) -> Result<()> {
+    if cancellation.is_cancelled() {
+        return abort(fs);
+    }
+    Ok(())
+}
+
+/// Stops between steps once `cancellation` is cancelled; a step that started finishes
+fn configure_project_files(
+    interpreter: &mut RubyMineInterpreter,
+    args: &RunOptions,
+    cancellation: &CancellationToken,
+) -> Result<()> {
+    // Fail before touching the IDE config when .idea can't be written at all
+    if !args.dry_run {
+        interpreter
+            .fs
+            .ensure_writable("write", &interpreter.idea_dir())?;
+    }
+    // Detection may have warned already
+    checkpoint(interpreter, args, cancellation)?;
+    for step in project_configurators(interpreter, args) {
+        configurator::run(step.as_ref(), interpreter)?;
+        checkpoint(interpreter, args, cancellation)?;
+    }
+    Ok(())
+}
+
+/// The steps of a run without a command, in order
+fn project_configurators(
+    interpreter: &RubyMineInterpreter,
+    args: &RunOptions,
+) -> Vec<Box<dyn Configurator>> {
+    let project_dir = Path::new(&interpreter.current_dir);
+    let mut steps: Vec<Box<dyn Configurator>> = Vec::new();
+    if args.all_ide_versions {
+        steps.push(Box::new(configurator::AllIdeVersions));
+    } else {
+        steps.push(Box::new(configurator::Interpreter));
+        steps.push(Box::new(configurator::Minitest));
+    }
+    if interpreter.shared_env {
+        steps.push(Box::new(configurator::SharedEnv));
+    }
+    if let Some(debugger) = Debugger::detect(project_dir) {
+        steps.push(Box::new(configurator::DebuggerAttach(debugger)));
+    }
+    if !interpreter.scripts.is_empty() {
+        steps.push(Box::new(configurator::Scripts));
+    }
+    if rbs::has_signatures(project_dir) {
+        steps.push(Box::new(configurator::Rbs));
+    }
+    if args.terminal {
+        steps.push(Box::new(configurator::Terminal));
+    }
+    steps.push(Box::new(configurator::Datasources(Vec::new())));
+    steps
+}
+
+/// Where a run stops between steps: once cancelled, or once a warning was printed
+/// with --strict
+fn checkpoint(
+    interpreter: &RubyMineInterpreter,
+    args: &RunOptions,
+    cancellation: &CancellationToken,
+) -> Result<()> {
+    cancellation.check()?;
+    warnings::check(&interpreter.out, args.strict)?;
+    Ok(())
+}
+
+/// Sends the report to --report and every --report-to sink. Files aren't written
+/// in dry-run mode; the stdout summary still is.
+fn write_reports(
+    interpreter: &RubyMineInterpreter,
+    args: &RunOptions,
+    error: Option<&anyhow::Error>,
+) -> Result<()> {
+    let mut sinks = args.report_to.clone();
+    if args.report {
+        sinks.push(report::Sink::File(
+            interpreter.idea_dir().join(report::FILE_NAME),
+        ));
+    }
+    if sinks.is_empty() {
+        return Ok(());
+    }
+
+    let project_dir = Path::new(&interpreter.current_dir);
+    let report = report::Report::collect(interpreter, args, error)?;
+    for sink in &sinks {
+        let content = sink.render(&report, project_dir)?;
+        match sink {
+            report::Sink::Stdout => interpreter.out.write(Stream::Stdout, &content),
+            report::Sink::File(_) if args.dry_run => {}
+            report::Sink::File(path) => {
+                if let Some(parent) = path.parent() {
+                    interpreter.fs.create_dir_all(parent)?;
+                }
+                interpreter.fs.write(path, &content)?;
+                interpreter
+                    .out
+                    .line(format!("Report written: {}", path.display()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Creates an interpreter for each JSON line on stdin, each with its project's config,
+/// then prints how each one went. Relative paths are relative to `invocation_dir`.
+fn configure_interpreters_from_stdin(
+    args: &RunOptions,
+    invocation_dir: &Path,
+    cancellation: &CancellationToken,
+    out: &Output,
+) -> Result<()> {
+    let mut input = String::new();
+    io::stdin()
+        .read_to_string(&mut input)
+        .context("Failed to read stdin")?;
+    let specs = interpreter_specs::parse(&input, invocation_dir)?;
+    if specs.is_empty() {
+        anyhow::bail!("No interpreters on stdin");
+    }
+
+    let mut results = Vec::new();
+    for spec in specs {
+        out.line("");
+        out.line(format!("==> {}", spec.dir.display()));
+        let result = create_interpreter_from_spec(args, &spec, cancellation, out);
+        results.push((spec.dir, result));
+    }
+
+    out.line("");
+    out.line("Summary:");
+    let mut failures = 0;
+    for (dir, result) in &results {
+        match result {
+            Ok(()) => out.line(format!("  {}: configured", dir.display())),
+            Err(err) => {
+                failures += 1;
+                out.line(format!("  {}: failed: {:#}", dir.display(), err));
+            }
+        }
+    }
+    out.line("");
+
+    if failures > 0 {
+        anyhow::bail!("{} of {} interpreters failed", failures, results.len());
+    }
+    Ok(())
+}
+
+fn create_interpreter_from_spec(
+    args: &RunOptions,
+    spec: &interpreter_specs::InterpreterSpec,
+    cancellation: &CancellationToken,
+    out: &Output,
+) -> Result<()> {
+    cancellation.check()?;
+    let mut args = args.clone();
+    if spec.ruby.is_some() {
+        args.ruby = spec.ruby.clone();
+    }
+    let config = LayeredConfig::load(
+        &args.config_flags(),
+        &spec.dir,
+        args.profile.as_deref(),
+        out,
+    )?
+    .effective()?;
+    let interpreter = RubyMineInterpreter::new(&args, &config, &spec.dir, out)?;
+    interpreter.create_interpreter()?;
+    abort_if_cancelled(&interpreter.fs, cancellation)?;
+    if !args.dry_run && interpreter.fs.changed() {
+        interpreter.enforce_backup_budget()?;
+    }
+    Ok(())
+}
+
+/// Configures every worktree of the repository `project_dir` is in, each with
+/// its own project config, then prints how each one went
+fn configure_git_worktrees(
+    args: &RunOptions,
+    project_dir: &Path,
+    cancellation: &CancellationToken,
+    out: &Output,
+) -> Result<()> {
+    let worktrees = worktree::list(project_dir)?;
+
+    let mut results = Vec::new();
+    for dir in worktrees {
+        out.line("");
+        out.line(format!("==> {}", dir.display()));
+        let result = configure_git_worktree(args, &dir, cancellation, out);
+        results.push((dir, result));
+    }
+
+    out.line("");
+    out.line("Summary:");
+    let mut failures = 0;
+    for (dir, result) in &results {
+        match result {
+            Ok(Some(existing)) => out.line(format!(
+                "  {}: already configured ({})",
+                dir.display(),
+                existing
+            )),
+            Ok(None) => out.line(format!("  {}: configured", dir.display())),
+            Err(err) => {
+                failures += 1;
+                out.line(format!("  {}: failed: {:#}", dir.display(), err));
+            }
+        }
+    }
+    out.line("");
+
+    if failures > 0 {
+        anyhow::bail!("{} of {} worktrees failed", failures, results.len());
+    }
+    Ok(())
+}
+
+/// Configures one worktree, unless it already has an interpreter, whose name is returned
+fn configure_git_worktree(
+    args: &RunOptions,
+    dir: &Path,
+    cancellation: &CancellationToken,
+    out: &Output,
+) -> Result<Option<String>> {
+    cancellation.check()?;
+    let config = LayeredConfig::load(&args.config_flags(), dir, args.profile.as_deref(), out)?
+        .effective()?;
+    let mut interpreter = RubyMineInterpreter::new(args, &config, dir, out)?;
+    if let Some(existing) = interpreter.managed_interpreter()? {
+        return Ok(Some(existing.name));
+    }
+    configure_project(&mut interpreter, args, cancellation)?;
+    Ok(None)
+}
+
+/// Applies the IDE-level configuration (interpreter and test templates) to every
+/// discovered config directory, then prints how each one went
+fn configure_all_ide_versions(interpreter: &mut RubyMineInterpreter) -> Result<()> {
+    let ide = interpreter.ide;
+    let config_dirs = RubyMineInterpreter::discover_config_dirs(ide, interpreter.channel)?;
+    if config_dirs.is_empty() {
+        anyhow::bail!("No {} configuration directory found", ide.display_name());
+    }
+
+    let mut results = Vec::new();
+    for config_dir in config_dirs {
+        interpreter.out.line("");
+        interpreter
+            .out
+            .line(format!("==> {}", config_dir.display()));
+        interpreter.config_dir_override = Some(config_dir.clone());
+        let result = interpreter
+            .create_interpreter()
+            .and_then(|_| interpreter.create_minitest_config());
+        results.push((config_dir, result));
+    }
+    interpreter.config_dir_override = None;
+
+    interpreter.out.line("");
+    interpreter.out.line("Summary:");
+    let mut failures = 0;
+    for (config_dir, result) in &results {
+        let name = config_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match result {
+            Ok(()) => interpreter.out.line(format!("  {}: ok", name)),
+            Err(err) => {
+                failures += 1;
+                interpreter
+                    .out
+                    .line(format!("  {}: failed: {:#}", name, err));
+            }
+        }
+    }
+    interpreter.out.line("");
+
+    if failures > 0 {
+        anyhow::bail!(
+            "{} of {} IDE config directories failed",
+            failures,
+            results.len()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+
+/// The ruby the bundle was locked with: Gemfile.lock's RUBY VERSION, else the file a
+/// `ruby file: ".ruby-version"` directive in the Gemfile points to. Versions come back
+/// as installed by version managers: `3.3.1`, or `jruby-9.4.5.0` for other engines.
+pub fn required_ruby(project_dir: &Path) -> Result<Option<String>> {
+    if let Ok(locked) = fs::read_to_string(project_dir.join("Gemfile.lock")) {
+        // RUBY VERSION
+        //    ruby 3.3.1p55
+        //    ruby 3.1.4p0 (jruby 9.4.5.0)
+        let section = Regex::new(
+            r"(?m)^RUBY VERSION\s*\n\s+ruby (\d[\w.]*?)(?:p\d+)?(?: \((\w+) ([\w.]+)\))?\s*$",
+        )
+        .unwrap();
+        if let Some(captures) = section.captures(&locked) {
+            return Ok(Some(match (captures.get(2), captures.get(3)) {
+                (Some(engine), Some(version)) => {
+                    format!("{}-{}", engine.as_str(), version.as_str())
+                }
+                _ => captures[1].to_string(),
+            }));
+        }
+    }
+
+    let Ok(gemfile) = fs::read_to_string(project_dir.join("Gemfile")) else {
+        return Ok(None);
+    };
+    let directive = Regex::new(r#"(?m)^\s*ruby\s*\(?\s*file:\s*["']([^"']+)["']"#).unwrap();
+    let Some(captures) = directive.captures(&gemfile) else {
+        return Ok(None);
+    };
+    let file = project_dir.join(&captures[1]);
+    let version = fs::read_to_string(&file)
+        .with_context(|| format!("Failed to read {} (named by the Gemfile)", file.display()))?;
+    let version = version.trim();
+    Ok(Some(
+        version.strip_prefix("ruby-").unwrap_or(version).to_string(),
+    ))
+}
@@ -0,0 +1,217 @@
+use crate::xml_misc;
+use crate::xml_splice::Splice;
+use anyhow::Result;
+use roxmltree::{Document, Node};
+
+/// Start of the marker comment. Files the tool owns whole (dataSources.xml,
+/// dataSources.local.xml, rbs.xml) carry it at the top; files shared with the IDE
+/// carry it right before each block the tool manages in them, such as the project
+/// SDK in misc.xml and the module's SDK in the .iml.
+const MARKER: &str = "managed by rubymine-configurator — rerun instead of editing";
+
+/// Whether a file or block still matches what the tool last wrote into it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkerStatus {
+    /// No marker: never written by the tool (or written before markers existed)
+    Unmanaged,
+    Intact,
+    /// Changed since the tool wrote it
+    Edited,
+}
+
+/// An element the tool manages in a file it shares with the IDE
+pub struct Block {
+    /// What it sets, for messages, e.g. "project SDK"
+    pub name: &'static str,
+    /// Whether a node is the block
+    pub is: fn(&Node) -> bool,
+    /// The attributes the tool sets on it, which are all the hash covers; the IDE is
+    /// free to add others. Empty when the tool writes the whole element.
+    pub attributes: &'static [&'static str],
+}
+
+/// Adds the marker comment, carrying a hash of the content, right after the XML declaration
+pub fn stamp(xml: &str) -> Result<String> {
+    let hash = content_hash(&Document::parse(xml)?.root_element());
+    Ok(xml_misc::prepend_comment(xml, &comment(&hash)))
+}
+
+pub fn check(xml: &str) -> MarkerStatus {
+    let Ok(doc) = Document::parse(xml) else {
+        return MarkerStatus::Edited;
+    };
+    let root = doc.root_element();
+    status(recorded_hash(&root).as_ref(), &content_hash(&root))
+}
+
+/// Puts a marker comment carrying the hash of `block` right before it, replacing the
+/// one there. `xml` comes back as it is when it has no such block.
+pub fn stamp_block(xml: &str, block: &Block) -> Result<String> {
+    let doc = Document::parse(xml)?;
+    let Some(node) = doc.descendants().find(block.is) else {
+        return Ok(xml.to_string());
+    };
+    let hash = block_hash(&node, block);
+    let mut splice = Splice::new(xml);
+    let fragment = format!("<!-- {} -->", comment(&hash));
+    match recorded_hash(&node) {
+        Some((_, recorded)) if recorded == hash => return Ok(xml.to_string()),
+        Some((comment, _)) => splice.replace(&comment, &fragment),
+        None => splice.insert_before(&node, &fragment),
+    }
+    Ok(splice.finish())
+}
+
+/// `check` for `block` in `xml`, which a file without the block passes
+pub fn check_block(xml: &str, block: &Block) -> MarkerStatus {
+    let Ok(doc) = Document::parse(xml) else {
+        return MarkerStatus::Edited;
+    };
+    let Some(node) = doc.descendants().find(block.is) else {
+        return MarkerStatus::Unmanaged;
+    };
+    status(recorded_hash(&node).as_ref(), &block_hash(&node, block))
+}
+
+fn comment(hash: &str) -> String {
+    format!("{} (hash {})", MARKER, hash)
+}
+
+fn status(recorded: Option<&(Node, String)>, hash: &str) -> MarkerStatus {
+    match recorded {
+        None => MarkerStatus::Unmanaged,
+        Some((_, recorded)) if recorded == hash => MarkerStatus::Intact,
+        Some(_) => MarkerStatus::Edited,
+    }
+}
+
+/// The marker comment among the comments right before `node`, with the hash it records
+fn recorded_hash<'a, 'input>(node: &Node<'a, 'input>) -> Option<(Node<'a, 'input>, String)> {
+    let mut sibling = node.prev_sibling();
+    while let Some(previous) = sibling.filter(|previous| !previous.is_element()) {
+        let hash = previous
+            .is_comment()
+            .then(|| previous.text())
+            .flatten()
+            .and_then(|text| text.trim().strip_prefix(MARKER))
+            .and_then(|rest| rest.trim().strip_prefix("(hash "))
+            .and_then(|rest| rest.strip_suffix(')'));
+        if let Some(hash) = hash {
+            return Some((previous, hash.to_string()));
+        }
+        sibling = previous.prev_sibling();
+    }
+    None
+}
+
+fn block_hash(node: &Node, block: &Block) -> String {
+    if block.attributes.is_empty() {
+        return content_hash(node);
+    }
+    let mut hasher = Hasher::default();
+    hasher.feed(node.tag_name().name().as_bytes());
+    for name in block.attributes {
+        hasher.feed(name.as_bytes());
+        hasher.feed(node.attribute(*name).unwrap_or_default().as_bytes());
+    }
+    hasher.finish()
+}
+
+/// FNV-1a over the element structure, ignoring whitespace and comments so that the
+/// IDE re-indenting a file doesn't count as an edit
+fn content_hash(root: &Node) -> String {
+    let mut hasher = Hasher::default();
+    for node in root.descendants() {
+        if node.is_element() {
+            hasher.feed(b"<");
+            hasher.feed(node.tag_name().name().as_bytes());
+            for attribute in node.attributes() {
+                hasher.feed(attribute.name().as_bytes());
+                hasher.feed(attribute.value().as_bytes());
+            }
+        } else if node.is_text() {
+            let text = node.text().unwrap_or_default().trim();
+            if !text.is_empty() {
+                hasher.feed(text.as_bytes());
+            }
+        }
+    }
+    hasher.finish()
+}
+
+struct Hasher(u64);
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher {
+    /// Adds `bytes` and a separator, so that "ab" + "c" and "a" + "bc" differ
+    fn feed(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter().chain(b"\0") {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SDK: Block = Block {
+        name: "SDK",
+        is: |node| node.has_tag_name("component"),
+        attributes: &["sdk"],
+    };
+
+    const MISC: &str = "<project version=\"4\">\n  <component sdk=\"Ruby 3.3\" />\n</project>\n";
+
+    #[test]
+    fn whole_files_are_edited_once_their_content_changes() {
+        let xml = stamp("<?xml version=\"1.0\"?>\n<a>\n  <b x=\"1\"/>\n</a>").unwrap();
+        assert_eq!(check(&xml), MarkerStatus::Intact);
+        assert_eq!(check(&xml.replace("  <b", "<b")), MarkerStatus::Intact);
+        assert_eq!(
+            check(&xml.replace("x=\"1\"", "x=\"2\"")),
+            MarkerStatus::Edited
+        );
+        assert_eq!(check("<a/>"), MarkerStatus::Unmanaged);
+    }
+
+    #[test]
+    fn stamp_block_puts_the_marker_before_the_block() {
+        let xml = stamp_block(MISC, &SDK).unwrap();
+        let lines: Vec<&str> = xml.lines().collect();
+        assert!(lines[1].starts_with("  <!-- managed by rubymine-configurator"));
+        assert_eq!(lines[2], "  <component sdk=\"Ruby 3.3\" />");
+        assert_eq!(check_block(&xml, &SDK), MarkerStatus::Intact);
+        assert_eq!(stamp_block(&xml, &SDK).unwrap(), xml);
+    }
+
+    #[test]
+    fn stamp_block_replaces_an_outdated_marker() {
+        let xml = stamp_block(MISC, &SDK).unwrap();
+        let edited = xml.replace("Ruby 3.3", "Ruby 3.4");
+        assert_eq!(check_block(&edited, &SDK), MarkerStatus::Edited);
+
+        let restamped = stamp_block(&edited, &SDK).unwrap();
+        assert_eq!(restamped.matches(MARKER).count(), 1);
+        assert_eq!(check_block(&restamped, &SDK), MarkerStatus::Intact);
+    }
+
+    #[test]
+    fn check_block_only_covers_the_managed_attributes() {
+        let xml = stamp_block(MISC, &SDK).unwrap();
+        let ide_added = xml.replace("/>", "languageLevel=\"1\" />");
+        assert_eq!(check_block(&ide_added, &SDK), MarkerStatus::Intact);
+        assert_eq!(check_block(MISC, &SDK), MarkerStatus::Unmanaged);
+        assert_eq!(check_block("<project/>", &SDK), MarkerStatus::Unmanaged);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::state::{Migration, State};
+use crate::RubyMineInterpreter;
+use anyhow::Result;
+use chrono::Local;
+
+/// Regenerates interpreters and data sources left behind by older releases under
+/// the current naming scheme, and records what changed in the state file
+pub fn run(interpreter: &mut RubyMineInterpreter) -> Result<()> {
+    let legacy_interpreters = interpreter.legacy_interpreters()?;
+    let legacy_datasources = interpreter.legacy_datasources()?;
+
+    if legacy_interpreters.is_empty() && legacy_datasources.is_empty() {
+        interpreter
+            .out
+            .line("No artifacts from older versions found");
+        return Ok(());
+    }
+
+    for entry in &legacy_interpreters {
+        interpreter.out.line(format!(
+            "Interpreter: {} -> {}",
+            entry.name, interpreter.interpreter_name
+        ));
+    }
+    for name in &legacy_datasources {
+        interpreter.out.line(format!(
+            "Data source: {} (no longer configured, will be removed)",
+            name
+        ));
+    }
+    interpreter.out.line("");
+
+    if !legacy_interpreters.is_empty() {
+        interpreter.replace_legacy = true;
+        interpreter.create_interpreter()?;
+    }
+    if !legacy_datasources.is_empty() {
+        interpreter.configure_datasources(&[])?;
+    }
+
+    if interpreter.dry_run {
+        return Ok(());
+    }
+
+    let at = Local::now().to_rfc3339();
+    let mut state = State::load()?;
+    state
+        .migrations
+        .extend(legacy_interpreters.iter().map(|entry| Migration {
+            at: at.clone(),
+            project_dir: interpreter.current_dir.clone(),
+            artifact: "interpreter".to_string(),
+            from: entry.name.clone(),
+            to: interpreter.interpreter_name.clone(),
+        }));
+    state
+        .migrations
+        .extend(legacy_datasources.iter().map(|name| Migration {
+            at: at.clone(),
+            project_dir: interpreter.current_dir.clone(),
+            artifact: "datasource".to_string(),
+            from: name.clone(),
+            to: String::new(),
+        }));
+    state.save(&interpreter.fs)?;
+    interpreter.out.line(format!(
+        "Recorded migration in {}",
+        State::path()?.display()
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use dirs::home_dir;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Project config files mise reads, in a directory or any of its parents
+const CONFIG_FILES: &[&str] = &[
+    "mise.toml",
+    ".mise.toml",
+    "mise.local.toml",
+    ".mise.local.toml",
+    ".mise/config.toml",
+    ".config/mise.toml",
+    ".config/mise/config.toml",
+    "mise/config.toml",
+];
+
+/// Whether a mise config file applies to `project_dir`
+pub fn manages(project_dir: &Path) -> bool {
+    project_dir
+        .ancestors()
+        .any(|dir| CONFIG_FILES.iter().any(|file| dir.join(file).is_file()))
+}
+
+/// Location of the mise executable: PATH first, then the installer's default location
+pub fn find_path() -> Option<String> {
+    if let Ok(Some(path)) = crate::RubyMineInterpreter::which("mise") {
+        return Some(path);
+    }
+
+    let home = home_dir().unwrap_or_else(|| PathBuf::from("/"));
+    let installed = home.join(".local").join("bin").join("mise");
+    installed
+        .exists()
+        .then(|| installed.to_string_lossy().to_string())
+}
+
+/// The executable mise resolves `program` to in `dir`, or `None` when mise isn't
+/// installed or doesn't manage `program` there
+pub fn which(program: &str, dir: &Path) -> Option<String> {
+    let mise = find_path()?;
+    let output = Command::new(mise)
+        .args(["which", program])
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!path.is_empty()).then_some(path)
+}
+
+/// Where mise installs rubies, one directory per version
+pub fn rubies_dir() -> Option<PathBuf> {
+    let data_dir = env::var_os("MISE_DATA_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".local").join("share").join("mise")))?;
+    Some(data_dir.join("installs").join("ruby"))
+}
@@ -0,0 +1,54 @@
+use dirs::home_dir;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// The default MySQL option file, ~/.my.cnf
+pub fn path() -> Option<PathBuf> {
+    home_dir().map(|home| home.join(".my.cnf"))
+}
+
+/// Options from the `[client]` group of ~/.my.cnf, keyed with `-` normalized to `_`
+/// (MySQL accepts both spellings). Empty if the file is missing or unreadable.
+pub fn client_options() -> HashMap<String, String> {
+    path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|content| parse_group(&content, "client"))
+        .unwrap_or_default()
+}
+
+fn parse_group(content: &str, group: &str) -> HashMap<String, String> {
+    let mut options = HashMap::new();
+    let mut in_group = false;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            in_group = name.trim() == group;
+            continue;
+        }
+        // !include and !includedir directives aren't followed
+        if !in_group || line.starts_with('!') {
+            continue;
+        }
+
+        let (key, value) = line.split_once('=').unwrap_or((line, ""));
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .or_else(|| {
+                value
+                    .strip_prefix('\'')
+                    .and_then(|value| value.strip_suffix('\''))
+            })
+            .unwrap_or(value);
+        options.insert(key.trim().replace('-', "_"), value.to_string());
+    }
+    options
+}
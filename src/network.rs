@@ -0,0 +1,116 @@
+use anyhow::Result;
+use std::env;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::Command;
+use std::time::Duration;
+
+/// How long a data source gets to accept a connection before it counts as unreachable
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Settings that keep the tools this one spawns (mise, asdf, homebrew-installed
+/// binaries, bundler) from reaching the network. What the tool reaches itself
+/// (containers, SSH hosts, the project's database servers) checks `ensure_online`
+/// instead.
+const OFFLINE_ENV: &[(&str, &str)] = &[
+    ("MISE_OFFLINE", "1"),
+    ("HOMEBREW_NO_AUTO_UPDATE", "1"),
+    ("HOMEBREW_NO_INSTALL_FROM_API", "1"),
+    ("BUNDLE_DISABLE_VERSION_CHECK", "true"),
+    ("BUNDLE_NO_INSTALL", "true"),
+];
+
+/// Puts the process in offline mode before anything is spawned. Every child
+/// process inherits the settings above.
+pub fn go_offline() {
+    for (name, value) in OFFLINE_ENV {
+        env::set_var(name, value);
+    }
+}
+
+/// Proxy settings under both spellings, since tools disagree on which one they
+/// read: curl only looks at lowercase http_proxy, docker at the uppercase ones
+const PROXY_ENV: &[(&str, &str)] = &[
+    ("HTTPS_PROXY", "https_proxy"),
+    ("HTTP_PROXY", "http_proxy"),
+    ("NO_PROXY", "no_proxy"),
+];
+
+/// Where tools look for the CA bundle that replaces the system one. A corporate
+/// bundle set under any of these names is passed under all of them.
+const CA_BUNDLE_ENV: &[&str] = &["SSL_CERT_FILE", "CURL_CA_BUNDLE", "REQUESTS_CA_BUNDLE"];
+
+/// The proxy settings from the environment, each under both spellings
+fn proxy_env() -> Vec<(&'static str, String)> {
+    let mut vars = Vec::new();
+    for (upper, lower) in PROXY_ENV {
+        let value = [upper, lower]
+            .iter()
+            .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()));
+        if let Some(value) = value {
+            vars.push((*upper, value.clone()));
+            vars.push((*lower, value));
+        }
+    }
+    vars
+}
+
+/// The CA bundle from the environment, if one is set
+fn ca_bundle() -> Option<String> {
+    CA_BUNDLE_ENV
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+}
+
+/// A docker command (run, compose or build, which may pull images), with the proxy
+/// settings and CA bundle under every name docker and the builds it runs read them.
+/// Other programs inherit the environment as it is.
+pub fn docker() -> Command {
+    let mut command = Command::new("docker");
+    command.envs(proxy_env());
+    if let Some(bundle) = ca_bundle() {
+        for name in CA_BUNDLE_ENV {
+            command.env(name, &bundle);
+        }
+    }
+    command
+}
+
+/// Arguments that pass the proxy settings of a `docker` command on into a container: `-e`
+/// for `docker run`, `--build-arg` for `docker build`. Only names are passed, so
+/// credentials in a proxy URL stay out of the process list. The CA bundle is a host
+/// path and can't be.
+pub fn docker_proxy_args(flag: &str) -> Vec<String> {
+    proxy_env()
+        .into_iter()
+        .flat_map(|(name, _)| [flag.to_string(), name.to_string()])
+        .collect()
+}
+
+/// Fails with --offline, before code that goes online to do `what`. Every network
+/// access the tool makes itself checks this first.
+pub fn ensure_online(offline: bool, what: &str) -> Result<()> {
+    if offline {
+        anyhow::bail!("--offline forbids {}", what);
+    }
+    Ok(())
+}
+
+/// Whether `name` was set by `go_offline` rather than by the project environment
+pub fn is_offline_setting(name: &str) -> bool {
+    OFFLINE_ENV
+        .iter()
+        .any(|(offline_name, _)| *offline_name == name)
+}
+
+/// Whether something accepts connections on `host:port`
+pub fn is_listening(host: &str, port: &str) -> bool {
+    let Ok(port) = port.parse::<u16>() else {
+        return false;
+    };
+    let Ok(addresses) = (host, port).to_socket_addrs() else {
+        return false;
+    };
+    addresses
+        .into_iter()
+        .any(|address| TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).is_ok())
+}
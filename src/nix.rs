@@ -0,0 +1,48 @@
+use dirs::home_dir;
+use std::path::{Path, PathBuf};
+
+/// Location of the nix executable: PATH first, then the profiles the installers set up.
+/// RubyMine doesn't start from a shell that sourced nix-daemon.sh, so PATH alone isn't enough.
+pub fn find_path() -> Option<String> {
+    if let Ok(Some(path)) = crate::RubyMineInterpreter::which("nix") {
+        return Some(path);
+    }
+
+    let mut candidates = vec![PathBuf::from("/nix/var/nix/profiles/default/bin/nix")];
+    if let Some(home) = home_dir() {
+        candidates.push(home.join(".nix-profile").join("bin").join("nix"));
+    }
+    candidates
+        .into_iter()
+        .find(|path| path.exists())
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+/// Arguments that put `nix develop` in the project's dev shell, before `--command`.
+/// A flake (including a devenv flake) is used as is; a shell.nix or default.nix is
+/// loaded with `--file`, since `nix-shell --run` only takes a single command string
+/// and RubyMine appends ruby's arguments one by one.
+pub fn develop_args(project_dir: &Path) -> Vec<String> {
+    let dir = project_dir.to_string_lossy().to_string();
+    if project_dir.join("flake.nix").is_file() {
+        return vec!["develop".to_string(), dir];
+    }
+    let file = ["shell.nix", "default.nix"]
+        .into_iter()
+        .map(|name| project_dir.join(name))
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| project_dir.join("shell.nix"));
+    vec![
+        "develop".to_string(),
+        "--impure".to_string(),
+        "--file".to_string(),
+        file.to_string_lossy().to_string(),
+    ]
+}
+
+/// Whether the project defines a Nix dev shell at all
+pub fn has_dev_shell(project_dir: &Path) -> bool {
+    ["flake.nix", "shell.nix", "default.nix"]
+        .iter()
+        .any(|name| project_dir.join(name).is_file())
+}
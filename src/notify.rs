@@ -0,0 +1,43 @@
+use crate::output::Output;
+use crate::warnings;
+use std::process::Command;
+
+const TITLE: &str = "rubymine-configurator";
+
+/// Shows a desktop notification: Notification Center on macOS, libnotify's
+/// notify-send elsewhere. Failing to notify only warns, since the run itself is over.
+pub fn send(message: &str, out: &Output) {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification {} with title {}",
+            applescript_string(message),
+            applescript_string(TITLE)
+        ));
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.args(["--app-name", TITLE, TITLE, message]);
+        command
+    };
+    let program = command.get_program().to_string_lossy().to_string();
+    match command.output() {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => warnings::warn(
+            out,
+            format!(
+                "{} couldn't show a notification: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ),
+        Err(err) => warnings::warn(
+            out,
+            format!("couldn't run {} to show a notification: {}", program, err),
+        ),
+    }
+}
+
+fn applescript_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Which of the caller's streams a piece of output belongs on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stream {
+    /// Results: dry-run XML, summaries, what was written
+    Stdout,
+    /// Progress and diagnostics
+    Stderr,
+}
+
+type Printer = Box<dyn FnMut(Stream, &str) + Send>;
+
+/// Where a run's messages go. The library never prints: the binary hands each
+/// piece to stdout or stderr as it's written, and other callers keep it to read
+/// afterwards. Clones share the same output.
+#[derive(Clone, Default)]
+pub struct Output(Arc<Mutex<Inner>>);
+
+#[derive(Default)]
+struct Inner {
+    printer: Option<Printer>,
+    kept: Vec<(Stream, String)>,
+    warnings: usize,
+}
+
+impl Output {
+    /// Output that's kept, for `text` to return
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Output handed to `printer` as it's written instead of kept
+    pub fn printing(printer: impl FnMut(Stream, &str) + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(Inner {
+            printer: Some(Box::new(printer)),
+            kept: Vec::new(),
+            warnings: 0,
+        })))
+    }
+
+    /// Writes `text` as is
+    pub fn write(&self, stream: Stream, text: &str) {
+        let mut inner = self.inner();
+        match &mut inner.printer {
+            Some(printer) => printer(stream, text),
+            None => inner.kept.push((stream, text.to_string())),
+        }
+    }
+
+    /// A line of results
+    pub fn line(&self, text: impl fmt::Display) {
+        self.write(Stream::Stdout, &format!("{}\n", text));
+    }
+
+    /// A line of progress or diagnostics
+    pub fn status(&self, text: impl fmt::Display) {
+        self.write(Stream::Stderr, &format!("{}\n", text));
+    }
+
+    /// How many warnings `warnings::warn` wrote here, which --strict fails the run on
+    pub fn warnings(&self) -> usize {
+        self.inner().warnings
+    }
+
+    pub(crate) fn count_warning(&self) {
+        self.inner().warnings += 1;
+    }
+
+    /// Everything kept for `stream` so far; empty for printed output
+    pub fn text(&self, stream: Stream) -> String {
+        self.inner()
+            .kept
+            .iter()
+            .filter(|(kept_stream, _)| *kept_stream == stream)
+            .map(|(_, text)| text.as_str())
+            .collect()
+    }
+
+    fn inner(&self) -> MutexGuard<'_, Inner> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
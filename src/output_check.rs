@@ -0,0 +1,75 @@
+use crate::jdk::JdkEntry;
+use crate::jdk_table;
+use anyhow::{Context, Result};
+use roxmltree::{Document, Node};
+
+/// Checks a regenerated file against the one it replaces: it has to parse, with the
+/// same root element. An original that doesn't parse has nothing to compare with.
+pub fn check_same_root(original: &str, generated: &str) -> Result<()> {
+    let Ok(original) = Document::parse(original) else {
+        return Ok(());
+    };
+    let generated = Document::parse(generated).context("the generated XML doesn't parse")?;
+    let (before, after) = (
+        original.root_element().tag_name().name(),
+        generated.root_element().tag_name().name(),
+    );
+    if before != after {
+        anyhow::bail!(
+            "the root element would change from <{}> to <{}>",
+            before,
+            after
+        );
+    }
+    Ok(())
+}
+
+/// Checks a regenerated jdk.table.xml against the one it replaces, if any: the
+/// ProjectJdkTable component is still there, with the SDK called `name` and at least
+/// as many of the SDKs `replaces` doesn't claim as before
+pub fn check_jdk_table(
+    original: Option<&str>,
+    generated: &str,
+    name: &str,
+    replaces: impl Fn(&JdkEntry) -> bool,
+) -> Result<()> {
+    let doc = Document::parse(generated).context("the generated XML doesn't parse")?;
+    if !doc.descendants().any(|node| is_jdk_table(&node)) {
+        anyhow::bail!("the ProjectJdkTable component is missing");
+    }
+    let generated = entries(&doc);
+    if !generated.iter().any(|entry| entry.name == name) {
+        anyhow::bail!("the interpreter {} is missing", name);
+    }
+
+    let Some(original) = original.and_then(|original| Document::parse(original).ok()) else {
+        return Ok(());
+    };
+    let unrelated = |entries: &[JdkEntry]| {
+        entries
+            .iter()
+            .filter(|entry| entry.name != name && !replaces(entry))
+            .count()
+    };
+    let (before, after) = (unrelated(&entries(&original)), unrelated(&generated));
+    if after < before {
+        anyhow::bail!(
+            "{} of the {} other SDKs would be lost",
+            before - after,
+            before
+        );
+    }
+    Ok(())
+}
+
+fn is_jdk_table(node: &Node) -> bool {
+    node.has_tag_name("component") && node.attribute("name") == Some(jdk_table::COMPONENT)
+}
+
+fn entries(doc: &Document) -> Vec<JdkEntry> {
+    doc.descendants()
+        .filter(is_jdk_table)
+        .flat_map(|table| table.children())
+        .filter_map(|child| JdkEntry::parse(&child))
+        .collect()
+}
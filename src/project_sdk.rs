@@ -0,0 +1,93 @@
+use crate::markers::{self, Block};
+use crate::xml_misc;
+use crate::xml_splice::{self, Splice};
+use anyhow::Result;
+use roxmltree::{Document, Node};
+use xmlwriter::{Options, XmlWriter};
+
+/// SDK type the Ruby plugin registers
+const SDK_TYPE: &str = "RUBY_SDK";
+
+/// The project SDK in misc.xml, stamped with a marker
+pub const PROJECT_SDK: Block = Block {
+    name: "project SDK",
+    is: is_root_manager,
+    attributes: &["project-jdk-name", "project-jdk-type"],
+};
+
+/// The module's own SDK in its .iml, stamped with a marker
+pub const MODULE_SDK: Block = Block {
+    name: "module SDK",
+    is: is_module_sdk,
+    attributes: &["jdkName", "jdkType"],
+};
+
+/// .idea/misc.xml with the project SDK set to the interpreter called `name` and
+/// stamped with a marker, or `None` when it already is. The SDK itself lives in the
+/// IDE's jdk.table.xml; the project only refers to it by name.
+pub fn set_project_sdk(misc_xml: Option<&str>, name: &str) -> Result<Option<String>> {
+    let Some(misc_xml) = misc_xml else {
+        let mut writer = XmlWriter::new(Options::default());
+        writer.write_declaration();
+        writer.start_element("project");
+        writer.write_attribute("version", "4");
+        write_root_manager(&mut writer, name);
+        writer.end_element();
+        return markers::stamp_block(&writer.end_document(), &PROJECT_SDK).map(Some);
+    };
+
+    let doc = Document::parse(misc_xml)?;
+    let root_manager = doc.root_element().children().find(is_root_manager);
+    let mut splice = Splice::new(misc_xml);
+    match root_manager {
+        Some(component)
+            if component.attribute("project-jdk-name") == Some(name)
+                && component.attribute("project-jdk-type") == Some(SDK_TYPE) => {}
+        Some(component) => {
+            splice.set_attribute(&component, "project-jdk-name", name);
+            splice.set_attribute(&component, "project-jdk-type", SDK_TYPE);
+        }
+        None => {
+            let component = xml_splice::fragment(|writer| {
+                write_root_manager(writer, name);
+                Ok(())
+            })?;
+            splice.append_child(&doc.root_element(), &component);
+        }
+    }
+    let updated = markers::stamp_block(&splice.finish(), &PROJECT_SDK)?;
+    Ok((updated != misc_xml).then_some(updated))
+}
+
+/// The module file with its own SDK entry pointed at `name` and stamped with a
+/// marker, or `None` when it has none (it inherits the project's) or already uses it
+pub fn set_module_sdk(iml: &str, name: &str) -> Result<Option<String>> {
+    let doc = Document::parse(iml)?;
+    let Some(entry) = doc.descendants().find(is_module_sdk) else {
+        return Ok(None);
+    };
+    let mut splice = Splice::new(iml);
+    if entry.attribute("jdkName") != Some(name) || entry.attribute("jdkType") != Some(SDK_TYPE) {
+        splice.set_attribute(&entry, "jdkName", name);
+        splice.set_attribute(&entry, "jdkType", SDK_TYPE);
+    }
+    let updated = markers::stamp_block(&splice.finish(), &MODULE_SDK)?;
+    Ok((updated != iml).then_some(updated))
+}
+
+fn is_root_manager(node: &Node) -> bool {
+    node.has_tag_name("component") && node.attribute("name") == Some("ProjectRootManager")
+}
+
+fn is_module_sdk(node: &Node) -> bool {
+    node.has_tag_name("orderEntry") && node.attribute("type") == Some("jdk")
+}
+
+fn write_root_manager(writer: &mut XmlWriter, name: &str) {
+    writer.start_element("component");
+    writer.write_attribute("name", "ProjectRootManager");
+    writer.write_attribute("version", "2");
+    xml_misc::write_attribute(writer, "project-jdk-name", name);
+    writer.write_attribute("project-jdk-type", SDK_TYPE);
+    writer.end_element();
+}
@@ -0,0 +1,141 @@
+use crate::denylist::Denylist;
+use crate::fs_backend::FsBackend;
+use crate::jdk::JdkEntry;
+use crate::output::Output;
+use crate::safe_write;
+use crate::state::{Artifact, State};
+use crate::xml_splice::Splice;
+use anyhow::{Context, Result};
+use roxmltree::Document;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Removes every interpreter, data source and run configuration recorded under `tag`,
+/// then forgets them. Interpreters `protected` protects stay, along with their records.
+pub fn run(
+    fs_backend: &FsBackend,
+    tag: &str,
+    protected: &Denylist,
+    dry_run: bool,
+    out: &Output,
+) -> Result<()> {
+    let mut state = State::load()?;
+    let (tagged, mut kept): (Vec<Artifact>, Vec<Artifact>) = state
+        .artifacts
+        .iter()
+        .cloned()
+        .partition(|artifact| artifact.tag.as_deref() == Some(tag));
+    if tagged.is_empty() {
+        out.line(format!("Nothing recorded with tag {}", tag));
+        return Ok(());
+    }
+
+    // dataSources.local.xml holds the other half of each data source
+    let mut names_by_file: BTreeMap<PathBuf, Vec<(&str, String)>> = BTreeMap::new();
+    for artifact in &tagged {
+        let element = match artifact.kind.as_str() {
+            "interpreter" => "jdk",
+            "runconfig" => "configuration",
+            _ => "data-source",
+        };
+        names_by_file
+            .entry(artifact.file.clone())
+            .or_default()
+            .push((element, artifact.name.clone()));
+        if element == "data-source" {
+            names_by_file
+                .entry(artifact.file.with_file_name("dataSources.local.xml"))
+                .or_default()
+                .push((element, artifact.name.clone()));
+        }
+    }
+
+    let mut left = Vec::new();
+    for (file, names) in &names_by_file {
+        let mut removed = Vec::new();
+        let mut file_left = Vec::new();
+        safe_write::read_modify_write(
+            file,
+            || {
+                removed.clear();
+                file_left.clear();
+                let Ok(content) = fs::read_to_string(file) else {
+                    return Ok(None);
+                };
+                let doc = Document::parse(&content)
+                    .with_context(|| format!("Failed to parse {}", file.display()))?;
+
+                // Only the pruned elements go, the rest of the file stays byte-identical
+                let mut splice = Splice::new(&content);
+                let mut removed_nodes = Vec::new();
+                for node in doc.root_element().descendants().skip(1) {
+                    if !node.is_element()
+                        || node
+                            .ancestors()
+                            .any(|ancestor| removed_nodes.contains(&ancestor))
+                    {
+                        continue;
+                    }
+                    let name = node
+                        .children()
+                        .find(|child| child.has_tag_name("name"))
+                        .and_then(|child| child.attribute("value"))
+                        .or_else(|| node.attribute("name"));
+                    let matched = names.iter().find(|(element, artifact_name)| {
+                        node.has_tag_name(*element) && name == Some(artifact_name.as_str())
+                    });
+                    let Some((_, artifact_name)) = matched else {
+                        continue;
+                    };
+                    if JdkEntry::parse(&node).is_some_and(|entry| protected.protects(&entry)) {
+                        file_left.push(artifact_name.clone());
+                        continue;
+                    }
+                    removed.push(artifact_name.clone());
+                    splice.remove(&node);
+                    removed_nodes.push(node);
+                }
+                Ok((!dry_run && !removed.is_empty()).then(|| splice.finish()))
+            },
+            |content| {
+                if let Some(backup) = fs_backend.backup(file)? {
+                    out.line(format!("Backup created: {}", backup.display()));
+                }
+                fs_backend.write(file, content)
+            },
+            out,
+        )?;
+
+        for name in &removed {
+            out.line(format!(
+                "{}Removing {} from {}",
+                if dry_run { "# " } else { "" },
+                name,
+                file.display()
+            ));
+        }
+        left.extend(file_left);
+    }
+
+    for name in &left {
+        out.line(format!("Leaving protected interpreter: {}", name));
+    }
+
+    if !dry_run {
+        let pruned = tagged.len();
+        kept.extend(
+            tagged
+                .into_iter()
+                .filter(|artifact| artifact.kind == "interpreter" && left.contains(&artifact.name)),
+        );
+        state.artifacts = kept;
+        state.save(fs_backend)?;
+        out.line(format!(
+            "Pruned {} artifact(s) tagged {}",
+            pruned - left.len(),
+            tag
+        ));
+    }
+    Ok(())
+}
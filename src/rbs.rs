@@ -0,0 +1,90 @@
+use crate::{bundle, xml_misc};
+use anyhow::Result;
+use roxmltree::{Document, Node};
+use std::path::Path;
+use xmlwriter::{Options, XmlWriter};
+
+/// Directory RBS signatures live in by convention
+pub const SIG_DIR: &str = "sig";
+
+pub fn has_signatures(project_dir: &Path) -> bool {
+    project_dir.join(SIG_DIR).is_dir()
+}
+
+/// .idea/rbs.xml turning on the RBS-aware features, plus the rbs collection and
+/// TypeProf when the project uses them
+pub fn settings_xml(project_dir: &Path) -> String {
+    let mut writer = XmlWriter::new(Options::default());
+    writer.write_declaration();
+    writer.start_element("project");
+    writer.write_attribute("version", "4");
+    writer.start_element("component");
+    writer.write_attribute("name", "RbsSettings");
+
+    let options = [
+        ("enabled", true),
+        (
+            "useRbsCollection",
+            project_dir.join("rbs_collection.yaml").is_file(),
+        ),
+        ("typeProfEnabled", bundle::has_gem(project_dir, "typeprof")),
+    ];
+    for (name, value) in options {
+        writer.start_element("option");
+        writer.write_attribute("name", name);
+        writer.write_attribute("value", if value { "true" } else { "false" });
+        writer.end_element();
+    }
+
+    writer.end_element(); // component
+    writer.end_element(); // project
+    writer.end_document()
+}
+
+/// The module file with `sig` added as a source root of its content root, or `None`
+/// when it's already there
+pub fn mark_source_root(iml: &str) -> Result<Option<String>> {
+    let doc = Document::parse(iml)?;
+    let already_marked = doc.descendants().any(|node| {
+        node.has_tag_name("sourceFolder")
+            && node
+                .attribute("url")
+                .is_some_and(|url| url.ends_with(&format!("/{}", SIG_DIR)))
+    });
+    if already_marked {
+        return Ok(None);
+    }
+
+    let mut writer = XmlWriter::new(Options::default());
+    writer.write_declaration();
+    let mut added = false;
+    xml_misc::write_document(&mut writer, &doc, |writer, root| {
+        write_element(writer, root, &mut added);
+        Ok(())
+    })?;
+    Ok(added.then(|| xml_misc::end_document(writer)))
+}
+
+fn write_element(writer: &mut XmlWriter, node: &Node, added: &mut bool) {
+    writer.start_element(node.tag_name().name());
+    xml_misc::copy_attributes(writer, node);
+    for child in node.children() {
+        if child.is_element() {
+            write_element(writer, &child, added);
+        } else {
+            xml_misc::write(writer, &child);
+        }
+    }
+
+    // Only the first content root, which is the project dir in a generated module
+    if node.has_tag_name("content") && !*added {
+        if let Some(url) = node.attribute("url") {
+            writer.start_element("sourceFolder");
+            xml_misc::write_attribute(writer, "url", &format!("{}/{}", url, SIG_DIR));
+            writer.write_attribute("isTestSource", "false");
+            writer.end_element();
+            *added = true;
+        }
+    }
+    writer.end_element();
+}
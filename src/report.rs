@@ -0,0 +1,265 @@
+use crate::debugger::Debugger;
+use crate::docker::Container;
+use crate::{rbs, scripts, RubyMineInterpreter, RunOptions};
+use anyhow::Result;
+use chrono::Local;
+use serde::Serialize;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub const FILE_NAME: &str = "rubymine-configurator-report.md";
+
+/// What this run configured, built from the files it actually wrote, for teammates
+/// wondering where the IDE setup came from and provisioning systems archiving runs
+#[derive(Serialize)]
+pub struct Report {
+    pub ide: String,
+    pub project: String,
+    pub generated_at: String,
+    pub interpreter: InterpreterSummary,
+    pub data_sources: Vec<DataSourceSummary>,
+    pub run_configurations: Vec<String>,
+    pub files_written: Vec<PathBuf>,
+    /// The command line that reproduces the run, `None` when it didn't come from the CLI
+    pub command: Option<String>,
+    /// Why the run failed, when it did
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct InterpreterSummary {
+    pub name: String,
+    pub ruby: String,
+    pub home_path: String,
+    pub gems_bin_dir: String,
+    pub container: Option<String>,
+    pub host: Option<String>,
+    pub runs_through: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct DataSourceSummary {
+    pub name: String,
+    pub user: String,
+    pub host: String,
+    pub port: String,
+    pub schemas: Vec<String>,
+}
+
+impl Report {
+    pub fn collect(
+        interpreter: &RubyMineInterpreter,
+        args: &RunOptions,
+        error: Option<&anyhow::Error>,
+    ) -> Result<Self> {
+        let project_dir = Path::new(&interpreter.current_dir);
+        let written = interpreter.fs.written();
+
+        let (container, host) = match interpreter.docker.as_ref().map(|docker| &docker.container) {
+            Some(container @ Container::Ssh(_)) => (None, Some(container.to_string())),
+            Some(container) => (Some(container.to_string()), None),
+            None => (None, None),
+        };
+        let runs_through = (interpreter.docker.is_none() && !interpreter.shim)
+            .then(|| interpreter.configurator_options().join(" "));
+
+        let data_sources = interpreter
+            .data_sources(&[])?
+            .into_iter()
+            .map(|data_source| DataSourceSummary {
+                name: data_source.name,
+                user: data_source.user,
+                host: data_source.host,
+                port: data_source.port,
+                schemas: data_source.schemas,
+            })
+            .collect();
+
+        let mut run_configurations = Vec::new();
+        let workspace_files = interpreter.find_workspace_files().unwrap_or_default();
+        if workspace_files.iter().any(|file| written.contains(file)) {
+            run_configurations
+                .push("Minitest template: RUBY_ARGS and the bundler environment".to_string());
+        }
+        if let Some(debugger) = Debugger::detect(project_dir) {
+            run_configurations.push(format!(
+                "Attach to {} ({})",
+                debugger.name(),
+                interpreter.project_name
+            ));
+        }
+        if rbs::has_signatures(project_dir) {
+            run_configurations.push(format!(
+                "RBS support, with {}/ as a source root",
+                rbs::SIG_DIR
+            ));
+        }
+
+        Ok(Self {
+            ide: interpreter.ide.display_name().to_string(),
+            project: interpreter.project_name.clone(),
+            generated_at: Local::now().format("%Y-%m-%d %H:%M").to_string(),
+            interpreter: InterpreterSummary {
+                name: interpreter.interpreter_name.clone(),
+                ruby: interpreter.ruby.sdk_version(),
+                home_path: interpreter.home_path()?,
+                gems_bin_dir: interpreter.gems_bin_dir.clone(),
+                container,
+                host,
+                runs_through,
+            },
+            data_sources,
+            run_configurations,
+            files_written: written,
+            command: args.invocation.as_deref().map(command_line),
+            error: error.map(|error| format!("{:#}", error)),
+        })
+    }
+
+    /// The markdown report, with written files shown relative to `project_dir`
+    pub fn markdown(&self, project_dir: &Path) -> Result<String> {
+        let mut report = String::new();
+
+        writeln!(report, "# {} configuration for {}", self.ide, self.project)?;
+        writeln!(report)?;
+        writeln!(
+            report,
+            "Generated by rubymine-configurator on {}. Rerun it instead of editing the files below by hand.",
+            self.generated_at
+        )?;
+        if let Some(error) = &self.error {
+            writeln!(report)?;
+            writeln!(report, "**The run failed:** {}", error)?;
+        }
+
+        writeln!(report)?;
+        writeln!(report, "## Interpreter")?;
+        writeln!(report)?;
+        let interpreter = &self.interpreter;
+        let interpreter_rows = [
+            ("Name", Some(&interpreter.name)),
+            ("Ruby", Some(&interpreter.ruby)),
+            ("Interpreter", Some(&interpreter.home_path)),
+            ("Gems bin dir", Some(&interpreter.gems_bin_dir)),
+            ("Container", interpreter.container.as_ref()),
+            ("Host", interpreter.host.as_ref()),
+            ("Runs through", interpreter.runs_through.as_ref()),
+        ];
+        for (label, value) in interpreter_rows {
+            if let Some(value) = value {
+                writeln!(report, "- **{}:** `{}`", label, value)?;
+            }
+        }
+
+        writeln!(report)?;
+        writeln!(report, "## Data sources")?;
+        writeln!(report)?;
+        if self.data_sources.is_empty() {
+            writeln!(report, "None.")?;
+        }
+        for data_source in &self.data_sources {
+            writeln!(
+                report,
+                "- **{}:** `{}@{}:{}` ({})",
+                data_source.name,
+                data_source.user,
+                data_source.host,
+                data_source.port,
+                data_source.schemas.join(", ")
+            )?;
+        }
+
+        writeln!(report)?;
+        writeln!(report, "## Run configurations")?;
+        writeln!(report)?;
+        if self.run_configurations.is_empty() {
+            writeln!(report, "None.")?;
+        }
+        for run_config in &self.run_configurations {
+            writeln!(report, "- {}", run_config)?;
+        }
+
+        writeln!(report)?;
+        writeln!(report, "## Files written")?;
+        writeln!(report)?;
+        for file in &self.files_written {
+            let shown = file.strip_prefix(project_dir).unwrap_or(file);
+            writeln!(report, "- `{}`", shown.display())?;
+        }
+
+        if let Some(command) = &self.command {
+            writeln!(report)?;
+            writeln!(report, "## Reproducing")?;
+            writeln!(report)?;
+            writeln!(report, "From the project directory:")?;
+            writeln!(report)?;
+            writeln!(report, "```sh")?;
+            writeln!(report, "{}", command)?;
+            writeln!(report, "```")?;
+        }
+
+        Ok(report)
+    }
+
+    pub fn json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+
+    /// A few lines for someone watching the terminal
+    pub fn summary(&self) -> String {
+        let outcome = match &self.error {
+            Some(error) => format!("failed: {}", error),
+            None => format!("configured {}", self.interpreter.name),
+        };
+        format!(
+            "{}: {}\n  {} file(s) written, {} data source(s), {} run configuration(s)\n",
+            self.project,
+            outcome,
+            self.files_written.len(),
+            self.data_sources.len(),
+            self.run_configurations.len()
+        )
+    }
+}
+
+/// Where `--report-to` sends the report
+#[derive(Debug, Clone)]
+pub enum Sink {
+    /// `-`: the summary on stdout
+    Stdout,
+    /// JSON when the file ends in .json, markdown otherwise
+    File(PathBuf),
+}
+
+impl FromStr for Sink {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        match value {
+            "" => Err("expected - or a file path".to_string()),
+            "-" => Ok(Sink::Stdout),
+            path => Ok(Sink::File(PathBuf::from(path))),
+        }
+    }
+}
+
+impl Sink {
+    pub fn render(&self, report: &Report, project_dir: &Path) -> Result<String> {
+        match self {
+            Sink::Stdout => Ok(report.summary()),
+            Sink::File(path) if path.extension().is_some_and(|ext| ext == "json") => report.json(),
+            Sink::File(_) => report.markdown(project_dir),
+        }
+    }
+}
+
+/// `args`, the arguments the CLI was started with, quoted so it can be pasted back
+/// into a shell
+fn command_line(args: &[String]) -> String {
+    ["rubymine-configurator".to_string()]
+        .into_iter()
+        .chain(args.iter().map(|arg| scripts::shell_quote(arg)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
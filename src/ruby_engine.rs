@@ -0,0 +1,143 @@
+use crate::ruby_wrapper::is_executable;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Ruby snippet printing what `RubyInfo::parse` reads, one value per line
+pub const PROBE: &str = "puts RUBY_VERSION; puts RUBY_ENGINE; puts RUBY_ENGINE_VERSION";
+
+/// Implementation of the detected ruby, from `RUBY_ENGINE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RubyEngine {
+    Mri,
+    JRuby,
+    TruffleRuby,
+}
+
+impl RubyEngine {
+    /// Anything unrecognized is treated like MRI, which is what RubyMine assumes too
+    pub fn from_engine(engine: &str) -> Self {
+        match engine {
+            "jruby" => RubyEngine::JRuby,
+            "truffleruby" => RubyEngine::TruffleRuby,
+            _ => RubyEngine::Mri,
+        }
+    }
+
+    /// Leading word of the interpreter name
+    pub fn label(self) -> &'static str {
+        match self {
+            RubyEngine::Mri => "Ruby",
+            RubyEngine::JRuby => "JRuby",
+            RubyEngine::TruffleRuby => "TruffleRuby",
+        }
+    }
+
+    /// Name of the launcher RubyMine recognizes the implementation by
+    fn launcher_name(self) -> Option<&'static str> {
+        match self {
+            RubyEngine::Mri => None,
+            RubyEngine::JRuby => Some("jruby"),
+            RubyEngine::TruffleRuby => Some("truffleruby"),
+        }
+    }
+
+    /// The path to write as homePath. JRuby and TruffleRuby installs also ship a `ruby`
+    /// (a symlink or copy of the launcher), but an SDK pointing at it is shown as
+    /// broken, so prefer the engine's own launcher next to it.
+    pub fn home_path(self, ruby_path: &str) -> String {
+        let Some(launcher) = self.launcher_name() else {
+            return ruby_path.to_string();
+        };
+        let path = Path::new(ruby_path);
+        if path.file_name().and_then(|name| name.to_str()) == Some(launcher) {
+            return ruby_path.to_string();
+        }
+        path.parent()
+            .map(|bin| bin.join(launcher))
+            .filter(|launcher| launcher.is_file())
+            .map(|launcher| launcher.to_string_lossy().to_string())
+            .unwrap_or_else(|| ruby_path.to_string())
+    }
+
+    /// Where gems bundled with the install keep their executables, beyond `Gem.bindir`
+    pub fn bundled_gems_bin_dir(self, ruby_path: &str) -> Option<PathBuf> {
+        let home = Path::new(ruby_path).parent()?.parent()?;
+        match self {
+            RubyEngine::Mri => None,
+            RubyEngine::JRuby => Some(home.join("lib/ruby/gems/shared/bin")),
+            RubyEngine::TruffleRuby => Some(home.join("lib/gems/bin")),
+        }
+    }
+}
+
+/// Whether `path` is the ruby the OS ships (macOS' /usr/bin/ruby, a distro's ruby
+/// package), which is almost never the one a project's environment asks for
+pub fn is_system_ruby(path: &Path) -> bool {
+    ["/usr/bin/", "/System/Library/Frameworks/Ruby.framework/"]
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+}
+
+/// Why `home_path` can't be an SDK's interpreter, if it can't: it has to be an
+/// executable that answers `--version` like a ruby (or jruby, truffleruby) does
+pub fn check_interpreter(home_path: &Path) -> Option<String> {
+    if !home_path.exists() {
+        return Some(format!("{} doesn't exist", home_path.display()));
+    }
+    if !is_executable(home_path) {
+        return Some(format!("{} isn't an executable file", home_path.display()));
+    }
+    let output = match Command::new(home_path).arg("--version").output() {
+        Ok(output) => output,
+        Err(err) => return Some(format!("{} couldn't be run: {}", home_path.display(), err)),
+    };
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || !version.to_lowercase().contains("ruby") {
+        return Some(format!(
+            "{} --version didn't answer like a ruby ({:?})",
+            home_path.display(),
+            version
+        ));
+    }
+    None
+}
+
+/// What the detected ruby reports about itself
+#[derive(Debug, Clone)]
+pub struct RubyInfo {
+    /// RUBY_VERSION, the language version the implementation is compatible with
+    pub version: String,
+    pub engine: RubyEngine,
+    /// RUBY_ENGINE_VERSION, e.g. 9.4.5.0 for JRuby
+    pub engine_version: String,
+}
+
+impl RubyInfo {
+    /// Parses the output of `PROBE`; older rubies without the engine constants are MRI
+    pub fn parse<'a>(mut lines: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let version = lines.next()?.trim().to_string();
+        if version.is_empty() {
+            return None;
+        }
+        let engine = RubyEngine::from_engine(lines.next().unwrap_or_default().trim());
+        let engine_version = lines
+            .next()
+            .map(|line| line.trim().to_string())
+            .filter(|engine_version| !engine_version.is_empty())
+            .unwrap_or_else(|| version.clone());
+        Some(Self {
+            version,
+            engine,
+            engine_version,
+        })
+    }
+
+    /// Version shown in the name and the SDK's `<version>`: the plain version for MRI,
+    /// e.g. `jruby-9.4.5.0` otherwise
+    pub fn sdk_version(&self) -> String {
+        match self.engine.launcher_name() {
+            None => self.version.clone(),
+            Some(engine) => format!("{}-{}", engine, self.engine_version),
+        }
+    }
+}
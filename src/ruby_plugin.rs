@@ -0,0 +1,27 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the Ruby plugin keeps the scripts test runs load, relative to its root
+pub fn patch_dir(plugin_root: &Path) -> PathBuf {
+    plugin_root.join("rb").join("testing").join("patch")
+}
+
+/// The Ruby plugin in the first of `plugins_dirs` that has one. Its directory is
+/// usually `ruby`, but marketplace and custom installs can name it otherwise, so
+/// any plugin with the test patch scripts counts.
+pub fn find(plugins_dirs: &[PathBuf]) -> Option<PathBuf> {
+    plugins_dirs.iter().find_map(|plugins_dir| {
+        let ruby = plugins_dir.join("ruby");
+        if patch_dir(&ruby).is_dir() {
+            return Some(ruby);
+        }
+        let mut plugins: Vec<PathBuf> = fs::read_dir(plugins_dir)
+            .ok()?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|plugin| patch_dir(plugin).is_dir())
+            .collect();
+        plugins.sort();
+        plugins.into_iter().next()
+    })
+}
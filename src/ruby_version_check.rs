@@ -0,0 +1,104 @@
+use crate::ruby_engine::{RubyEngine, RubyInfo};
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+
+/// A version the project asks for, and where it asked
+pub struct Requirement {
+    pub source: &'static str,
+    pub version: String,
+}
+
+/// Versions requested by `.ruby-version` and the Gemfile's `ruby "x.y.z"` directive
+pub fn requirements(project_dir: &Path) -> Vec<Requirement> {
+    let mut requirements = Vec::new();
+
+    if let Ok(content) = fs::read_to_string(project_dir.join(".ruby-version")) {
+        // Version managers accept "ruby-3.3.0" and "3.3.0"
+        let version = content.trim();
+        let version = version.strip_prefix("ruby-").unwrap_or(version);
+        if !version.is_empty() {
+            requirements.push(Requirement {
+                source: ".ruby-version",
+                version: version.to_string(),
+            });
+        }
+    }
+
+    if let Ok(content) = fs::read_to_string(project_dir.join("Gemfile")) {
+        let directive = Regex::new(r#"(?m)^\s*ruby\s*\(?\s*["']([^"']+)["']"#).unwrap();
+        if let Some(captures) = directive.captures(&content) {
+            requirements.push(Requirement {
+                source: "Gemfile",
+                version: captures[1].trim().to_string(),
+            });
+        }
+    }
+
+    requirements
+}
+
+/// Whether `ruby` satisfies `required`. An engine-qualified requirement such as
+/// `jruby-9.4.5.0` is checked against the engine and its own version.
+pub fn satisfied_by(ruby: &RubyInfo, required: &str) -> bool {
+    for engine in ["jruby", "truffleruby"] {
+        if let Some(version) = required
+            .strip_prefix(engine)
+            .and_then(|rest| rest.strip_prefix('-'))
+        {
+            return ruby.engine == RubyEngine::from_engine(engine)
+                && satisfies(&ruby.engine_version, version);
+        }
+    }
+    satisfies(&ruby.version, required)
+}
+
+/// Whether `actual` (RUBY_VERSION) satisfies `required`, which may be an exact or
+/// partial version ("3.3" matches 3.3.x) or a `~>`/`>=` style constraint
+fn satisfies(actual: &str, required: &str) -> bool {
+    let required = required.trim();
+    let (operator, version) = ["~>", ">=", "<=", "!=", "=", ">", "<"]
+        .iter()
+        .find_map(|operator| {
+            required
+                .strip_prefix(operator)
+                .map(|rest| (*operator, rest.trim()))
+        })
+        .unwrap_or(("", required));
+
+    let actual_parts = parse(actual);
+    let required_parts = parse(version);
+    let compared = compare(&actual_parts, &required_parts);
+
+    match operator {
+        "" | "=" => actual_parts.starts_with(&required_parts),
+        "!=" => !actual_parts.starts_with(&required_parts),
+        ">=" => compared.is_ge(),
+        "<=" => compared.is_le(),
+        ">" => compared.is_gt(),
+        "<" => compared.is_lt(),
+        // ~> 3.2.1 means >= 3.2.1 and < 3.3; ~> 3.2 means >= 3.2 and < 4
+        _ => {
+            let fixed = required_parts.len().saturating_sub(1).max(1);
+            compared.is_ge()
+                && actual_parts.starts_with(&required_parts[..fixed.min(required_parts.len())])
+        }
+    }
+}
+
+fn parse(version: &str) -> Vec<u64> {
+    version
+        .split(['.', '-'])
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+fn compare(actual: &[u64], required: &[u64]) -> std::cmp::Ordering {
+    let len = actual.len().max(required.len());
+    let pad = |parts: &[u64]| {
+        let mut parts = parts.to_vec();
+        parts.resize(len, 0);
+        parts
+    };
+    pad(actual).cmp(&pad(required))
+}
@@ -0,0 +1,286 @@
+use dirs::home_dir;
+use std::collections::HashMap;
+use std::env;
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+
+/// The program a wrapper script hands over to, taken from its last `exec` line that
+/// names an existing executable. Understands `exec env VAR=... /path/ruby "$@"`, line
+/// continuations, `$VAR`/`${VAR}`/`~` expansion (from the script's own assignments or
+/// the environment) outside single quotes and skips heredoc bodies.
+pub fn exec_target(content: &str) -> Option<String> {
+    let mut variables: HashMap<String, String> = HashMap::new();
+    let mut target = None;
+    let mut heredoc_end: Option<String> = None;
+
+    for line in content.replace("\\\n", " ").lines() {
+        let line = line.trim();
+        if let Some(end) = &heredoc_end {
+            if line == end {
+                heredoc_end = None;
+            }
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let words = split_words(line, &variables);
+        heredoc_end = heredoc_terminator(&words);
+
+        // NAME=value and export NAME=value, for expanding later lines
+        if let [assignment] | [_, assignment] = words.as_slice() {
+            if words.len() == 1 || words[0] == "export" {
+                if let Some((name, value)) = assignment.split_once('=') {
+                    if is_variable_name(name) {
+                        variables.insert(name.to_string(), value.to_string());
+                        continue;
+                    }
+                }
+            }
+        }
+
+        let Some(exec_index) = words.iter().position(|word| word == "exec") else {
+            continue;
+        };
+        if let Some(program) = program_after_exec(&words[exec_index + 1..]) {
+            if is_executable(Path::new(program)) {
+                target = Some(program.clone());
+            }
+        }
+    }
+
+    target
+}
+
+/// Skips `exec` and `env` options and `VAR=value` assignments to reach the program
+fn program_after_exec(words: &[String]) -> Option<&String> {
+    let mut words = words.iter().peekable();
+    while let Some(word) = words.next() {
+        match word.as_str() {
+            // exec -a NAME, env -u NAME, env -C DIR take an argument
+            "-a" | "-u" | "-C" => {
+                words.next();
+            }
+            "env" | "command" | "-" | "--" => {}
+            _ if word.starts_with('-') => {}
+            _ if word
+                .split_once('=')
+                .is_some_and(|(name, _)| is_variable_name(name)) => {}
+            _ if word.starts_with('<') || word.starts_with('>') => return None,
+            _ => return Some(word),
+        }
+    }
+    None
+}
+
+/// The terminator of a heredoc started on this line (`<<EOF`, `<<-'EOF'`), if any.
+/// Here-strings (`<<<`) don't span lines and are ignored.
+fn heredoc_terminator(words: &[String]) -> Option<String> {
+    words.iter().enumerate().find_map(|(index, word)| {
+        if word.starts_with("<<<") {
+            return None;
+        }
+        let rest = word.strip_prefix("<<")?;
+        let rest = rest.strip_prefix('-').unwrap_or(rest);
+        let end = if rest.is_empty() {
+            words.get(index + 1)?.as_str()
+        } else {
+            rest
+        };
+        Some(end.trim_matches(['\'', '"']).to_string())
+    })
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits a shell line into words, honoring single/double quotes and backslashes, and
+/// expands a leading `~` and `$NAME`/`${NAME}` references except in single quotes.
+/// Everything from an unquoted `#`, `;`, `&&` or `||` on is dropped.
+fn split_words(line: &str, variables: &HashMap<String, String>) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+    let mut quote: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some('"') if c == '\\' => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+            }
+            Some('"') if c == '$' => expand_variable(&mut chars, &mut current, variables),
+            Some(_) => current.push(c),
+            None => match c {
+                '\'' | '"' => {
+                    quote = Some(c);
+                    in_word = true;
+                }
+                '\\' => {
+                    if let Some(next) = chars.next() {
+                        current.push(next);
+                        in_word = true;
+                    }
+                }
+                ';' => break,
+                '#' if !in_word => break,
+                '&' | '|' if chars.peek() == Some(&c) => break,
+                // At the start of a word or of an assignment's value
+                '~' if (!in_word || current.strip_suffix('=').is_some_and(is_variable_name))
+                    && chars
+                        .peek()
+                        .is_none_or(|next| *next == '/' || next.is_whitespace()) =>
+                {
+                    current.push_str(&home());
+                    in_word = true;
+                }
+                '$' => {
+                    expand_variable(&mut chars, &mut current, variables);
+                    in_word = true;
+                }
+                c if c.is_whitespace() => {
+                    if in_word {
+                        words.push(std::mem::take(&mut current));
+                        in_word = false;
+                    }
+                }
+                c => {
+                    current.push(c);
+                    in_word = true;
+                }
+            },
+        }
+    }
+    if in_word {
+        words.push(current);
+    }
+    words
+}
+
+/// Pushes the value of the `NAME` or `{NAME}` following a `$` onto `word`, or the `$`
+/// itself when no name follows (e.g. `$@`). Variables come from the script's own
+/// assignments, then the environment.
+fn expand_variable(
+    chars: &mut Peekable<Chars>,
+    word: &mut String,
+    variables: &HashMap<String, String>,
+) {
+    let name: String = if chars.peek() == Some(&'{') {
+        chars.next();
+        chars.by_ref().take_while(|c| *c != '}').collect()
+    } else {
+        let mut name = String::new();
+        while let Some(c) = chars
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || **c == '_')
+        {
+            name.push(*c);
+            chars.next();
+        }
+        name
+    };
+    if name.is_empty() {
+        word.push('$');
+    } else if name == "HOME" {
+        word.push_str(&home());
+    } else {
+        let value = variables
+            .get(&name)
+            .cloned()
+            .or_else(|| env::var(&name).ok());
+        word.push_str(&value.unwrap_or_default());
+    }
+}
+
+fn home() -> String {
+    home_dir()
+        .map(|home| home.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(unix)]
+pub fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.is_file()
+        && path
+            .metadata()
+            .map(|metadata| metadata.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+pub fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        split_words(line, &HashMap::new())
+    }
+
+    #[test]
+    fn finds_the_program_behind_exec_env() {
+        let script = "#!/bin/sh\nexec env RUBYOPT=-W0 -u GEM_HOME /bin/sh \"$@\"\n";
+        assert_eq!(exec_target(script).as_deref(), Some("/bin/sh"));
+    }
+
+    #[test]
+    fn expands_home() {
+        let home = home();
+        assert_eq!(
+            words("exec $HOME/.rubies/ruby"),
+            ["exec".to_string(), format!("{}/.rubies/ruby", home)]
+        );
+        assert_eq!(
+            words("exec \"${HOME}/.rubies/ruby\" \"$@\""),
+            [
+                "exec".to_string(),
+                format!("{}/.rubies/ruby", home),
+                "$@".to_string()
+            ]
+        );
+        assert_eq!(
+            words("RUBIES=~/.rubies"),
+            [format!("RUBIES={}/.rubies", home)]
+        );
+    }
+
+    #[test]
+    fn expands_the_scripts_own_variables() {
+        let script = "RUBY_ROOT=/bin\nexport RUBY=\"$RUBY_ROOT\"/sh\nexec \"$RUBY\" \"$@\"\n";
+        assert_eq!(exec_target(script).as_deref(), Some("/bin/sh"));
+    }
+
+    #[test]
+    fn keeps_single_quoted_words_literal() {
+        assert_eq!(words("exec '$HOME/ruby' '~'"), ["exec", "$HOME/ruby", "~"]);
+        assert_eq!(words("RUBY='${RUBY_ROOT}'"), ["RUBY=${RUBY_ROOT}"]);
+        let script = "RUBY_ROOT=/bin\nexec '$RUBY_ROOT/sh' \"$@\"\n";
+        assert_eq!(exec_target(script), None);
+    }
+
+    #[test]
+    fn joins_line_continuations() {
+        let script = "exec env \\\n  GEM_HOME=/tmp/gems \\\n  /bin/sh \\\n  \"$@\"\n";
+        assert_eq!(exec_target(script).as_deref(), Some("/bin/sh"));
+    }
+
+    #[test]
+    fn skips_heredoc_bodies() {
+        let script = "exec /bin/sh \"$@\"\ncat <<-'EOF'\n  exec /bin/ls\nEOF\ncat <<USAGE >&2\nexec /bin/ls\nUSAGE\n";
+        assert_eq!(exec_target(script).as_deref(), Some("/bin/sh"));
+    }
+}
@@ -0,0 +1,130 @@
+use crate::xml_splice::Splice;
+use anyhow::Result;
+use roxmltree::{Document, Node};
+
+/// Setting naming the SDK a run configuration uses instead of the project's
+const SDK_NAME_SETTING: &str = "ALTERN_SDK_NAME";
+/// Setting saying whether that SDK is used at all
+const USE_SDK_SETTING: &str = "SHOULD_USE_SDK";
+
+/// A run configuration that runs with a specific SDK
+pub struct SdkReference {
+    /// Configuration name, or its factory for a template
+    pub configuration: String,
+    pub sdk_name: String,
+}
+
+/// Run configurations in workspace.xml that pick their own SDK. Settings are
+/// `<*_SETTINGS_ID NAME=... VALUE=...>` elements whose tag depends on the
+/// configuration type, so they're matched by NAME only.
+pub fn sdk_references(workspace_xml: &str) -> Result<Vec<SdkReference>> {
+    let doc = Document::parse(workspace_xml)?;
+    Ok(doc
+        .descendants()
+        .filter(|node| node.has_tag_name("configuration"))
+        .filter(|configuration| setting(configuration, USE_SDK_SETTING) == Some("true"))
+        .filter_map(|configuration| {
+            let sdk_name = setting(&configuration, SDK_NAME_SETTING)?;
+            let name = configuration
+                .attribute("name")
+                .map(|name| name.to_string())
+                .or_else(|| {
+                    configuration
+                        .attribute("factoryName")
+                        .map(|factory| format!("{} template", factory))
+                })
+                .unwrap_or_default();
+            Some(SdkReference {
+                configuration: name,
+                sdk_name: sdk_name.to_string(),
+            })
+        })
+        .collect())
+}
+
+fn setting<'a>(configuration: &Node<'a, '_>, name: &str) -> Option<&'a str> {
+    configuration
+        .children()
+        .find(|child| child.attribute("NAME") == Some(name))
+        .and_then(|child| child.attribute("VALUE"))
+}
+
+/// workspace.xml with every run configuration using one of `dangling` pointed at
+/// `sdk_name`, the rest of the file left byte-identical. Configurations that only
+/// remember an SDK they don't use (SHOULD_USE_SDK isn't true) are left alone, as
+/// `sdk_references` leaves them out.
+pub fn repoint(workspace_xml: &str, dangling: &[String], sdk_name: &str) -> Result<String> {
+    let doc = Document::parse(workspace_xml)?;
+    let mut splice = Splice::new(workspace_xml);
+    let settings = doc
+        .descendants()
+        .filter(|node| node.has_tag_name("configuration"))
+        .filter(|configuration| setting(configuration, USE_SDK_SETTING) == Some("true"))
+        .filter_map(|configuration| {
+            configuration
+                .children()
+                .find(|child| child.attribute("NAME") == Some(SDK_NAME_SETTING))
+        });
+    for node in settings {
+        let repointed = node
+            .attribute("VALUE")
+            .is_some_and(|value| dangling.iter().any(|name| name == value));
+        if repointed {
+            splice.set_attribute(&node, "VALUE", sdk_name);
+        }
+    }
+    Ok(splice.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORKSPACE: &str = r#"<project version="4">
+  <component name="RunManager">
+    <configuration name="specs" type="RSpecRunConfigurationType">
+      <module name="app" />
+      <RSPEC_RUN_CONFIG_SETTINGS_ID NAME="SHOULD_USE_SDK" VALUE="true" />
+      <RSPEC_RUN_CONFIG_SETTINGS_ID NAME="ALTERN_SDK_NAME" VALUE="Ruby 3.2.2" />
+    </configuration>
+    <configuration name="server" type="RailsRunConfigurationType">
+      <RAILS_SERVER_SETTINGS_ID NAME="SHOULD_USE_SDK" VALUE="false" />
+      <RAILS_SERVER_SETTINGS_ID NAME="ALTERN_SDK_NAME" VALUE="Ruby 3.2.2" />
+    </configuration>
+  </component>
+</project>
+"#;
+
+    #[test]
+    fn sdk_references_only_lists_configurations_using_their_sdk() {
+        let references = sdk_references(WORKSPACE).unwrap();
+        let names: Vec<(&str, &str)> = references
+            .iter()
+            .map(|reference| {
+                (
+                    reference.configuration.as_str(),
+                    reference.sdk_name.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(names, [("specs", "Ruby 3.2.2")]);
+    }
+
+    #[test]
+    fn repoint_leaves_configurations_not_using_their_sdk_alone() {
+        let dangling = ["Ruby 3.2.2".to_string()];
+        let repointed = repoint(WORKSPACE, &dangling, "Ruby 3.3.0 (app)").unwrap();
+        assert_eq!(
+            repointed,
+            WORKSPACE.replacen(
+                r#"NAME="ALTERN_SDK_NAME" VALUE="Ruby 3.2.2""#,
+                r#"NAME="ALTERN_SDK_NAME" VALUE="Ruby 3.3.0 (app)""#,
+                1
+            )
+        );
+        assert_eq!(
+            repoint(WORKSPACE, &[], "Ruby 3.3.0 (app)").unwrap(),
+            WORKSPACE
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use crate::output::Output;
+use anyhow::{Context, Result};
+use std::fs::{self, File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Suffixes of the files the IDE's "safe write" creates next to a file while saving
+/// it: the new content goes to the tmp file, the old one is renamed aside, then the
+/// tmp file takes its place
+const TEMP_SUFFIXES: &[&str] = &["___jb_tmp___", "___jb_old___"];
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long to wait for a save to finish before giving up
+const MAX_WAIT: Duration = Duration::from_secs(3);
+/// Temp files older than this were left behind by an IDE that died mid-save
+const STALE_AFTER: Duration = Duration::from_secs(60);
+/// Read-modify-write attempts before giving up on a file that keeps changing
+const ATTEMPTS: usize = 3;
+
+/// The IDE's safe-write files for `path` that look like a save in progress
+pub fn temp_files(path: &Path) -> Vec<PathBuf> {
+    let Some(name) = path.file_name() else {
+        return Vec::new();
+    };
+    TEMP_SUFFIXES
+        .iter()
+        .map(|suffix| {
+            let mut temp_name = name.to_os_string();
+            temp_name.push(suffix);
+            path.with_file_name(temp_name)
+        })
+        .filter(|temp| {
+            let age = fs::metadata(temp)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+            fs::symlink_metadata(temp).is_ok() && age.is_none_or(|age| age < STALE_AFTER)
+        })
+        .collect()
+}
+
+/// Waits until the IDE isn't saving `path`
+pub fn wait_until_idle(path: &Path) -> Result<()> {
+    let mut waited = Duration::ZERO;
+    loop {
+        let temp_files = temp_files(path);
+        let Some(temp_file) = temp_files.first() else {
+            return Ok(());
+        };
+        if waited >= MAX_WAIT {
+            anyhow::bail!(
+                "RubyMine is still saving {} ({} exists); try again once it's done",
+                path.display(),
+                temp_file.display()
+            );
+        }
+        thread::sleep(POLL_INTERVAL);
+        waited += POLL_INTERVAL;
+    }
+}
+
+/// Enough of a file's metadata to tell whether it was rewritten
+#[derive(PartialEq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: Option<u64>,
+}
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok();
+    Stamp {
+        modified: metadata
+            .as_ref()
+            .and_then(|metadata| metadata.modified().ok()),
+        len: metadata.map(|metadata| metadata.len()),
+    }
+}
+
+/// Takes an advisory lock (flock) on `path`'s directory, held until the file is
+/// dropped, so that two runs of the tool don't interleave their updates. The
+/// directory is locked rather than the file because writes replace the file.
+/// The IDE doesn't take the lock; the modification checks are what catch it.
+#[cfg(unix)]
+fn lock_dir(path: &Path, out: &Output) -> Result<Option<File>> {
+    let Some(dir) = path.parent().filter(|dir| dir.is_dir()) else {
+        return Ok(None);
+    };
+    let lock = File::open(dir).with_context(|| format!("Failed to open {}", dir.display()))?;
+    match lock.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            out.status(format!(
+                "Waiting for another run to finish updating files in {}",
+                dir.display()
+            ));
+            lock.lock()
+                .with_context(|| format!("Failed to lock {}", dir.display()))?;
+        }
+        Err(TryLockError::Error(err)) => {
+            return Err(err).with_context(|| format!("Failed to lock {}", dir.display()))
+        }
+    }
+    Ok(Some(lock))
+}
+
+/// Directories can't be opened as files here, so there's nothing to lock
+#[cfg(not(unix))]
+fn lock_dir(_path: &Path, _out: &Output) -> Result<Option<File>> {
+    Ok(None)
+}
+
+/// Reads and updates `path` with `modify`, then saves the result with `write`, unless
+/// the IDE started saving the file or changed it in between, in which case the whole
+/// cycle starts over. Other runs of the tool are kept out for the whole cycle.
+/// `modify` returns `None` when there's nothing to write.
+pub fn read_modify_write(
+    path: &Path,
+    mut modify: impl FnMut() -> Result<Option<String>>,
+    mut write: impl FnMut(&str) -> Result<()>,
+    out: &Output,
+) -> Result<()> {
+    let _lock = lock_dir(path, out)?;
+    for _ in 0..ATTEMPTS {
+        wait_until_idle(path)?;
+        let before = stamp(path);
+        let Some(content) = modify()? else {
+            return Ok(());
+        };
+        if temp_files(path).is_empty() && stamp(path) == before {
+            return write(&content);
+        }
+        out.status(format!(
+            "{} changed while it was being updated; starting over",
+            path.display()
+        ));
+    }
+    anyhow::bail!(
+        "{} kept changing while it was being updated, so nothing was written; close RubyMine or try again",
+        path.display()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_dir;
+    use std::cell::Cell;
+
+    /// A jdk.table.xml with `content` in a directory of its own
+    fn file(name: &str, content: &str) -> PathBuf {
+        let path = scratch_dir(name).join("jdk.table.xml");
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn temp_file(path: &Path, suffix: &str, age: Duration) -> PathBuf {
+        let mut name = path.file_name().unwrap().to_os_string();
+        name.push(suffix);
+        let temp = path.with_file_name(name);
+        File::create(&temp)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+        temp
+    }
+
+    #[test]
+    fn read_modify_write_starts_over_when_the_file_changes_in_between() {
+        let path = file("starts_over", "<application />");
+        let reads = Cell::new(0);
+        let mut written = Vec::new();
+        read_modify_write(
+            &path,
+            || {
+                reads.set(reads.get() + 1);
+                let content = fs::read_to_string(&path)?;
+                if reads.get() == 1 {
+                    fs::write(&path, "<application>saved by the IDE</application>")?;
+                }
+                Ok(Some(content + "\n"))
+            },
+            |content| {
+                written.push(content.to_string());
+                Ok(())
+            },
+            &Output::new(),
+        )
+        .unwrap();
+        assert_eq!(reads.get(), 2);
+        assert_eq!(written, ["<application>saved by the IDE</application>\n"]);
+    }
+
+    #[test]
+    fn read_modify_write_gives_up_on_a_file_that_keeps_changing() {
+        let path = file("keeps_changing", "");
+        let reads = Cell::new(0);
+        let err = read_modify_write(
+            &path,
+            || {
+                reads.set(reads.get() + 1);
+                fs::write(&path, "x".repeat(reads.get()))?;
+                Ok(Some(String::new()))
+            },
+            |_| panic!("nothing should be written"),
+            &Output::new(),
+        )
+        .unwrap_err();
+        assert_eq!(reads.get(), ATTEMPTS);
+        assert!(err.to_string().contains("kept changing"));
+    }
+
+    #[test]
+    fn read_modify_write_skips_writing_when_nothing_changed() {
+        let path = file("unchanged", "<application />");
+        read_modify_write(
+            &path,
+            || Ok(None),
+            |_| panic!("nothing should be written"),
+            &Output::new(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn temp_files_ignores_ones_left_behind_by_a_dead_ide() {
+        let path = file("temp_files", "<application />");
+        assert!(temp_files(&path).is_empty());
+
+        let tmp = temp_file(&path, "___jb_tmp___", Duration::ZERO);
+        let old = temp_file(&path, "___jb_old___", STALE_AFTER - Duration::from_secs(5));
+        assert_eq!(temp_files(&path), [tmp.clone(), old]);
+
+        temp_file(&path, "___jb_old___", STALE_AFTER + Duration::from_secs(5));
+        assert_eq!(temp_files(&path), [tmp]);
+    }
+
+    #[test]
+    fn wait_until_idle_returns_once_the_save_is_done() {
+        let path = file("wait", "<application />");
+        let tmp = temp_file(&path, "___jb_tmp___", Duration::ZERO);
+        let saving = thread::spawn(move || {
+            thread::sleep(POLL_INTERVAL * 2);
+            fs::remove_file(tmp).unwrap();
+        });
+        wait_until_idle(&path).unwrap();
+        assert!(temp_files(&path).is_empty());
+        saving.join().unwrap();
+
+        temp_file(&path, "___jb_old___", STALE_AFTER * 2);
+        wait_until_idle(&path).unwrap();
+    }
+}
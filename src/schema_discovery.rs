@@ -0,0 +1,63 @@
+use anyhow::{Context, Result};
+use std::process::Command;
+
+/// Schemas every MySQL server has, which are never worth introspecting
+const SYSTEM_SCHEMAS: &[&str] = &["information_schema", "mysql", "performance_schema", "sys"];
+
+/// Seconds to wait for the server before giving up on discovery
+const CONNECT_TIMEOUT: u32 = 3;
+
+/// The server's schemas starting with `prefix`, from `SHOW DATABASES` run through the
+/// mysql client. Fails when the client is missing or the server can't be reached.
+pub fn show_databases(
+    host: &str,
+    port: &str,
+    user: &str,
+    password: &str,
+    prefix: &str,
+) -> Result<Vec<String>> {
+    let output = Command::new("mysql")
+        .args([
+            "--host",
+            host,
+            "--port",
+            port,
+            "--user",
+            user,
+            &format!("--connect-timeout={}", CONNECT_TIMEOUT),
+            "--batch",
+            "--skip-column-names",
+            "--execute",
+            "SHOW DATABASES",
+        ])
+        // Keeps the password out of the process list
+        .env("MYSQL_PWD", password)
+        .output()
+        .context("Failed to run the mysql client")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim().to_string()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|schema| !schema.is_empty() && !SYSTEM_SCHEMAS.contains(schema))
+        .filter(|schema| schema.starts_with(prefix))
+        .map(String::from)
+        .collect())
+}
+
+/// The introspection scope for a data source: its own database (or `@`, the current
+/// schema) first, then the live schemas
+pub fn scope(configured: &[String], live: Vec<String>) -> Vec<String> {
+    let mut schemas = Vec::from_iter(configured.first().cloned());
+    for schema in live {
+        if !schemas.contains(&schema) {
+            schemas.push(schema);
+        }
+    }
+    schemas
+}
@@ -0,0 +1,239 @@
+use crate::xml_misc;
+use crate::xml_splice::{self, Splice};
+use anyhow::Result;
+use roxmltree::{Document, Node};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use xmlwriter::XmlWriter;
+
+/// A team workflow (db reseed, codegen...) as a run configuration, declared in
+/// config as a `[[scripts]]` table
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    /// Run configuration name
+    pub name: String,
+    /// What to run: a path relative to the working directory, or a command on the PATH
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Variables set on top of the project environment
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Directory it runs in, relative to the project dir [default: the project dir]
+    pub working_dir: Option<String>,
+    /// How the IDE runs it [default: ruby for .rb files, shell otherwise]
+    pub kind: Option<ScriptKind>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScriptKind {
+    /// A Ruby run configuration, run by the project's interpreter
+    Ruby,
+    /// A Shell Script run configuration, run through the configurator
+    Shell,
+}
+
+impl Script {
+    pub fn kind(&self) -> ScriptKind {
+        self.kind.unwrap_or(if self.command.ends_with(".rb") {
+            ScriptKind::Ruby
+        } else {
+            ScriptKind::Shell
+        })
+    }
+
+    /// The working directory, with the project dir as `$PROJECT_DIR$`
+    fn work_dir(&self) -> String {
+        match self.working_dir.as_deref() {
+            None | Some("") | Some(".") => "$PROJECT_DIR$".to_string(),
+            Some(dir) if Path::new(dir).is_absolute() => dir.to_string(),
+            Some(dir) => format!("$PROJECT_DIR$/{}", dir.trim_end_matches('/')),
+        }
+    }
+
+    fn write(&self, writer: &mut XmlWriter, module: &str, configurator: &[String]) {
+        writer.start_element("configuration");
+        xml_misc::write_attribute(writer, "name", &self.name);
+        match self.kind() {
+            ScriptKind::Ruby => self.write_ruby(writer, module),
+            ScriptKind::Shell => self.write_shell(writer, configurator),
+        }
+        writer.start_element("method");
+        writer.write_attribute("v", "2");
+        writer.end_element();
+        writer.end_element(); // configuration
+    }
+
+    fn write_ruby(&self, writer: &mut XmlWriter, module: &str) {
+        writer.write_attribute("type", "RubyRunConfigurationType");
+        writer.write_attribute("factoryName", "Ruby");
+
+        writer.start_element("module");
+        xml_misc::write_attribute(writer, "name", module);
+        writer.end_element();
+
+        let work_dir = self.work_dir();
+        let script_path = if self.command.contains('/') && !Path::new(&self.command).is_absolute() {
+            format!("{}/{}", work_dir, self.command)
+        } else {
+            self.command.clone()
+        };
+        // The project SDK is the interpreter, which runs ruby in the project environment
+        for (name, value) in [
+            ("RUBY_ARGS", String::new()),
+            ("WORK DIR", work_dir),
+            ("SHOULD_USE_SDK", "false".to_string()),
+            ("ALTERN_SDK_NAME", String::new()),
+            ("myPassParentEnvs", "true".to_string()),
+        ] {
+            write_setting(writer, name, &value);
+        }
+        self.write_envs(writer);
+        write_setting(writer, "SCRIPT_PATH", &script_path);
+        write_setting(writer, "SCRIPT_ARGS", &shell_words(&self.args));
+    }
+
+    fn write_shell(&self, writer: &mut XmlWriter, configurator: &[String]) {
+        writer.write_attribute("type", "ShConfigurationType");
+
+        let command: Vec<String> = configurator
+            .iter()
+            .chain([&self.command])
+            .chain(&self.args)
+            .cloned()
+            .collect();
+        for (name, value) in [
+            ("SCRIPT_TEXT", shell_words(&command)),
+            ("INDEPENDENT_SCRIPT_PATH", "true".to_string()),
+            ("SCRIPT_PATH", String::new()),
+            ("SCRIPT_OPTIONS", String::new()),
+            ("INDEPENDENT_SCRIPT_WORKING_DIRECTORY", "true".to_string()),
+            ("SCRIPT_WORKING_DIRECTORY", self.work_dir()),
+            ("INDEPENDENT_INTERPRETER_PATH", "true".to_string()),
+            ("INTERPRETER_PATH", String::new()),
+            ("INTERPRETER_OPTIONS", String::new()),
+            ("EXECUTE_IN_TERMINAL", "true".to_string()),
+            ("EXECUTE_SCRIPT_FILE", "false".to_string()),
+        ] {
+            writer.start_element("option");
+            xml_misc::write_attribute(writer, "name", name);
+            xml_misc::write_attribute(writer, "value", &value);
+            writer.end_element();
+        }
+        self.write_envs(writer);
+    }
+
+    fn write_envs(&self, writer: &mut XmlWriter) {
+        if self.env.is_empty() {
+            return;
+        }
+        writer.start_element("envs");
+        for (name, value) in &self.env {
+            writer.start_element("env");
+            xml_misc::write_attribute(writer, "name", name);
+            xml_misc::write_attribute(writer, "value", value);
+            writer.end_element();
+        }
+        writer.end_element();
+    }
+}
+
+fn write_setting(writer: &mut XmlWriter, name: &str, value: &str) {
+    writer.start_element("RUBY_RUN_CONFIG_SETTINGS_ID");
+    writer.write_attribute("NAME", name);
+    xml_misc::write_attribute(writer, "VALUE", value);
+    writer.end_element();
+}
+
+/// Characters the shell never splits on, globs or expands
+const SHELL_SAFE: &str = "-_./:=@%+,";
+
+/// `word` as it's typed in a shell, single-quoted unless it's all letters, digits
+/// and `SHELL_SAFE` characters
+pub fn shell_quote(word: &str) -> String {
+    quote_unless(word, "")
+}
+
+/// `words` as a command line, single-quoting those the shell would split or glob.
+/// `$` is deliberately left unquoted, so that IDE macros such as `$PROJECT_DIR$` and
+/// variables such as `$HOME` in a script's args still expand.
+fn shell_words(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|word| quote_unless(word, "$"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `word`, single-quoted unless it's all letters, digits, `SHELL_SAFE` and `also_safe` characters
+fn quote_unless(word: &str, also_safe: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || SHELL_SAFE.contains(c) || also_safe.contains(c));
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+/// Fails on two scripts with the same name, which would overwrite each other
+pub fn check_names(scripts: &[Script]) -> Result<()> {
+    let mut names = BTreeSet::new();
+    for script in scripts {
+        if !names.insert(script.name.as_str()) {
+            anyhow::bail!("Two scripts are named {:?}", script.name);
+        }
+    }
+    Ok(())
+}
+
+/// workspace.xml with a run configuration for each of `scripts` in the RunManager,
+/// replacing earlier ones of the same name. `configurator` is the command shell
+/// scripts run through to get the project environment.
+pub fn apply(
+    workspace_xml: &str,
+    scripts: &[Script],
+    module: &str,
+    configurator: &[String],
+) -> Result<String> {
+    let doc = Document::parse(workspace_xml)?;
+    let root = doc.root_element();
+    let mut splice = Splice::new(workspace_xml);
+    let Some(run_manager) = root.children().find(is_run_manager) else {
+        let component = xml_splice::fragment(|writer| {
+            writer.start_element("component");
+            writer.write_attribute("name", "RunManager");
+            for script in scripts {
+                script.write(writer, module, configurator);
+            }
+            writer.end_element();
+            Ok(())
+        })?;
+        splice.append_child(&root, &component);
+        return Ok(splice.finish());
+    };
+
+    for script in scripts {
+        let configuration = xml_splice::fragment(|writer| {
+            script.write(writer, module, configurator);
+            Ok(())
+        })?;
+        let existing = run_manager.children().find(|child| {
+            child.has_tag_name("configuration") && child.attribute("name") == Some(&script.name)
+        });
+        match existing {
+            Some(existing) => splice.replace(&existing, &configuration),
+            None => splice.append_child(&run_manager, &configuration),
+        }
+    }
+    Ok(splice.finish())
+}
+
+fn is_run_manager(node: &Node) -> bool {
+    node.has_tag_name("component") && node.attribute("name") == Some("RunManager")
+}
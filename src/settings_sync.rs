@@ -0,0 +1,35 @@
+use roxmltree::Document;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Local copy of the synced settings, which Backup & Sync pushes back over
+/// `options/` the next time it runs.
+pub fn sync_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join("settingsSync")
+}
+
+/// Whether Backup & Sync (Settings Sync) is turned on for `config_dir`.
+///
+/// The IDE records this as `<option name="syncEnabled" value="true"/>` in
+/// options/settingsSync.xml; the settingsSync directory alone only means it
+/// was enabled at some point.
+pub fn is_enabled(config_dir: &Path) -> bool {
+    let settings_file = config_dir.join("options").join("settingsSync.xml");
+    let Ok(content) = fs::read_to_string(settings_file) else {
+        return false;
+    };
+    let Ok(doc) = Document::parse(&content) else {
+        return false;
+    };
+
+    doc.descendants().any(|node| {
+        node.has_tag_name("option")
+            && node.attribute("name") == Some("syncEnabled")
+            && node.attribute("value") == Some("true")
+    })
+}
+
+/// Where jdk.table.xml lives inside the sync directory
+pub fn jdk_table_file(config_dir: &Path) -> PathBuf {
+    sync_dir(config_dir).join("options").join("jdk.table.xml")
+}
@@ -0,0 +1,121 @@
+use crate::ruby_wrapper::is_executable;
+use anyhow::{Context, Result};
+use dirs::home_dir;
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+/// Directory holding a project's shadowenv programs
+pub const SHADOWENV_DIR: &str = ".shadowenv.d";
+
+/// Where shadowenv is looked for, in order, unless `shadowenv_candidates` is configured.
+/// `PATH` stands for a PATH lookup.
+pub const DEFAULT_CANDIDATES: &[&str] = &[
+    // Homebrew on Apple Silicon, then on Intel
+    "/opt/homebrew/bin/shadowenv",
+    "/usr/local/bin/shadowenv",
+    // Homebrew on Linux, system-wide and per-user
+    "/home/linuxbrew/.linuxbrew/bin/shadowenv",
+    "~/.linuxbrew/bin/shadowenv",
+    "PATH",
+    "~/.dev/userprofile/bin/shadowenv",
+    "~/.local/bin/shadowenv",
+    "/opt/dev/bin/shadowenv",
+];
+
+/// The newest executable shadowenv among `candidates`, the earliest candidate
+/// winning between equal versions. A stale file left by an uninstall or an old
+/// copy earlier in the list doesn't shadow a working, newer one.
+pub fn find(candidates: &[String]) -> Option<String> {
+    let home = home_dir().unwrap_or_default();
+    let mut best: Option<(Option<Version>, String)> = None;
+    for candidate in candidates {
+        let path = if candidate == "PATH" {
+            match crate::RubyMineInterpreter::which("shadowenv") {
+                Ok(Some(path)) => path,
+                _ => continue,
+            }
+        } else {
+            match candidate.strip_prefix("~/") {
+                Some(relative) => home.join(relative).to_string_lossy().to_string(),
+                None => candidate.clone(),
+            }
+        };
+        if !is_executable(Path::new(&path)) {
+            continue;
+        }
+        let version = version(&path);
+        if best
+            .as_ref()
+            .is_none_or(|(best_version, _)| version > *best_version)
+        {
+            best = Some((version, path));
+        }
+    }
+    best.map(|(_, path)| path)
+}
+
+/// Oldest shadowenv whose `exec` takes the `--dir <dir> --` form the configurator uses
+pub const MINIMUM_VERSION: Version = Version(2, 0, 0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(pub u32, pub u32, pub u32);
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// The version `shadowenv --version` reports (`shadowenv 2.1.2`), or `None` when it
+/// can't be run or says something unexpected
+pub fn version(shadowenv: &str) -> Option<Version> {
+    let output = Command::new(shadowenv).arg("--version").output().ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut parts = stdout
+        .split_whitespace()
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))?
+        .split('.')
+        .map(|part| part.parse::<u32>());
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    Some(Version(major, minor, patch))
+}
+
+pub fn has_shadowenv_dir(project_dir: &Path) -> bool {
+    project_dir.join(SHADOWENV_DIR).is_dir()
+}
+
+/// Whether shadowenv will load `project_dir`'s environment. shadowenv has no
+/// query for this, so it runs a no-op in the project and looks for the refusal
+/// (`untrusted shadowenv program`). `None` when shadowenv can't be run at all.
+pub fn is_trusted(shadowenv: &str, project_dir: &Path) -> Option<bool> {
+    let output = Command::new(shadowenv)
+        .args(["exec", "--dir"])
+        .arg(project_dir)
+        .args(["--", "true"])
+        .output()
+        .ok()?;
+    if output.status.success() {
+        return Some(true);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).to_lowercase();
+    stderr.contains("untrusted").then_some(false)
+}
+
+/// `shadowenv trust`, which trusts the directory it runs in
+pub fn trust(shadowenv: &str, project_dir: &Path) -> Result<()> {
+    let output = Command::new(shadowenv)
+        .arg("trust")
+        .current_dir(project_dir)
+        .output()
+        .context("Failed to run shadowenv trust")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "shadowenv trust failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use crate::xml_misc;
+use crate::xml_splice::{self, Splice};
+use anyhow::Result;
+use roxmltree::Node;
+use std::collections::{BTreeMap, HashMap};
+
+/// The env file, in .idea
+pub const FILE_NAME: &str = "rubymine-configurator.env";
+
+/// Run configuration extension of the EnvFile plugin, which loads env files
+const EXTENSION: &str = "net.ashald.envfile";
+
+/// Project environment variables the file carries, besides RUBY
+const VARIABLES: [&str; 3] = ["GEM_HOME", "GEM_PATH", "PATH"];
+
+/// What every process the IDE spawns should agree on: the ruby, where its gems are,
+/// and the PATH that finds both
+pub fn variables(ruby: &str, project_env: &HashMap<String, String>) -> BTreeMap<String, String> {
+    let mut variables: BTreeMap<String, String> = VARIABLES
+        .iter()
+        .filter_map(|name| Some((name.to_string(), project_env.get(*name)?.clone())))
+        .collect();
+    variables.insert("RUBY".to_string(), ruby.to_string());
+    variables
+}
+
+/// The env file, one `KEY=value` per line
+pub fn render(variables: &BTreeMap<String, String>) -> String {
+    let mut content = String::from(
+        "# Generated by rubymine-configurator from the project environment. Rerun it instead of editing.\n",
+    );
+    for (name, value) in variables {
+        content.push_str(&format!("{}={}\n", name, quote(value)));
+    }
+    content
+}
+
+/// Adds a reference to the env file at `path` to `configuration`, keeping any env
+/// files it already loads. Returns whether anything changed.
+pub fn reference(splice: &mut Splice, configuration: &Node, path: &str) -> Result<bool> {
+    let extension = configuration.children().find(|child| {
+        child.has_tag_name("extension") && child.attribute("name") == Some(EXTENSION)
+    });
+    let Some(extension) = extension else {
+        let block = xml_splice::fragment(|writer| {
+            writer.start_element("extension");
+            writer.write_attribute("name", EXTENSION);
+            for (name, value) in [
+                ("IS_ENABLED", "true"),
+                ("IS_SUBST", "false"),
+                ("IS_PATH_MACRO_SUPPORTED", "false"),
+                ("IS_IGNORE_MISSING_FILES", "false"),
+                ("IS_ENABLE_EXPERIMENTAL_INTEGRATIONS", "false"),
+            ] {
+                writer.start_element("option");
+                writer.write_attribute("name", name);
+                writer.write_attribute("value", value);
+                writer.end_element();
+            }
+            writer.start_element("ENTRIES");
+            // The run configuration's own variables, then the file's
+            writer.start_element("ENTRY");
+            writer.write_attribute("IS_ENABLED", "true");
+            writer.write_attribute("PARSER", "runconfig");
+            writer.write_attribute("IS_EXECUTABLE", "false");
+            writer.end_element();
+            writer.start_element("ENTRY");
+            write_entry_attributes(writer, path);
+            writer.end_element();
+            writer.end_element(); // ENTRIES
+            writer.end_element(); // extension
+            Ok(())
+        })?;
+        splice.append_child(configuration, &block);
+        return Ok(true);
+    };
+
+    let referenced = extension
+        .descendants()
+        .any(|node| node.has_tag_name("ENTRY") && node.attribute("PATH") == Some(path));
+    if referenced {
+        return Ok(false);
+    }
+    let entry = xml_splice::fragment(|writer| {
+        writer.start_element("ENTRY");
+        write_entry_attributes(writer, path);
+        writer.end_element();
+        Ok(())
+    })?;
+    let entries = extension
+        .children()
+        .find(|child| child.has_tag_name("ENTRIES"));
+    splice.append_child(entries.as_ref().unwrap_or(&extension), &entry);
+    Ok(true)
+}
+
+fn write_entry_attributes(writer: &mut xmlwriter::XmlWriter, path: &str) {
+    writer.write_attribute("IS_ENABLED", "true");
+    writer.write_attribute("PARSER", "env");
+    writer.write_attribute("IS_EXECUTABLE", "false");
+    xml_misc::write_attribute(writer, "PATH", path);
+}
+
+/// `value` double-quoted when a dotenv parser would otherwise misread it
+fn quote(value: &str) -> String {
+    if value.is_empty() || value.contains(|c: char| c.is_whitespace() || "#\"'\\".contains(c)) {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
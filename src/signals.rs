@@ -0,0 +1,24 @@
+use crate::api::CancellationToken;
+use crate::output::Output;
+use anyhow::{Context, Result};
+use std::process;
+
+/// Exit status of a process killed by SIGINT, which shells expect after Ctrl-C
+const INTERRUPTED_STATUS: i32 = 130;
+
+/// Cancels `token` on SIGINT or SIGTERM (Ctrl-C or closing the console on Windows),
+/// so the run stops between steps instead of in the middle of writing a file. A
+/// second signal exits right away.
+pub fn cancel_on_interrupt(token: &CancellationToken, out: &Output) -> Result<()> {
+    let token = token.clone();
+    let out = out.clone();
+    ctrlc::set_handler(move || {
+        if token.is_cancelled() {
+            process::exit(INTERRUPTED_STATUS);
+        }
+        out.status("");
+        out.status("Interrupted: stopping after the current step (interrupt again to quit now)");
+        token.cancel();
+    })
+    .context("Failed to install the interrupt handler")
+}
@@ -0,0 +1,167 @@
+use crate::jdk_table;
+use regex::Regex;
+use roxmltree::{Document, Node};
+use std::fmt;
+use std::path::Path;
+
+/// Prefixes of remote SDK home paths, which don't exist on this machine
+const REMOTE_PREFIXES: &[&str] = &["docker://", "docker-compose://", "ssh://"];
+
+/// Something the IDE would choke on, or silently drop, when loading jdk.table.xml
+pub struct Problem {
+    /// The SDK it's about, or `None` for the file as a whole
+    pub sdk: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.sdk {
+            Some(sdk) => write!(f, "{}: {}", sdk, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Reads jdk.table.xml the way the IDE does when a project opens: the
+/// ProjectJdkTable component, then each `<jdk version="2">` with its required
+/// children, and for Ruby SDKs the `<additional>` element the Ruby plugin reads.
+/// Home paths are only checked for existence when `check_paths` is set.
+pub fn validate(content: &str, check_paths: bool) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let file_problem = |message: String| Problem { sdk: None, message };
+
+    let doc = match Document::parse(content) {
+        Ok(doc) => doc,
+        Err(err) => return vec![file_problem(format!("not well-formed XML: {}", err))],
+    };
+    let root = doc.root_element();
+    if !root.has_tag_name("application") {
+        problems.push(file_problem(format!(
+            "root element is <{}>, not <application>",
+            root.tag_name().name()
+        )));
+    }
+    let tables: Vec<Node> = root
+        .children()
+        .filter(|child| {
+            child.has_tag_name("component") && child.attribute("name") == Some(jdk_table::COMPONENT)
+        })
+        .collect();
+    match tables.len() {
+        0 => problems.push(file_problem(
+            "no ProjectJdkTable component directly under <application>".to_string(),
+        )),
+        1 => {}
+        _ => problems.push(file_problem(
+            "more than one ProjectJdkTable component; the IDE only reads the first".to_string(),
+        )),
+    }
+
+    // The Ruby plugin parses the version to decide which language level to use
+    let version_re = Regex::new(r"^(?:[a-z]+-)?\d+(?:\.\d+)*").unwrap();
+    let mut seen = Vec::new();
+    for (index, jdk) in tables
+        .first()
+        .into_iter()
+        .flat_map(|table| table.children())
+        .filter(|child| child.has_tag_name("jdk"))
+        .enumerate()
+    {
+        let name = child_value(&jdk, "name");
+        let label = name
+            .clone()
+            .unwrap_or_else(|| format!("SDK #{}", index + 1));
+        let mut problem = |message: String| {
+            problems.push(Problem {
+                sdk: Some(label.clone()),
+                message,
+            })
+        };
+
+        if jdk.attribute("version") != Some("2") {
+            problem("<jdk> needs version=\"2\"".to_string());
+        }
+        match &name {
+            None => problem("missing <name value=...>".to_string()),
+            Some(name) if seen.contains(name) => {
+                problem("duplicate name; the IDE keeps only the first".to_string())
+            }
+            Some(name) => seen.push(name.clone()),
+        }
+        for tag in ["type", "homePath"] {
+            if child_value(&jdk, tag).is_none() {
+                problem(format!("missing <{} value=...>", tag));
+            }
+        }
+        if !has_child(&jdk, "roots") {
+            problem("missing <roots>".to_string());
+        }
+
+        if child_value(&jdk, "type").as_deref() != Some("RUBY_SDK") {
+            continue;
+        }
+        match child_value(&jdk, "version") {
+            None => problem("missing <version value=...>".to_string()),
+            Some(version) if !version_re.is_match(&version) => {
+                problem(format!("version {:?} isn't a Ruby version", version))
+            }
+            Some(_) => {}
+        }
+        let roots = jdk.children().find(|child| child.has_tag_name("roots"));
+        for tag in ["classPath", "sourcePath"] {
+            if roots.is_some_and(|roots| !has_child(&roots, tag)) {
+                problem(format!("<roots> is missing <{}>", tag));
+            }
+        }
+        if let Some(home_path) = child_value(&jdk, "homePath") {
+            let remote = REMOTE_PREFIXES
+                .iter()
+                .any(|prefix| home_path.starts_with(prefix));
+            if check_paths && !remote && !Path::new(&home_path).exists() {
+                problem(format!("home path {} doesn't exist", home_path));
+            }
+        }
+
+        let Some(additional) = jdk
+            .children()
+            .find(|child| child.has_tag_name("additional"))
+        else {
+            problem("missing <additional>, which the Ruby plugin requires".to_string());
+            continue;
+        };
+        if additional.attribute("GEMS_BIN_DIR_PATH").is_none() {
+            problem("<additional> is missing GEMS_BIN_DIR_PATH".to_string());
+        }
+        for node in additional.descendants() {
+            if node.has_tag_name("VERSION_MANAGER") && node.attribute("ID").is_none() {
+                problem("<VERSION_MANAGER> is missing ID".to_string());
+            }
+            if node.has_tag_name("REMOTE_SDK_DATA") && node.attribute("INTERPRETER_PATH").is_none()
+            {
+                problem("<REMOTE_SDK_DATA> is missing INTERPRETER_PATH".to_string());
+            }
+            let in_configurator = node
+                .ancestors()
+                .any(|ancestor| ancestor.has_tag_name("custom-configurator"));
+            if in_configurator && node.has_tag_name("option") && node.attribute("value").is_none() {
+                problem("custom-configurator <option> without a value".to_string());
+            }
+        }
+    }
+
+    problems
+}
+
+/// A direct child's `value` attribute, as the IDE writes it
+fn child_value(node: &Node, tag: &str) -> Option<String> {
+    node.children()
+        .find(|child| child.has_tag_name(tag))?
+        .attribute("value")
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+fn has_child(node: &Node, tag: &str) -> bool {
+    node.children().any(|child| child.has_tag_name(tag))
+}
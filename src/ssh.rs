@@ -0,0 +1,114 @@
+use anyhow::{Context, Result};
+use dirs::home_dir;
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+/// A cloud dev environment (Spin and the like) reached over SSH, with the project
+/// synced into it (e.g. by mutagen)
+#[derive(Debug, Clone)]
+pub struct SshHost {
+    /// The host as given, which may be a `Host` alias from ~/.ssh/config
+    pub alias: String,
+    pub user: String,
+    pub hostname: String,
+    pub port: u16,
+}
+
+impl fmt::Display for SshHost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ssh {}@{}", self.user, self.alias)
+    }
+}
+
+impl SshHost {
+    /// Resolves `[user@]host[:port]` the way ssh itself would, so aliases that dev
+    /// environment tooling writes into ~/.ssh/config turn into the real host and user
+    pub fn resolve(destination: &str) -> Result<Self> {
+        let (user, rest) = match destination.split_once('@') {
+            Some((user, rest)) => (Some(user.to_string()), rest),
+            None => (None, destination),
+        };
+        let (host, port) = match rest.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                Some(
+                    port.parse::<u16>()
+                        .with_context(|| format!("Invalid port in {}", destination))?,
+                ),
+            ),
+            None => (rest, None),
+        };
+
+        let mut command = Command::new("ssh");
+        command.arg("-G");
+        if let Some(user) = &user {
+            command.args(["-l", user]);
+        }
+        if let Some(port) = port {
+            command.args(["-p", &port.to_string()]);
+        }
+        let output = command.arg(host).output().context("Failed to run ssh")?;
+        let config = String::from_utf8_lossy(&output.stdout).to_string();
+        let option = |name: &str| {
+            config.lines().find_map(|line| {
+                let (key, value) = line.split_once(' ')?;
+                (key == name).then(|| value.trim().to_string())
+            })
+        };
+
+        Ok(Self {
+            alias: host.to_string(),
+            user: option("user")
+                .or(user)
+                .or_else(|| std::env::var("USER").ok())
+                .unwrap_or_default(),
+            hostname: option("hostname").unwrap_or(host.to_string()),
+            port: option("port")
+                .and_then(|port| port.parse().ok())
+                .or(port)
+                .unwrap_or(22),
+        })
+    }
+
+    /// Where the synced project is expected on the host: the same path relative to
+    /// the home directory, which is how dev environments lay out checkouts
+    pub fn default_project_dir(project_dir: &Path) -> String {
+        match home_dir().and_then(|home| project_dir.strip_prefix(home).ok().map(Path::to_path_buf))
+        {
+            Some(relative) => format!("~/{}", relative.to_string_lossy()),
+            None => project_dir.to_string_lossy().to_string(),
+        }
+    }
+
+    /// Runs `ruby -e script` in `dir` on the host, through a login shell so the
+    /// environment's ruby setup (chruby, shadowenv hooks, ...) is loaded
+    pub fn ruby_command(&self, dir: &str, script: &str) -> Command {
+        let dir = match dir.strip_prefix("~/") {
+            Some(relative) => format!("~/{}", shell_quote(relative)),
+            None => shell_quote(dir),
+        };
+        let remote = format!(
+            "cd {} && exec \"$SHELL\" -lc {}",
+            dir,
+            shell_quote(&format!("ruby -e {}", shell_quote(script)))
+        );
+        let mut command = Command::new("ssh");
+        command
+            .args([
+                "-o",
+                "BatchMode=yes",
+                "-p",
+                &self.port.to_string(),
+                "-l",
+                &self.user,
+            ])
+            .arg(&self.alias)
+            .arg(remote);
+        command
+    }
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
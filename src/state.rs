@@ -0,0 +1,121 @@
+use crate::fs_backend::FsBackend;
+use anyhow::{Context, Result};
+use dirs::home_dir;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where the tool keeps its own files (shims, state)
+pub fn data_dir() -> Result<PathBuf> {
+    let home = home_dir().context("Could not find home directory")?;
+    Ok(home
+        .join(".local")
+        .join("share")
+        .join("rubymine-configurator"))
+}
+
+/// What the tool remembers between runs, in `<data dir>/state.toml`
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    pub migrations: Vec<Migration>,
+    /// Entries the tool created, which tells them apart from the user's own
+    pub artifacts: Vec<Artifact>,
+    /// Backups moved into the backup store by `migrate-backups`
+    pub backups: Vec<StoredBackup>,
+}
+
+/// A backup in the backup store, which no longer sits next to the file it's a copy of
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StoredBackup {
+    /// The file it's a backup of
+    pub file: PathBuf,
+    pub backup: PathBuf,
+}
+
+/// An entry a run created in one of the IDE's files, so later runs (and `prune
+/// --tag`) can find it again
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Artifact {
+    /// The run's `--tag`, if it had one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// RFC 3339 timestamp
+    pub created_at: String,
+    pub project_dir: String,
+    /// "interpreter", "datasource" or "runconfig"
+    pub kind: String,
+    pub name: String,
+    /// The file holding the artifact (jdk.table.xml, dataSources.xml)
+    pub file: PathBuf,
+}
+
+/// An artifact from an older release that was brought under the current scheme
+#[derive(Serialize, Deserialize)]
+pub struct Migration {
+    /// RFC 3339 timestamp
+    pub at: String,
+    pub project_dir: String,
+    /// "interpreter" or "datasource"
+    pub artifact: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl State {
+    pub fn path() -> Result<PathBuf> {
+        Ok(data_dir()?.join("state.toml"))
+    }
+
+    /// The saved state, or an empty one if nothing has been saved yet
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("Invalid state file {}", path.display()))
+    }
+
+    /// Records `artifact`. An earlier record of the same artifact is kept, tag
+    /// included, unless `artifact` has a tag of its own. Returns whether anything changed.
+    pub fn record(&mut self, artifact: Artifact) -> bool {
+        let earlier = self.artifacts.iter().position(|existing| {
+            existing.kind == artifact.kind
+                && existing.name == artifact.name
+                && existing.file == artifact.file
+        });
+        match earlier {
+            Some(index) if artifact.tag.is_none() || self.artifacts[index].tag == artifact.tag => {
+                return false
+            }
+            Some(index) => {
+                self.artifacts.remove(index);
+            }
+            None => {}
+        }
+        self.artifacts.push(artifact);
+        true
+    }
+
+    /// Forgets the `kind` artifacts recorded in `file` that aren't among `present`
+    /// anymore, such as an interpreter a later run replaced. Returns whether any were.
+    pub fn forget_missing(&mut self, kind: &str, file: &Path, present: &[String]) -> bool {
+        let before = self.artifacts.len();
+        self.artifacts.retain(|artifact| {
+            artifact.kind != kind || artifact.file != file || present.contains(&artifact.name)
+        });
+        self.artifacts.len() != before
+    }
+
+    pub fn save(&self, fs: &FsBackend) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            if !dir.exists() {
+                fs.create_dir_all(dir)?;
+            }
+        }
+        fs.write(&path, &toml::to_string(self)?)
+    }
+}
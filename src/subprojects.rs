@@ -0,0 +1,50 @@
+use crate::xml_misc;
+use roxmltree::Document;
+use std::path::{Path, PathBuf};
+
+/// Start of the comment tying a subproject's data source file to the root's it was
+/// generated from
+const REFERENCE: &str = "data sources shared from";
+
+/// `xml`, one of the root project's data source files, for a subproject's .idea,
+/// with a comment referring to `root_file` it comes from. The IDE can't read another
+/// project's data sources, so the subproject gets the definitions themselves,
+/// UUIDs included, and the comment is what keeps them from being edited apart.
+pub fn referencing(xml: &str, root_file: &Path) -> String {
+    let comment = format!("{} {}", REFERENCE, root_file.display());
+    xml_misc::prepend_comment(xml, &comment)
+}
+
+/// The root project file a subproject's data source file refers to, if it's one
+/// `referencing` wrote
+pub fn root_file(xml: &str) -> Option<PathBuf> {
+    let doc = Document::parse(xml).ok()?;
+    let text = doc
+        .root()
+        .children()
+        .filter(|node| node.is_comment())
+        .filter_map(|node| node.text())
+        .find_map(|text| text.trim().strip_prefix(REFERENCE))?;
+    Some(PathBuf::from(text.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XML: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<project version=\"4\" />\n";
+
+    #[test]
+    fn refers_back_to_the_root_file() {
+        let root = Path::new("/work/umbrella/.idea/dataSources.xml");
+        let xml = referencing(XML, root);
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!-- "));
+        assert_eq!(root_file(&xml).as_deref(), Some(root));
+    }
+
+    #[test]
+    fn files_without_the_comment_refer_to_nothing() {
+        assert_eq!(root_file(XML), None);
+        assert_eq!(root_file("<!-- other --><project />"), None);
+    }
+}
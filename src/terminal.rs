@@ -0,0 +1,116 @@
+use crate::xml_misc;
+use anyhow::Result;
+use roxmltree::{Document, Node};
+use xmlwriter::{Options, XmlWriter};
+
+/// workspace.xml component holding the project's terminal settings
+const COMPONENT: &str = "TerminalProjectOptionsProvider";
+const SHELL_PATH_OPTION: &str = "shellPath";
+
+/// The terminal's shell command: the user's login shell, started through the
+/// configurator so new tabs get the same environment as the interpreter
+pub fn shell_command(configurator: &[String], shell: &str) -> String {
+    configurator
+        .iter()
+        .map(String::as_str)
+        .chain([shell, "-l"])
+        .map(|word| {
+            if word.contains(' ') {
+                format!("\"{}\"", word)
+            } else {
+                word.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The shell command workspace.xml currently sets for the terminal, if any
+pub fn shell_path(workspace_xml: &str) -> Result<Option<String>> {
+    let doc = Document::parse(workspace_xml)?;
+    Ok(doc
+        .descendants()
+        .find(|node| is_component(node))
+        .and_then(|component| {
+            component
+                .children()
+                .find(|child| is_shell_path(child))?
+                .attribute("value")
+                .map(|value| value.to_string())
+        }))
+}
+
+/// workspace.xml with the terminal's shell command set to `shell_command`, adding
+/// the component when the project has none yet
+pub fn set_shell_path(workspace_xml: &str, shell_command: &str) -> Result<String> {
+    let doc = Document::parse(workspace_xml)?;
+    let mut writer = XmlWriter::new(Options::default());
+    writer.write_declaration();
+    xml_misc::write_document(&mut writer, &doc, |writer, root| {
+        write_element(writer, root, shell_command);
+        Ok(())
+    })?;
+    Ok(xml_misc::end_document(writer))
+}
+
+/// A workspace.xml with nothing but the terminal settings, for projects the IDE hasn't opened
+pub fn new_workspace(shell_command: &str) -> String {
+    let mut writer = XmlWriter::new(Options::default());
+    writer.write_declaration();
+    writer.start_element("project");
+    writer.write_attribute("version", "4");
+    write_component(&mut writer, None, shell_command);
+    writer.end_element();
+    writer.end_document()
+}
+
+fn is_component(node: &Node) -> bool {
+    node.has_tag_name("component") && node.attribute("name") == Some(COMPONENT)
+}
+
+fn is_shell_path(node: &Node) -> bool {
+    node.has_tag_name("option") && node.attribute("name") == Some(SHELL_PATH_OPTION)
+}
+
+fn write_element(writer: &mut XmlWriter, node: &Node, shell_command: &str) {
+    writer.start_element(node.tag_name().name());
+    xml_misc::copy_attributes(writer, node);
+    let mut has_component = false;
+    for child in node.children() {
+        if child.is_element() && is_component(&child) {
+            write_component(writer, Some(&child), shell_command);
+            has_component = true;
+        } else if child.is_element() {
+            write_element(writer, &child, shell_command);
+        } else {
+            xml_misc::write(writer, &child);
+        }
+    }
+    let is_root_element = node.parent().is_some_and(|parent| parent.is_root());
+    if is_root_element && !has_component {
+        write_component(writer, None, shell_command);
+    }
+    writer.end_element();
+}
+
+/// The component with its other options kept and shellPath replaced
+fn write_component(writer: &mut XmlWriter, existing: Option<&Node>, shell_command: &str) {
+    writer.start_element("component");
+    writer.write_attribute("name", COMPONENT);
+    for child in existing
+        .into_iter()
+        .flat_map(|component| component.children())
+        .filter(|child| !is_shell_path(child))
+    {
+        if child.is_element() {
+            write_element(writer, &child, shell_command);
+        } else {
+            xml_misc::write(writer, &child);
+        }
+    }
+    writer.start_element("option");
+    writer.write_attribute("name", SHELL_PATH_OPTION);
+    xml_misc::write_attribute(writer, "value", shell_command);
+    writer.end_element();
+    writer.end_element();
+}
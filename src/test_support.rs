@@ -0,0 +1,27 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Once;
+
+/// HOME for the tests, under the system temp dir, so that the audit log and the
+/// state file they write to stay out of the real one. Set on first use.
+pub fn home() -> PathBuf {
+    static HOME: Once = Once::new();
+    let home = base().join("home");
+    HOME.call_once(|| env::set_var("HOME", &home));
+    home
+}
+
+/// An empty directory of its own for the test `name`, under the system temp dir
+pub fn scratch_dir(name: &str) -> PathBuf {
+    home();
+    let dir = base().join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn base() -> PathBuf {
+    env::temp_dir().join(format!("rubymine-configurator-{}", process::id()))
+}
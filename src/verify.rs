@@ -0,0 +1,79 @@
+use crate::RubyMineInterpreter;
+use anyhow::{Context, Result};
+use std::process::Command;
+
+/// What RubyMine runs first when it loads an interpreter, as arguments to homePath
+const CHECKS: &[(&str, &[&str])] = &[("ruby -v", &["-v"]), ("gem env", &["-S", "gem", "env"])];
+
+/// Runs the interpreter the way RubyMine will, through the configurator options
+/// recorded in jdk.table.xml, so a broken entry shows up before the IDE restarts
+pub fn run(interpreter: &RubyMineInterpreter) -> Result<()> {
+    if interpreter.docker.is_some() {
+        anyhow::bail!("verify only runs local interpreters");
+    }
+    let config_file = interpreter.interpreter_config_file()?;
+    let entry = interpreter.managed_interpreter()?.with_context(|| {
+        format!(
+            "No interpreter for this project in {}; run without a command to create it",
+            config_file.display()
+        )
+    })?;
+    let home_path = entry
+        .home_path
+        .as_deref()
+        .with_context(|| format!("{} has no homePath", entry.name))?;
+
+    interpreter
+        .out
+        .line(format!("{} ({}):", entry.name, config_file.display()));
+    let mut failures = 0;
+    for (label, args) in CHECKS {
+        let mut command = entry.configurator.clone();
+        command.push(home_path.to_string());
+        command.extend(args.iter().map(|arg| arg.to_string()));
+        interpreter.out.line(format!("  $ {}", command.join(" ")));
+
+        let output = Command::new(&command[0]).args(&command[1..]).output();
+        let (ok, detail) = match output {
+            Ok(output) => {
+                let stream = if output.status.success() {
+                    &output.stdout
+                } else {
+                    &output.stderr
+                };
+                let first_line = String::from_utf8_lossy(stream)
+                    .lines()
+                    .find(|line| !line.trim().is_empty())
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string();
+                (output.status.success(), first_line)
+            }
+            Err(err) => (false, format!("couldn't run {}: {}", command[0], err)),
+        };
+        if ok {
+            interpreter
+                .out
+                .line(format!("  [ok] {}: {}", label, detail));
+        } else {
+            failures += 1;
+            interpreter
+                .out
+                .line(format!("  [problem] {}: {}", label, detail));
+        }
+    }
+
+    interpreter.out.line("");
+    if failures > 0 {
+        anyhow::bail!(
+            "{} of {} checks failed; RubyMine won't be able to use {}",
+            failures,
+            CHECKS.len(),
+            entry.name
+        );
+    }
+    interpreter
+        .out
+        .line(format!("RubyMine should be able to use {}", entry.name));
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use dirs::home_dir;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The Ruby version manager an interpreter belongs to, as far as RubyMine's
+/// `VERSION_MANAGER` setting is concerned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionManager {
+    System,
+    Rvm {
+        /// e.g. "ruby-3.3.0"
+        ruby: String,
+        gemset: Option<String>,
+    },
+    Chruby,
+}
+
+impl VersionManager {
+    /// Works out the manager from where the interpreter lives. The rvm gemset comes
+    /// from GEM_HOME (`.../gems/ruby-3.3.0@name`) or the project's .ruby-gemset.
+    pub fn detect(
+        ruby_path: &Path,
+        project_env: &HashMap<String, String>,
+        project_dir: &Path,
+    ) -> Self {
+        if let Some(ruby) = rvm_ruby(ruby_path) {
+            let gemset = project_env
+                .get("GEM_HOME")
+                .and_then(|gem_home| {
+                    Path::new(gem_home)
+                        .file_name()?
+                        .to_str()?
+                        .split_once('@')
+                        .map(|(_, gemset)| gemset.to_string())
+                })
+                .or_else(|| {
+                    let gemset = fs::read_to_string(project_dir.join(".ruby-gemset")).ok()?;
+                    Some(gemset.trim().to_string()).filter(|gemset| !gemset.is_empty())
+                });
+            return VersionManager::Rvm { ruby, gemset };
+        }
+
+        if rubies_dirs().iter().any(|dir| ruby_path.starts_with(dir)) {
+            return VersionManager::Chruby;
+        }
+
+        VersionManager::System
+    }
+
+    /// Value of `<VERSION_MANAGER ID="...">`
+    pub fn id(&self) -> &'static str {
+        match self {
+            VersionManager::System => "system",
+            VersionManager::Rvm { .. } => "rvm",
+            VersionManager::Chruby => "chruby",
+        }
+    }
+
+    pub fn gemset(&self) -> Option<&str> {
+        match self {
+            VersionManager::Rvm { gemset, .. } => gemset.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// bin directory of the rvm gemset, e.g. ~/.rvm/gems/ruby-3.3.0@name/bin
+    pub fn gemset_bin_dir(&self) -> Option<PathBuf> {
+        let VersionManager::Rvm {
+            ruby,
+            gemset: Some(gemset),
+        } = self
+        else {
+            return None;
+        };
+        rvm_roots()
+            .into_iter()
+            .map(|root| {
+                root.join("gems")
+                    .join(format!("{}@{}", ruby, gemset))
+                    .join("bin")
+            })
+            .find(|dir| dir.is_dir())
+    }
+}
+
+/// The ruby executable of an installed `version` (`3.3.1`, `jruby-9.4.5.0`), looking
+/// through every version manager's install directory
+pub fn find_installed(version: &str) -> Option<PathBuf> {
+    // rvm and chruby name installs ruby-3.3.1; asdf, mise and rbenv just 3.3.1
+    let names = if version.contains('-') {
+        vec![version.to_string()]
+    } else {
+        vec![format!("ruby-{}", version), version.to_string()]
+    };
+
+    let mut install_dirs: Vec<PathBuf> = rvm_roots()
+        .into_iter()
+        .map(|root| root.join("rubies"))
+        .chain(rubies_dirs())
+        .collect();
+    install_dirs.extend(crate::asdf::rubies_dir());
+    install_dirs.extend(crate::mise::rubies_dir());
+    install_dirs.extend(rbenv_versions_dir());
+
+    install_dirs
+        .iter()
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name).join("bin")))
+        .flat_map(|bin| ["ruby", "jruby", "truffleruby"].map(|program| bin.join(program)))
+        .find(|ruby| ruby.is_file())
+}
+
+fn rbenv_versions_dir() -> Option<PathBuf> {
+    let root = env::var_os("RBENV_ROOT")
+        .filter(|root| !root.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".rbenv")))?;
+    Some(root.join("versions"))
+}
+
+/// rvm installs, per-user first
+fn rvm_roots() -> Vec<PathBuf> {
+    let mut roots = Vec::from_iter(home_dir().map(|home| home.join(".rvm")));
+    roots.push(PathBuf::from("/usr/local/rvm"));
+    roots
+}
+
+/// Directories chruby looks for rubies in
+fn rubies_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::from_iter(home_dir().map(|home| home.join(".rubies")));
+    dirs.push(PathBuf::from("/opt/rubies"));
+    dirs
+}
+
+/// The rvm ruby name (`ruby-3.3.0`) when `ruby_path` is inside an rvm rubies dir
+fn rvm_ruby(ruby_path: &Path) -> Option<String> {
+    rvm_roots().into_iter().find_map(|root| {
+        let rest = ruby_path.strip_prefix(root.join("rubies")).ok()?;
+        match rest.components().next()? {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        }
+    })
+}
@@ -0,0 +1,36 @@
+use crate::output::Output;
+use std::fmt;
+
+/// Exit status of a run that only failed because of warnings under --strict
+pub const STRICT_STATUS: i32 = 3;
+
+/// Writes a warning to `out` and counts it there, so --strict can fail the run on it
+pub fn warn(out: &Output, message: impl fmt::Display) {
+    out.status(format!("Warning: {}", message));
+    out.count_warning();
+}
+
+/// The error --strict turns printed warnings into
+#[derive(Debug)]
+pub struct Strict(pub usize);
+
+impl fmt::Display for Strict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} warning(s) with --strict (rerun without it to accept them)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for Strict {}
+
+/// Fails once a warning was written to `out`, when `strict`
+pub fn check(out: &Output, strict: bool) -> Result<(), Strict> {
+    match out.warnings() {
+        0 => Ok(()),
+        _ if !strict => Ok(()),
+        count => Err(Strict(count)),
+    }
+}
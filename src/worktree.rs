@@ -0,0 +1,125 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// Recognizes one checkout layout, returning the name that tells this checkout
+/// apart from other checkouts of the same project
+type Classifier = fn(&Path) -> Option<String>;
+
+/// Known layouts, most specific first
+const CLASSIFIERS: &[Classifier] = &[trees, git_worktree, bare_repo, sibling_checkout, host_org];
+
+/// Qualifier that goes in front of the project name in interpreter names, if the
+/// project dir follows a recognized layout
+pub fn qualifier(project_dir: &Path) -> Option<String> {
+    CLASSIFIERS
+        .iter()
+        .find_map(|classify| classify(project_dir))
+        .filter(|name| !name.is_empty())
+}
+
+fn components(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `.../trees/{worktree}/...`
+fn trees(dir: &Path) -> Option<String> {
+    let components = components(dir);
+    let position = components.iter().position(|name| name == "trees")?;
+    components.get(position + 1).cloned()
+}
+
+/// A linked `git worktree`, whose `.git` file points at `<repo>/.git/worktrees/{name}`
+fn git_worktree(dir: &Path) -> Option<String> {
+    let content = fs::read_to_string(dir.join(".git")).ok()?;
+    let gitdir = content
+        .lines()
+        .find_map(|line| line.strip_prefix("gitdir:"))?;
+    let gitdir = Path::new(gitdir.trim());
+    let parent = gitdir.parent()?;
+    if parent.file_name()? != "worktrees" {
+        return None;
+    }
+    Some(gitdir.file_name()?.to_string_lossy().to_string())
+}
+
+/// Worktrees kept inside a bare clone: `repo.git/{name}` or `repo.git/worktrees/{name}`
+fn bare_repo(dir: &Path) -> Option<String> {
+    let components = components(dir);
+    let position = components
+        .iter()
+        .position(|name| name.ends_with(".git") && name != ".git")?;
+    let mut rest = components[position + 1..].iter();
+    match rest.next()? {
+        worktrees if worktrees == "worktrees" => rest.next().cloned(),
+        name => Some(name.clone()),
+    }
+}
+
+/// `../repo-{branch}` next to a `../repo` checkout
+fn sibling_checkout(dir: &Path) -> Option<String> {
+    let name = dir.file_name()?.to_str()?;
+    let parent = dir.parent()?;
+    // Longest matching prefix wins, so `my-app-fix` next to `my-app` yields "fix"
+    name.match_indices('-')
+        .rev()
+        .find(|(index, _)| parent.join(&name[..*index]).join(".git").exists())
+        .map(|(index, _)| name[index + 1..].to_string())
+}
+
+/// Code hosts recognized anywhere in a path
+const KNOWN_HOSTS: &[&str] = &["github.com", "gitlab.com", "bitbucket.org"];
+
+/// `.../{host}/{org}/{repo}`, as used by ghq and GOPATH-style trees (`~/src/github.com/org/repo`)
+fn host_org(dir: &Path) -> Option<String> {
+    let components = components(dir);
+    let count = components.len();
+    if count < 3 {
+        return None;
+    }
+    let host = &components[count - 3];
+    // Other hosts only count under a src/ or ghq/ root, so `/Users/first.last/work/app`
+    // isn't mistaken for one
+    let under_root = count >= 4 && ["src", "ghq"].contains(&components[count - 4].as_str());
+    let is_host = KNOWN_HOSTS.contains(&host.as_str()) || (under_root && host.contains('.'));
+    is_host.then(|| components[count - 2].clone())
+}
+
+/// Every checkout of the repository `dir` belongs to (`git worktree list`), main
+/// worktree first. Bare repositories and worktrees whose directory is gone are left out.
+pub fn list(dir: &Path) -> Result<Vec<PathBuf>> {
+    let output = Command::new("git")
+        .args(["worktree", "list", "--porcelain"])
+        .current_dir(dir)
+        .output()
+        .context("Failed to run git worktree list")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git worktree list failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    Ok(stdout
+        .split("\n\n")
+        .filter(|entry| {
+            !entry
+                .lines()
+                .any(|line| line == "bare" || line.starts_with("prunable"))
+        })
+        .filter_map(|entry| {
+            entry
+                .lines()
+                .find_map(|line| line.strip_prefix("worktree "))
+        })
+        .map(PathBuf::from)
+        .filter(|path| path.is_dir())
+        .collect())
+}
@@ -0,0 +1,158 @@
+use anyhow::Result;
+use roxmltree::{Attribute, Document, Node, NodeType};
+use xmlwriter::XmlWriter;
+
+/// Brackets a processing instruction written as a placeholder comment. xmlwriter
+/// can't write instructions, and this can't appear in a real comment.
+const INSTRUCTION_MARK: char = '\u{1}';
+/// Brackets source text written as a placeholder comment, for what xmlwriter can't
+/// write as it was: CDATA sections and entities in text
+const VERBATIM_MARK: char = '\u{2}';
+
+/// Writes `node` when it's a comment, a processing instruction or text, which
+/// rewrites would otherwise drop along with whatever users or other tools noted in
+/// them. Text is copied from the source, CDATA sections and entities included, and
+/// whitespace-only text is left to the writer's indentation. Returns whether it was
+/// one of those. The output must go through `end_document`.
+pub fn write(writer: &mut XmlWriter, node: &Node) -> bool {
+    match node.node_type() {
+        NodeType::Text => {
+            let Some(source) = text_source(node).map(str::trim) else {
+                return false;
+            };
+            if !source.is_empty() {
+                writer.write_comment_fmt(format_args!(
+                    "{mark}{}{mark}",
+                    source,
+                    mark = VERBATIM_MARK
+                ));
+            }
+            true
+        }
+        NodeType::Comment => {
+            writer.write_comment(node.text().unwrap_or_default());
+            true
+        }
+        NodeType::PI => {
+            let Some(pi) = node.pi() else {
+                return false;
+            };
+            let value = pi
+                .value
+                .map(|value| format!(" {}", value))
+                .unwrap_or_default();
+            writer.write_comment_fmt(format_args!(
+                "{mark}{}{}{mark}",
+                pi.target,
+                value,
+                mark = INSTRUCTION_MARK
+            ));
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Writes `node`'s attributes as they are in the source, so that entities such as
+/// `&#10;` in a value stay as they were
+pub fn copy_attributes(writer: &mut XmlWriter, node: &Node) {
+    for attr in node.attributes() {
+        copy_attribute(writer, node, &attr);
+    }
+}
+
+/// Writes `attr`, one of `node`'s attributes, as it is in the source
+pub fn copy_attribute(writer: &mut XmlWriter, node: &Node, attr: &Attribute) {
+    let value = &node.document().input_text()[attr.range_value()];
+    writer.write_attribute_raw(attr.name(), |buffer| {
+        buffer.extend_from_slice(value.as_bytes())
+    });
+}
+
+/// Writes an attribute with `value` escaped the way xmlwriter leaves to its callers:
+/// ampersands, angle brackets and line breaks, which would otherwise come out as
+/// broken XML or be read back as spaces
+pub fn write_attribute(writer: &mut XmlWriter, name: &str, value: &str) {
+    // Quotes are escaped by xmlwriter itself
+    writer.write_attribute_raw(name, |buffer| {
+        buffer.extend_from_slice(escape_markup(value).as_bytes())
+    });
+}
+
+/// `xml` with `comment` right after the XML declaration, or first when it has none
+pub fn prepend_comment(xml: &str, comment: &str) -> String {
+    let comment = format!("<!-- {} -->", comment);
+    match xml.split_once('\n') {
+        Some((declaration, rest)) if declaration.starts_with("<?xml") => {
+            format!("{}\n{}\n{}", declaration, comment, rest)
+        }
+        _ => format!("{}\n{}", comment, xml),
+    }
+}
+
+/// `value` escaped for a double-quoted attribute
+pub fn escape_attribute(value: &str) -> String {
+    escape_markup(value).replace('"', "&quot;")
+}
+
+fn escape_markup(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('\n', "&#10;")
+        .replace('\r', "&#13;")
+        .replace('\t', "&#9;")
+}
+
+/// The source of the text node `node`. roxmltree merges text, CDATA sections and
+/// entities into one node whose range only covers the first of them, so this is
+/// everything between the siblings (or the parent's tags) around it.
+fn text_source<'a>(node: &Node<'a, '_>) -> Option<&'a str> {
+    let input = node.document().input_text();
+    let start = match node.prev_sibling() {
+        Some(previous) => previous.range().end,
+        None => {
+            let parent = node.parent().filter(Node::is_element)?;
+            let after_attributes = parent
+                .attributes()
+                .next_back()
+                .map_or(parent.range().start, |attr| attr.range().end);
+            after_attributes + input[after_attributes..].find('>')? + 1
+        }
+    };
+    let end = match node.next_sibling() {
+        Some(next) => next.range().start,
+        None => {
+            let parent = node.parent()?.range();
+            parent.start + input[parent].rfind("</")?
+        }
+    };
+    input.get(start..end)
+}
+
+/// Writes what's around the root element with `write`, and the root element itself
+/// with `write_root`
+pub fn write_document<F>(writer: &mut XmlWriter, doc: &Document, mut write_root: F) -> Result<()>
+where
+    F: FnMut(&mut XmlWriter, &Node) -> Result<()>,
+{
+    for node in doc.root().children() {
+        if node.is_element() {
+            write_root(writer, &node)?;
+        } else {
+            write(writer, &node);
+        }
+    }
+    Ok(())
+}
+
+/// `writer`'s document, with processing instructions and source text in place of
+/// their placeholders
+pub fn end_document(writer: XmlWriter) -> String {
+    writer
+        .end_document()
+        .replace(&format!("<!--{}", INSTRUCTION_MARK), "<?")
+        .replace(&format!("{}-->", INSTRUCTION_MARK), "?>")
+        .replace(&format!("<!--{}", VERBATIM_MARK), "")
+        .replace(&format!("{}-->", VERBATIM_MARK), "")
+}
@@ -0,0 +1,373 @@
+use crate::xml_misc;
+use anyhow::Result;
+use roxmltree::Node;
+use std::ops::Range;
+use xmlwriter::{Options, XmlWriter};
+
+/// Indentation xmlwriter uses for each level of a fragment
+const WRITER_INDENT: usize = 4;
+/// Indentation assumed when a file gives no example of its own (the IDE's)
+const DEFAULT_INDENT: &str = "  ";
+
+/// Edits to an XML file made by splicing its source text, so that everything the
+/// tool doesn't change stays byte-identical: formatting, attribute quoting, entities
+/// and comments. Nodes come from a roxmltree document parsed from the same source.
+pub struct Splice<'a> {
+    source: &'a str,
+    edits: Vec<(Range<usize>, String)>,
+}
+
+impl<'a> Splice<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            edits: Vec::new(),
+        }
+    }
+
+    /// Removes `node`, along with its line when nothing else is on it
+    pub fn remove(&mut self, node: &Node) {
+        let range = node.range();
+        let line_start = self.line_start(range.start);
+        let start = if self.source[line_start..range.start].trim().is_empty() {
+            self.source[..line_start]
+                .strip_suffix("\r\n")
+                .or_else(|| self.source[..line_start].strip_suffix('\n'))
+                .map_or(line_start, str::len)
+        } else {
+            range.start
+        };
+        self.edits.push((start..range.end, String::new()));
+    }
+
+    /// Replaces `node` with `fragment`, a single element as xmlwriter writes it
+    pub fn replace(&mut self, node: &Node, fragment: &str) {
+        let indent = self.indent_of(node.range().start);
+        let unit = self.indent_unit(node);
+        let text = self.reindent(fragment, &indent, &unit);
+        self.edits
+            .push((node.range(), text.trim_start().to_string()));
+    }
+
+    /// Adds `fragment` right before `node`, on its own line when `node` starts one
+    pub fn insert_before(&mut self, node: &Node, fragment: &str) {
+        let start = node.range().start;
+        let indent = self.indent_of(start);
+        let unit = self.indent_unit(node);
+        let text = self.reindent(fragment, &indent, &unit);
+        if indent.is_empty() {
+            self.edits.push((start..start, text));
+        } else {
+            let line_start = self.line_start(start);
+            self.edits.push((
+                line_start..line_start,
+                format!("{}{}", text, self.newline()),
+            ));
+        }
+    }
+
+    /// Adds `fragment` as the last child of `parent`, indented like its siblings
+    pub fn append_child(&mut self, parent: &Node, fragment: &str) {
+        let range = parent.range();
+        let element = &self.source[range.clone()];
+        let parent_indent = self.indent_of(range.start);
+        let unit = self.indent_unit(parent);
+        let child_indent = self
+            .first_child_element(parent)
+            .filter(|child| self.starts_line(child.range().start))
+            .map(|child| self.indent_of(child.range().start))
+            .unwrap_or_else(|| format!("{}{}", parent_indent, unit));
+        let newline = self.newline();
+        let text = self.reindent(fragment, &child_indent, &unit);
+
+        if let Some(open_tag) = element.strip_suffix("/>") {
+            // <component name="..."/> becomes <component name="...">...</component>
+            let name = open_tag[1..]
+                .split(|c: char| c.is_whitespace())
+                .next()
+                .unwrap_or_default();
+            let end = range.start + open_tag.trim_end().len();
+            self.edits.push((
+                end..range.end,
+                format!(
+                    ">{}{}{}{}</{}>",
+                    newline, text, newline, parent_indent, name
+                ),
+            ));
+            return;
+        }
+
+        // Inserted rather than replacing the whitespace before the closing tag, so
+        // that several children can be appended to the same parent
+        let close = range.start + element.rfind("</").unwrap_or(element.len());
+        let content_end = range.start + self.source[range.start..close].trim_end().len();
+        self.edits
+            .push((content_end..content_end, format!("{}{}", newline, text)));
+        if !self.source[content_end..close].contains('\n') {
+            // Moved after the children appended so far, and only there once
+            let closing_line = (content_end..close, format!("{}{}", newline, parent_indent));
+            self.edits.retain(|edit| *edit != closing_line);
+            self.edits.push(closing_line);
+        }
+    }
+
+    /// Sets `name` on `node` to `value`, adding the attribute after the others when missing
+    pub fn set_attribute(&mut self, node: &Node, name: &str, value: &str) {
+        let escaped = xml_misc::escape_attribute(value);
+        if let Some(attribute) = node.attributes().find(|attribute| attribute.name() == name) {
+            let range = attribute.range_value();
+            let escaped = if self.source[..range.start].ends_with('\'') {
+                escaped.replace('\'', "&apos;")
+            } else {
+                escaped
+            };
+            self.edits.push((range, escaped));
+            return;
+        }
+        let end = node
+            .attributes()
+            .next_back()
+            .map(|attribute| attribute.range().end)
+            .unwrap_or(node.range().start + 1 + node.tag_name().name().len());
+        self.edits
+            .push((end..end, format!(" {}=\"{}\"", name, escaped)));
+    }
+
+    /// The source with every edit applied
+    pub fn finish(mut self) -> String {
+        // Insertions at a position go before an edit starting there
+        self.edits
+            .sort_by_key(|(range, _)| (range.start, range.end));
+        let mut output = String::with_capacity(self.source.len());
+        let mut position = 0;
+        for (range, text) in &self.edits {
+            // Edits inside a removed or replaced node are moot
+            if range.start < position {
+                continue;
+            }
+            output.push_str(&self.source[position..range.start]);
+            output.push_str(text);
+            position = range.end;
+        }
+        output.push_str(&self.source[position..]);
+        output
+    }
+
+    fn line_start(&self, position: usize) -> usize {
+        self.source[..position]
+            .rfind('\n')
+            .map_or(0, |index| index + 1)
+    }
+
+    fn starts_line(&self, position: usize) -> bool {
+        self.source[self.line_start(position)..position]
+            .trim()
+            .is_empty()
+    }
+
+    /// The whitespace before the node starting at `position`, when it starts its line
+    fn indent_of(&self, position: usize) -> String {
+        if self.starts_line(position) {
+            self.source[self.line_start(position)..position].to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    /// One level of indentation in this file, judging from `node` and its children
+    fn indent_unit(&self, node: &Node) -> String {
+        let element = node
+            .ancestors()
+            .filter(|ancestor| ancestor.is_element())
+            .find_map(|ancestor| {
+                let child = self.first_child_element(&ancestor)?;
+                let outer = self.indent_of(ancestor.range().start);
+                let inner = self.indent_of(child.range().start);
+                inner
+                    .strip_prefix(&outer)
+                    .filter(|unit| !unit.is_empty())
+                    .map(str::to_string)
+            });
+        element.unwrap_or_else(|| DEFAULT_INDENT.to_string())
+    }
+
+    fn first_child_element<'b>(&self, node: &Node<'b, 'b>) -> Option<Node<'b, 'b>> {
+        node.children().find(|child| child.is_element())
+    }
+
+    fn newline(&self) -> &'static str {
+        if self.source.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+
+    /// `fragment` with xmlwriter's indentation turned into this file's, every line
+    /// starting at `indent`
+    fn reindent(&self, fragment: &str, indent: &str, unit: &str) -> String {
+        fragment
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let content = line.trim_start_matches(' ');
+                let depth = (line.len() - content.len()) / WRITER_INDENT;
+                format!("{}{}{}", indent, unit.repeat(depth), content)
+            })
+            .collect::<Vec<_>>()
+            .join(self.newline())
+    }
+}
+
+/// A fragment written with `write` on its own, for `replace` and `append_child`
+pub fn fragment(write: impl FnOnce(&mut XmlWriter) -> Result<()>) -> Result<String> {
+    let mut writer = XmlWriter::new(Options::default());
+    write(&mut writer)?;
+    Ok(writer.end_document())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use roxmltree::Document;
+
+    /// `source` with `edit` applied to its element called `name`
+    fn spliced(source: &str, name: &str, edit: impl FnOnce(&mut Splice, &Node)) -> String {
+        let doc = Document::parse(source).unwrap();
+        let node = doc
+            .descendants()
+            .find(|node| node.has_tag_name(name))
+            .unwrap();
+        let mut splice = Splice::new(source);
+        edit(&mut splice, &node);
+        splice.finish()
+    }
+
+    #[test]
+    fn remove_takes_the_line_of_a_node_alone_on_it() {
+        let source = "<a>\n  <b/>\n  <c/>\n</a>";
+        let output = spliced(source, "b", |splice, node| splice.remove(node));
+        assert_eq!(output, "<a>\n  <c/>\n</a>");
+    }
+
+    #[test]
+    fn remove_leaves_what_shares_the_line() {
+        let source = "<a><b x=\"1\"/><c/></a>";
+        let output = spliced(source, "b", |splice, node| splice.remove(node));
+        assert_eq!(output, "<a><c/></a>");
+    }
+
+    #[test]
+    fn remove_keeps_crlf_line_endings() {
+        let source = "<a>\r\n  <b/>\r\n  <c/>\r\n</a>\r\n";
+        let output = spliced(source, "b", |splice, node| splice.remove(node));
+        assert_eq!(output, "<a>\r\n  <c/>\r\n</a>\r\n");
+    }
+
+    #[test]
+    fn append_child_opens_a_self_closing_parent() {
+        let source = "<a>\n  <b name=\"x\" />\n</a>";
+        let output = spliced(source, "b", |splice, node| {
+            splice.append_child(node, "<c/>")
+        });
+        assert_eq!(output, "<a>\n  <b name=\"x\">\n    <c/>\n  </b>\n</a>");
+    }
+
+    #[test]
+    fn append_child_adds_several_children_in_order() {
+        let source = "<a>\n\t<b/>\n</a>";
+        let output = spliced(source, "a", |splice, node| {
+            splice.append_child(node, "<c>\n    <d/>\n</c>");
+            splice.append_child(node, "<e/>");
+        });
+        assert_eq!(output, "<a>\n\t<b/>\n\t<c>\n\t\t<d/>\n\t</c>\n\t<e/>\n</a>");
+    }
+
+    #[test]
+    fn append_child_moves_a_closing_tag_sharing_a_line_onto_its_own() {
+        let source = "<a>\n  <b><c/></b>\n</a>";
+        let output = spliced(source, "b", |splice, node| {
+            splice.append_child(node, "<d/>");
+            splice.append_child(node, "<e/>");
+        });
+        assert_eq!(output, "<a>\n  <b><c/>\n    <d/>\n    <e/>\n  </b>\n</a>");
+    }
+
+    #[test]
+    fn insert_before_goes_on_its_own_line_at_the_same_indent() {
+        let source = "<a>\n    <b/>\n</a>";
+        let output = spliced(source, "b", |splice, node| {
+            splice.insert_before(node, "<c/>")
+        });
+        assert_eq!(output, "<a>\n    <c/>\n    <b/>\n</a>");
+    }
+
+    #[test]
+    fn insert_before_stays_inline_next_to_an_inline_node() {
+        let source = "<a><b/></a>";
+        let output = spliced(source, "b", |splice, node| {
+            splice.insert_before(node, "<c/>")
+        });
+        assert_eq!(output, "<a><c/><b/></a>");
+    }
+
+    #[test]
+    fn set_attribute_replaces_only_the_value() {
+        let source = "<a>\n  <b  x = 'old'  y=\"1\"/>\n</a>";
+        let output = spliced(source, "b", |splice, node| {
+            splice.set_attribute(node, "x", "new")
+        });
+        assert_eq!(output, "<a>\n  <b  x = 'new'  y=\"1\"/>\n</a>");
+    }
+
+    #[test]
+    fn set_attribute_escapes_for_the_value_quotes() {
+        let source = "<a><b x='old' y=\"old\"/></a>";
+        let output = spliced(source, "b", |splice, node| {
+            splice.set_attribute(node, "x", "it's R&D");
+            splice.set_attribute(node, "y", "say \"<hi>\"");
+        });
+        assert_eq!(
+            output,
+            "<a><b x='it&apos;s R&amp;D' y=\"say &quot;&lt;hi>&quot;\"/></a>"
+        );
+        assert!(Document::parse(&output).is_ok());
+    }
+
+    #[test]
+    fn set_attribute_adds_a_missing_one_after_the_others() {
+        let source = "<a><b x=\"1\"/><c/></a>";
+        let output = spliced(source, "b", |splice, node| {
+            splice.set_attribute(node, "y", "a&b")
+        });
+        assert_eq!(output, "<a><b x=\"1\" y=\"a&amp;b\"/><c/></a>");
+
+        let output = spliced(source, "c", |splice, node| {
+            splice.set_attribute(node, "y", "2")
+        });
+        assert_eq!(output, "<a><b x=\"1\"/><c y=\"2\"/></a>");
+    }
+
+    #[test]
+    fn finish_drops_edits_inside_a_removed_node() {
+        let source = "<a>\n  <b>\n    <c x=\"1\"/>\n  </b>\n  <d/>\n</a>";
+        let doc = Document::parse(source).unwrap();
+        let find = |name| {
+            doc.descendants()
+                .find(|node| node.has_tag_name(name))
+                .unwrap()
+        };
+        let mut splice = Splice::new(source);
+        splice.set_attribute(&find("c"), "x", "2");
+        splice.remove(&find("b"));
+        splice.append_child(&find("c"), "<e/>");
+        splice.set_attribute(&find("d"), "y", "3");
+        assert_eq!(splice.finish(), "<a>\n  <d y=\"3\"/>\n</a>");
+    }
+
+    #[test]
+    fn untouched_source_comes_back_as_is() {
+        let source = "<?xml version='1.0'?>\r\n<!-- c -->\r\n<a  b = \"&#10;\" ><![CDATA[x]]></a>";
+        assert_eq!(Splice::new(source).finish(), source);
+    }
+}